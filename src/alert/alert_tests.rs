@@ -0,0 +1,221 @@
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::alert::{Alert, AlertDispatcher, AlertEngine, AlertRule, AlertStatus, Webhook, WebhookError, WebhookOptions};
+    use crate::monitor::{Monitor, MonitorOptions};
+    use crate::node::{Node, NodeId};
+    use crate::ping::mock::{closed_port, local_node, MockConfig, MockNode, PingedNode};
+    use crate::ping::{CancelHandle, PingOptions, Tip};
+    use crate::types::AdakaiError;
+
+    fn with_tip(addr: &str, slot: u64) -> Node {
+        PingedNode::new(addr).latency(10).tip(Tip { slot, block_no: slot / 20, hash: "aa".to_string() }).build()
+    }
+
+    fn statuses(alerts: &[Alert]) -> Vec<(String, AlertStatus)> {
+        alerts.iter().map(|a| (a.key.clone(), a.status)).collect()
+    }
+
+    /// serve starts a stand-in of a webhook receiver answering the requests with the given
+    /// statuses, then with 200. It returns the URL and the bodies received.
+    fn serve(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hooks/adakai", listener.local_addr().unwrap());
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+
+        let received = bodies.clone();
+        thread::spawn(move || {
+            let mut statuses = statuses.into_iter();
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                received.lock().unwrap().push(String::from_utf8(body).unwrap());
+
+                let status = statuses.next().unwrap_or(200);
+                let response = format!("HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        (url, bodies)
+    }
+
+    fn webhook(url: &str) -> Webhook<crate::http::ReqwestTransport> {
+        let options = WebhookOptions { retries: 2, backoff: Duration::from_millis(10), timeout: Duration::from_secs(5) };
+        Webhook::new_default(url, options).unwrap()
+    }
+
+    #[test]
+    fn alert_rules() {
+        let rules = AlertRule::from_json(
+            r#"[
+              {"rule": "offline", "checks": 3},
+              {"rule": "latency_above", "threshold_ms": 100},
+              {"rule": "tip_lag", "max_slots": 50},
+              {"rule": "upstream_peers", "producer": {"addr": "BP.example.com.", "port": 6000},
+               "peers": [{"addr": "10.0.0.1", "port": 3001}, {"addr": "10.0.0.2", "port": 3001}], "min_online": 2}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(AlertRule::Offline { checks: 3 }, rules[0]);
+        let mut engine = AlertEngine::new(rules);
+
+        // offline for two checks: the second peer was never pinged, but two peers online is already out of reach
+        let alerts = engine.check(&PingedNode::new("10.0.0.1").refused().build());
+        assert_eq!(vec![("upstream_peers/bp.example.com:6000".to_string(), AlertStatus::Firing)], statuses(&alerts));
+        assert_eq!(NodeId::new("bp.example.com", 6000), alerts[0].node);
+        assert_eq!("producer bp.example.com:6000 has 0 of 2 upstream peers online, 1 not pinged yet (min 2)", alerts[0].message);
+        assert!(engine.check(&PingedNode::new("10.0.0.1").refused().build()).is_empty());
+        let mut not_measured = PingedNode::new("10.0.0.1").refused().build();
+        not_measured.set_measured(false);
+        assert!(engine.check(&not_measured).is_empty());
+
+        // the second peer is up: the producer still has a single upstream peer online
+        assert!(engine.check(&PingedNode::new("10.0.0.2").latency(40).build()).is_empty());
+
+        // the third failure fires once, the next ones are deduplicated
+        let alerts = engine.check(&PingedNode::new("10.0.0.1").refused().build());
+        assert_eq!(vec![("offline/10.0.0.1:3001".to_string(), AlertStatus::Firing)], statuses(&alerts));
+        assert!(alerts[0].message.contains("offline for 3 consecutive checks"), "{}", alerts[0].message);
+        assert!(engine.check(&PingedNode::new("10.0.0.1").refused().build()).is_empty());
+        assert!(engine.is_firing(0, &NodeId::new("10.0.0.1", 3001)));
+
+        // back online but slow: recovery notices and a latency alert
+        let alerts = engine.check(&PingedNode::new("10.0.0.1").latency(150).build());
+        assert_eq!(
+            vec![
+                ("offline/10.0.0.1:3001".to_string(), AlertStatus::Resolved),
+                ("latency_above/10.0.0.1:3001".to_string(), AlertStatus::Firing),
+                ("upstream_peers/bp.example.com:6000".to_string(), AlertStatus::Resolved),
+            ],
+            statuses(&alerts)
+        );
+        assert_eq!("10.0.0.1:3001 is back online", alerts[0].message);
+        // an offline node does not resolve nor raise the latency alert
+        assert_eq!(1, engine.check(&PingedNode::new("10.0.0.1").refused().build()).len());
+        let alerts = engine.check(&PingedNode::new("10.0.0.1").latency(90).build());
+        assert_eq!(("latency_above/10.0.0.1:3001".to_string(), AlertStatus::Resolved), statuses(&alerts)[0]);
+
+        // tip lag against the best tip observed so far
+        assert!(engine.check(&with_tip("10.0.0.3", 1_000)).is_empty());
+        assert!(engine.check(&with_tip("10.0.0.4", 960)).is_empty());
+        let alerts = engine.check(&with_tip("10.0.0.4", 900));
+        assert_eq!(vec![("tip_lag/10.0.0.4:3001".to_string(), AlertStatus::Firing)], statuses(&alerts));
+        assert_eq!("10.0.0.4:3001 tip 100 slots behind the best tip (max 50)", alerts[0].message);
+        assert_eq!(AlertStatus::Resolved, engine.check(&with_tip("10.0.0.4", 1_010))[0].status);
+        assert!(engine.check(&with_tip("10.0.0.3", 1_020)).is_empty());
+    }
+
+    #[test]
+    fn upstream_peers_not_pinged() {
+        let peers = vec![NodeId::new("10.0.0.1", 3001), NodeId::new("10.0.0.2", 3001), NodeId::new("10.0.0.3", 3001)];
+        let rule = AlertRule::UpstreamPeers { producer: NodeId::new("10.0.0.9", 6000), peers, min_online: 2 };
+        let mut engine = AlertEngine::new(vec![rule]);
+
+        // one peer offline, one never pinged: two peers may still be online
+        assert!(engine.check(&PingedNode::new("10.0.0.1").refused().build()).is_empty());
+        assert!(engine.check(&PingedNode::new("10.0.0.2").latency(40).build()).is_empty());
+
+        // the last peer is offline too
+        let alerts = engine.check(&PingedNode::new("10.0.0.3").refused().build());
+        assert_eq!(vec![("upstream_peers/10.0.0.9:6000".to_string(), AlertStatus::Firing)], statuses(&alerts));
+        assert_eq!("producer 10.0.0.9:6000 has 1 of 3 upstream peers online (min 2)", alerts[0].message);
+        let alerts = engine.check(&PingedNode::new("10.0.0.1").latency(40).build());
+        assert_eq!(vec![("upstream_peers/10.0.0.9:6000".to_string(), AlertStatus::Resolved)], statuses(&alerts));
+    }
+
+    #[test]
+    fn webhook_retries() {
+        let alert = AlertEngine::new(vec![AlertRule::Offline { checks: 1 }]).check(&PingedNode::new("10.0.0.1").refused().build()).remove(0);
+
+        // two failures, then delivered
+        let (url, bodies) = serve(vec![503, 500]);
+        webhook(&url).send(&alert).unwrap();
+        let bodies = bodies.lock().unwrap().clone();
+        assert_eq!(3, bodies.len());
+        assert!(bodies.iter().all(|b| b == &bodies[0]));
+        let json: serde_json::Value = serde_json::from_str(&bodies[0]).unwrap();
+        assert_eq!("firing", json["status"]);
+        assert_eq!("offline", json["rule"]);
+        assert_eq!("offline/10.0.0.1:3001", json["key"]);
+        assert_eq!("10.0.0.1", json["node"]["addr"]);
+        assert_eq!(alert, serde_json::from_str(&bodies[0]).unwrap());
+
+        // retries exhausted
+        let (url, bodies) = serve(vec![503, 503, 429]);
+        assert!(matches!(webhook(&url).send(&alert), Err(WebhookError::RateLimited { .. })));
+        assert_eq!(3, bodies.lock().unwrap().len());
+
+        // a client error is not retried
+        let (url, bodies) = serve(vec![400]);
+        assert_eq!(Err(WebhookError::Http { status: 400 }), webhook(&url).send(&alert));
+        assert_eq!(1, bodies.lock().unwrap().len());
+
+        // nobody listening
+        let error = webhook(&format!("http://127.0.0.1:{}/", closed_port())).send(&alert).unwrap_err();
+        assert!(matches!(error, WebhookError::Transport(_)), "{:?}", error);
+        assert!(AdakaiError::from(error).to_string().starts_with("network error: webhook transport error: "));
+    }
+
+    #[test]
+    fn alerts_alongside_monitor() {
+        let port = closed_port();
+        let node = local_node(port);
+
+        let mut monitor = Monitor::new(MonitorOptions {
+            interval: Duration::from_millis(100),
+            retry_interval: Duration::from_millis(50),
+            ping: PingOptions { retries: 0, connect_timeout: Duration::from_millis(500), ..Default::default() },
+            ..Default::default()
+        });
+        monitor.add(node);
+
+        // the first delivery fails once
+        let (url, bodies) = serve(vec![502]);
+        let dispatcher = AlertDispatcher::start(webhook(&url));
+        monitor.add_handler(Box::new(dispatcher.handler(vec![AlertRule::Offline { checks: 2 }])));
+
+        // the node comes up after a while
+        let starter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            MockNode::start_on(&format!("127.0.0.1:{}", port), MockConfig::default())
+        });
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let shutdown = CancelHandle::new();
+            let stop = shutdown.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(800)).await;
+                stop.cancel();
+            });
+            monitor.run(shutdown).await;
+        });
+        let _mock = starter.join().unwrap();
+        assert!(dispatcher.stop().is_empty());
+
+        let alerts: Vec<Alert> = bodies.lock().unwrap().iter().map(|b| serde_json::from_str(b).unwrap()).collect();
+        assert_eq!(3, alerts.len(), "{:?}", alerts);
+        assert_eq!(alerts[0], alerts[1]);
+        assert_eq!(AlertStatus::Firing, alerts[1].status);
+        assert_eq!(AlertStatus::Resolved, alerts[2].status);
+        assert_eq!(alerts[1].key, alerts[2].key);
+    }
+}
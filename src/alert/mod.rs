@@ -0,0 +1,473 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::history::now;
+use crate::http::{HttpError, HttpTransport, ReqwestTransport};
+use crate::monitor::{MonitorEvent, MonitorHandler, NodeState};
+use crate::node::{Node, NodeId};
+use crate::ping::best_tip;
+use crate::types::{AdakaiError, AdakaiResult};
+
+mod alert_tests;
+
+/// AlertRule is a condition raising an alert. Rules are declared in JSON, e.g.
+/// `[{"rule": "offline", "checks": 3}, {"rule": "latency_above", "threshold_ms": 500}]`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum AlertRule {
+    /// Offline: the node did not answer `checks` consecutive pings
+    Offline {
+        /// checks is the number of consecutive failed pings
+        checks: u32,
+    },
+
+    /// LatencyAbove: the total latency of the node is above the threshold
+    LatencyAbove {
+        /// threshold_ms is the threshold in milliseconds
+        threshold_ms: u64,
+    },
+
+    /// TipLag: the tip of the node is more than `max_slots` behind the best tip observed (the
+    /// nodes must be pinged with PingOptions::query_tip)
+    TipLag {
+        /// max_slots is the number of slots the node may be behind
+        max_slots: u64,
+    },
+
+    /// UpstreamPeers: fewer than `min_online` of the upstream peers of a producer are online. The peers
+    /// not pinged yet are not counted as offline.
+    UpstreamPeers {
+        /// producer is the block producer the alert is raised for
+        producer: NodeId,
+
+        /// peers are the relays the producer connects to, they must be monitored
+        peers: Vec<NodeId>,
+
+        /// min_online is the minimum number of peers online
+        min_online: usize,
+    },
+}
+
+impl AlertRule {
+    /// name: returns the name of the rule as written in JSON, e.g. `offline`
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlertRule::Offline { .. } => "offline",
+            AlertRule::LatencyAbove { .. } => "latency_above",
+            AlertRule::TipLag { .. } => "tip_lag",
+            AlertRule::UpstreamPeers { .. } => "upstream_peers",
+        }
+    }
+
+    /// from_json: reads a list of rules
+    pub fn from_json(json: &str) -> AdakaiResult<Vec<AlertRule>> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// canonical: returns the rule with the node identities in their canonical form
+    fn canonical(self) -> AlertRule {
+        match self {
+            AlertRule::UpstreamPeers { producer, peers, min_online } => AlertRule::UpstreamPeers {
+                producer: NodeId::new(&producer.addr, producer.port),
+                peers: peers.iter().map(|p| NodeId::new(&p.addr, p.port)).collect(),
+                min_online,
+            },
+            rule => rule,
+        }
+    }
+}
+
+/// AlertStatus tells whether an alert starts or ends
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertStatus {
+    /// Firing: the condition of the rule became true
+    Firing,
+
+    /// Resolved: the condition of the rule is no longer true (recovery notice)
+    Resolved,
+}
+
+/// Alert is the payload of the webhooks, sent once when a rule starts firing for a node and once
+/// when it is resolved
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alert {
+    /// key identifies the incident, `<rule>/<addr:port>`: the firing alert and its recovery
+    /// notice have the same key
+    pub key: String,
+
+    /// status is firing or resolved
+    pub status: AlertStatus,
+
+    /// rule is the name of the rule (see AlertRule::name)
+    pub rule: String,
+
+    /// node is the node the alert is about, the producer for the upstream_peers rule
+    pub node: NodeId,
+
+    /// message describes the alert
+    pub message: String,
+
+    /// timestamp is the time of the check that raised the alert, in seconds since the epoch
+    pub timestamp: u64,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.status {
+            AlertStatus::Firing => "FIRING",
+            AlertStatus::Resolved => "RESOLVED",
+        };
+        write!(f, "[{}] {}", status, self.message)
+    }
+}
+
+/// AlertEngine evaluates the rules on every ping of a node. An alert is raised once when the
+/// condition of a rule becomes true for a node and once when it is back to false, never while it
+/// stays the same.
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    nodes: BTreeMap<NodeId, Node>,
+    failures: HashMap<NodeId, u32>,
+    firing: HashSet<(usize, NodeId)>,
+}
+
+impl AlertEngine {
+    /// new: returns an engine evaluating the rules, nothing firing
+    pub fn new(rules: Vec<AlertRule>) -> AlertEngine {
+        AlertEngine {
+            rules: rules.into_iter().map(AlertRule::canonical).collect(),
+            nodes: BTreeMap::new(),
+            failures: HashMap::new(),
+            firing: HashSet::new(),
+        }
+    }
+
+    /// rules: returns the rules of the engine
+    pub fn rules(&self) -> &[AlertRule] {
+        &self.rules
+    }
+
+    /// is_firing: returns true if the rule at the position in rules() fires for the node
+    pub fn is_firing(&self, rule: usize, id: &NodeId) -> bool {
+        self.firing.contains(&(rule, id.clone()))
+    }
+
+    /// check: records the ping of the node and returns the alerts it raises. Nodes that were not
+    /// measured are ignored.
+    pub fn check(&mut self, node: &Node) -> Vec<Alert> {
+        if !node.measured() {
+            return Vec::new();
+        }
+        let id = node.id();
+        let failures = self.failures.entry(id.clone()).or_insert(0);
+        *failures = if node.online() { 0 } else { *failures + 1 };
+        let failures = *failures;
+        self.nodes.insert(id.clone(), node.clone());
+
+        let timestamp = now();
+        let mut alerts = Vec::new();
+        for position in 0..self.rules.len() {
+            let rule = &self.rules[position];
+            let (subject, holds, message) = match self.evaluate(rule, node, failures) {
+                Some(evaluated) => evaluated,
+                None => continue,
+            };
+
+            let key = (position, subject.clone());
+            let status = match (holds, self.firing.contains(&key)) {
+                (true, false) => AlertStatus::Firing,
+                (false, true) => AlertStatus::Resolved,
+                _ => continue,
+            };
+            if holds {
+                self.firing.insert(key);
+            } else {
+                self.firing.remove(&key);
+            }
+
+            let alert = Alert {
+                key: format!("{}/{}", rule.name(), subject),
+                status,
+                rule: rule.name().to_string(),
+                node: subject,
+                message,
+                timestamp,
+            };
+            info!("alert {}", alert);
+            alerts.push(alert);
+        }
+        alerts
+    }
+
+    /// evaluate: returns the subject of the rule, whether its condition holds and a message
+    /// describing the state. None when the ping does not tell (e.g. latency of an offline node).
+    fn evaluate(&self, rule: &AlertRule, node: &Node, failures: u32) -> Option<(NodeId, bool, String)> {
+        let id = node.id();
+        match rule {
+            AlertRule::Offline { checks } => {
+                if node.online() {
+                    return Some((id.clone(), false, format!("{} is back online", id)));
+                }
+                let error = node.online_error().map(|e| e.to_string()).unwrap_or_default();
+                let message = format!("{} offline for {} consecutive checks: {}", id, failures, error);
+                Some((id, failures >= (*checks).max(1), message))
+            }
+            AlertRule::LatencyAbove { threshold_ms } => {
+                if !node.online() {
+                    return None;
+                }
+                let latency = node.total_latency();
+                let above = latency > Duration::from_millis(*threshold_ms);
+                let position = if above { "above" } else { "back below" };
+                let message = format!("{} latency {} ms {} {} ms", id, latency.as_millis(), position, threshold_ms);
+                Some((id, above, message))
+            }
+            AlertRule::TipLag { max_slots } => {
+                let slot = node.tip()?.slot;
+                let nodes: Vec<Node> = self.nodes.values().cloned().collect();
+                let lag = best_tip(&nodes).map(|t| t.slot).unwrap_or_default().saturating_sub(slot);
+                let message = format!("{} tip {} slots behind the best tip (max {})", id, lag, max_slots);
+                Some((id, lag > *max_slots, message))
+            }
+            AlertRule::UpstreamPeers { producer, peers, min_online } => {
+                if !peers.contains(&id) {
+                    return None;
+                }
+                // only the measured peers are counted, the ones not pinged yet do not count as offline
+                let measured: Vec<&Node> = peers.iter().filter_map(|peer| self.nodes.get(peer)).collect();
+                let online = measured.iter().filter(|node| node.online()).count();
+                let offline = measured.len() - online;
+                let unknown = peers.len() - measured.len();
+                let unknown = if unknown > 0 { format!(", {} not pinged yet", unknown) } else { String::new() };
+                let message = format!(
+                    "producer {} has {} of {} upstream peers online{} (min {})",
+                    producer,
+                    online,
+                    peers.len(),
+                    unknown,
+                    min_online
+                );
+                Some((producer.clone(), peers.len() - offline < *min_online, message))
+            }
+        }
+    }
+}
+
+/// WebhookOptions holds the retry policy of the webhooks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WebhookOptions {
+    /// retries is the number of times a failed delivery is retried (default 3)
+    pub retries: u32,
+
+    /// backoff is the delay before the first retry, doubled on every retry (default 1 s). A longer
+    /// Retry-After of the receiver is honoured.
+    pub backoff: Duration,
+
+    /// timeout is the timeout of a request with the default transport (default 10 s)
+    pub timeout: Duration,
+}
+
+impl Default for WebhookOptions {
+    fn default() -> Self {
+        WebhookOptions {
+            retries: 3,
+            backoff: Duration::from_secs(1),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// WebhookError is the reason an alert could not be delivered
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WebhookError {
+    /// Transport: the request could not be performed (DNS, connection, TLS, timeout...)
+    Transport(String),
+
+    /// Http: the receiver answered with an unexpected HTTP status
+    Http {
+        /// status is the HTTP status code
+        status: u16,
+    },
+
+    /// RateLimited: the receiver refused the alert because of too many requests
+    RateLimited {
+        /// retry_after is the delay requested by the receiver, if given
+        retry_after: Option<Duration>,
+    },
+
+    /// Encode: the alert could not be encoded as JSON
+    Encode(String),
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebhookError::Transport(e) => write!(f, "webhook transport error: {}", e),
+            WebhookError::Http { status } => write!(f, "webhook HTTP status: {}", status),
+            WebhookError::RateLimited { retry_after: Some(d) } => {
+                write!(f, "webhook rate limited (retry after {}s)", d.as_secs())
+            }
+            WebhookError::RateLimited { retry_after: None } => write!(f, "webhook rate limited"),
+            WebhookError::Encode(e) => write!(f, "alert encode error: {}", e),
+        }
+    }
+}
+
+impl Error for WebhookError {}
+
+impl From<HttpError> for WebhookError {
+    fn from(e: HttpError) -> Self {
+        WebhookError::Transport(e.0)
+    }
+}
+
+impl From<WebhookError> for AdakaiError {
+    fn from(e: WebhookError) -> Self {
+        AdakaiError::Network(Box::new(e))
+    }
+}
+
+/// Webhook posts the alerts as JSON (see Alert) to an URL
+pub struct Webhook<T: HttpTransport> {
+    transport: T,
+    url: String,
+    options: WebhookOptions,
+}
+
+impl Webhook<ReqwestTransport> {
+    /// new_default: returns a webhook posting to the url with the reqwest transport
+    pub fn new_default(url: &str, options: WebhookOptions) -> Result<Webhook<ReqwestTransport>, WebhookError> {
+        let transport = ReqwestTransport::new(options.timeout)?;
+        Ok(Webhook::new(transport, url, options))
+    }
+}
+
+impl<T: HttpTransport> Webhook<T> {
+    /// new: returns a webhook posting to the url with the transport
+    pub fn new(transport: T, url: &str, options: WebhookOptions) -> Webhook<T> {
+        Webhook {
+            transport,
+            url: url.to_string(),
+            options,
+        }
+    }
+
+    /// url: returns the URL the alerts are posted to
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// send: posts the alert, retrying on transport errors, 5xx and 429 answers. Other answers
+    /// than 2xx fail at once.
+    pub fn send(&self, alert: &Alert) -> Result<(), WebhookError> {
+        let body = serde_json::to_string(alert).map_err(|e| WebhookError::Encode(e.to_string()))?;
+        let mut backoff = self.options.backoff;
+        let mut attempt = 0;
+        loop {
+            let (error, retry_after) = match self.transport.post_json(&self.url, &body) {
+                Ok(response) if (200..300).contains(&response.status) => return Ok(()),
+                Ok(response) if response.status == 429 => (
+                    WebhookError::RateLimited { retry_after: response.retry_after },
+                    response.retry_after,
+                ),
+                Ok(response) if response.status >= 500 => (WebhookError::Http { status: response.status }, None),
+                Ok(response) => return Err(WebhookError::Http { status: response.status }),
+                Err(e) => (e.into(), None),
+            };
+            if attempt >= self.options.retries {
+                return Err(error);
+            }
+            attempt += 1;
+            debug!("webhook {} failed ({}), retry {} of {}", self.url, error, attempt, self.options.retries);
+            thread::sleep(retry_after.map_or(backoff, |r| r.max(backoff)));
+            backoff *= 2;
+        }
+    }
+}
+
+enum Queued {
+    Alert(Alert),
+    Stop,
+}
+
+/// AlertDispatcher delivers the alerts through a webhook on a thread of its own, so that the
+/// monitor loop never waits for the receiver.
+pub struct AlertDispatcher {
+    queue: mpsc::Sender<Queued>,
+    worker: thread::JoinHandle<Vec<Alert>>,
+}
+
+impl AlertDispatcher {
+    /// start: starts the delivery thread
+    pub fn start<T: HttpTransport + Send + 'static>(webhook: Webhook<T>) -> AlertDispatcher {
+        let (queue, queued) = mpsc::channel();
+        let worker = thread::spawn(move || {
+            let mut undelivered = Vec::new();
+            for queued in queued {
+                match queued {
+                    Queued::Alert(alert) => {
+                        if let Err(e) = webhook.send(&alert) {
+                            warn!("alert {} not delivered to {}: {}", alert.key, webhook.url(), e);
+                            undelivered.push(alert);
+                        }
+                    }
+                    Queued::Stop => break,
+                }
+            }
+            undelivered
+        });
+        AlertDispatcher { queue, worker }
+    }
+
+    /// send: queues the alert for delivery
+    pub fn send(&self, alert: Alert) {
+        let _ = self.queue.send(Queued::Alert(alert));
+    }
+
+    /// handler: returns a monitor handler evaluating the rules on every check and queuing the
+    /// alerts they raise on this dispatcher
+    pub fn handler(&self, rules: Vec<AlertRule>) -> AlertHandler {
+        AlertHandler {
+            engine: AlertEngine::new(rules),
+            queue: self.queue.clone(),
+        }
+    }
+
+    /// stop: delivers the alerts queued so far, stops the thread and returns the alerts that
+    /// could not be delivered
+    pub fn stop(self) -> Vec<Alert> {
+        let _ = self.queue.send(Queued::Stop);
+        self.worker.join().unwrap_or_default()
+    }
+}
+
+/// AlertHandler is the MonitorHandler feeding an AlertEngine with the checks of the monitor, see
+/// AlertDispatcher::handler
+pub struct AlertHandler {
+    engine: AlertEngine,
+    queue: mpsc::Sender<Queued>,
+}
+
+impl AlertHandler {
+    /// engine: returns the engine evaluating the rules
+    pub fn engine(&self) -> &AlertEngine {
+        &self.engine
+    }
+}
+
+impl MonitorHandler for AlertHandler {
+    fn on_event(&mut self, _event: &MonitorEvent) {}
+
+    fn on_check(&mut self, node: &Node, _state: NodeState) {
+        for alert in self.engine.check(node) {
+            let _ = self.queue.send(Queued::Alert(alert));
+        }
+    }
+}
@@ -0,0 +1,146 @@
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use clap::Parser;
+
+    use adakairust::node::NodeId;
+    use adakairust::report::{ReportFormat, SortKey};
+    use adakairust::types::{AdakaiError, NetworkType};
+
+    use crate::{run, Cli, Command, EXIT_HEALTHY, EXIT_UNHEALTHY};
+
+    fn closed_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    fn topology_file(name: &str, producers: &[(&str, u16, u16)]) -> PathBuf {
+        let producers: Vec<String> = producers
+            .iter()
+            .map(|(addr, port, valency)| format!(r#"{{"addr": "{}", "port": {}, "valency": {}}}"#, addr, port, valency))
+            .collect();
+        let path = env::temp_dir().join(format!("adakai-cli-{}-{}.json", std::process::id(), name));
+        fs::write(&path, format!(r#"{{"Producers": [{}]}}"#, producers.join(", "))).unwrap();
+        path
+    }
+
+    /// adakai runs the command line and returns the exit code and the output
+    fn adakai(args: &[&str]) -> (u8, String) {
+        let cli = Cli::try_parse_from([&["adakai", "--retries", "0", "--timeout", "1"], args].concat()).unwrap();
+        let mut out = Vec::new();
+        let code = run(&cli, &mut out).unwrap();
+        (code, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn parse_arguments() {
+        let cli = Cli::try_parse_from(["adakai", "--network", "magic=42", "--timeout", "1.5", "ping", "Relay.Example.com:3001"]).unwrap();
+        assert_eq!(NetworkType::Custom(42), cli.network);
+        assert_eq!(Duration::from_millis(1500), cli.timeout);
+        assert_eq!(ReportFormat::Table, cli.format);
+        assert!(matches!(cli.command, Command::Ping { node } if node == NodeId::new("relay.example.com", 3001)));
+
+        // the global flags may follow the subcommand
+        let cli = Cli::try_parse_from(["adakai", "diff", "a.json", "b.json", "--format", "ndjson", "--network", "preview", "--sort", "latency"]).unwrap();
+        assert_eq!(ReportFormat::Ndjson, cli.format);
+        assert_eq!(SortKey::Latency, cli.sort);
+        assert_eq!(NetworkType::Preview, cli.network);
+
+        for invalid in [
+            vec!["adakai", "ping", "relay.example.com"],
+            vec!["adakai", "--network", "nonet", "ping", "relay:3001"],
+            vec!["adakai", "--timeout", "0", "ping", "relay:3001"],
+            vec!["adakai", "--format", "xml", "ping", "relay:3001"],
+            vec!["adakai", "--sort", "speed", "ping", "relay:3001"],
+            vec!["adakai", "select"],
+        ] {
+            let error = Cli::try_parse_from(&invalid).unwrap_err();
+            assert_eq!(2, error.exit_code(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn ping_exit_codes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = format!("127.0.0.1:{}", listener.local_addr().unwrap().port());
+        let (code, out) = adakai(&["--tcp", "ping", &open]);
+        assert_eq!(EXIT_HEALTHY, code, "{}", out);
+        let row = out.lines().nth(1).unwrap();
+        assert!(row.starts_with(&open) && row.contains(" yes "), "{}", out);
+
+        let closed = format!("127.0.0.1:{}", closed_port());
+        let (code, out) = adakai(&["--format", "json", "ping", &closed]);
+        assert_eq!(EXIT_UNHEALTHY, code);
+        let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!("127.0.0.1", json[0]["addr"]);
+        assert_eq!(false, json[0]["online"]);
+        assert!(json[0]["error"].as_str().unwrap().contains("connect"), "{}", out);
+    }
+
+    #[test]
+    fn ping_file_and_select() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap().port();
+        let path = topology_file("ping", &[("127.0.0.1", open, 2), ("127.0.0.1", closed_port(), 1)]);
+        let path = path.to_str().unwrap();
+
+        let (code, out) = adakai(&["--tcp", "ping-file", path]);
+        assert_eq!(EXIT_UNHEALTHY, code);
+        assert_eq!(3, out.lines().count(), "{}", out);
+        let (_, out) = adakai(&["--tcp", "--format", "csv", "--online-only", "ping-file", path]);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(2, lines.len(), "{}", out);
+        assert!(lines[1].starts_with(&format!("127.0.0.1,{},,,true,", open)), "{}", out);
+        assert_eq!(EXIT_HEALTHY, adakai(&["--tcp", "ping-file", path, "--min-online", "1"]).0);
+
+        let (code, out) = adakai(&["--tcp", "select", path, "--count", "1"]);
+        assert_eq!(EXIT_HEALTHY, code);
+        let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(1, json["Producers"].as_array().unwrap().len());
+        assert_eq!(open, json["Producers"][0]["port"]);
+
+        let output = env::temp_dir().join(format!("adakai-cli-{}-selected.json", std::process::id()));
+        let (code, out) = adakai(&["--tcp", "select", path, "--count", "2", "--p2p", "-o", output.to_str().unwrap()]);
+        assert_eq!(EXIT_UNHEALTHY, code);
+        assert!(out.is_empty());
+        assert!(fs::read_to_string(&output).unwrap().contains("localRoots"));
+
+        let cli = Cli::try_parse_from(["adakai", "ping-file", "/nonexistent/topology.json"]).unwrap();
+        let error = run(&cli, &mut Vec::new()).unwrap_err();
+        assert!(error.to_string().starts_with("/nonexistent/topology.json: io error: "), "{}", error);
+        assert!(matches!(&error, AdakaiError::File { error, .. } if matches!(**error, AdakaiError::Io(_))), "{:?}", error);
+
+        let invalid = topology_file("invalid", &[("127.0.0.1", open, 0)]);
+        let cli = Cli::try_parse_from(["adakai", "ping-file", invalid.to_str().unwrap()]).unwrap();
+        let error = run(&cli, &mut Vec::new()).unwrap_err();
+        assert!(matches!(&error, AdakaiError::File { error, .. } if matches!(**error, AdakaiError::Validation(_))), "{:?}", error);
+        assert!(error.to_string().ends_with("invalid valency 0, expected 1 to 100"), "{}", error);
+
+        fs::remove_file(path).unwrap();
+        fs::remove_file(invalid).unwrap();
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn diff_exit_codes() {
+        let old = topology_file("old", &[("10.0.0.1", 3001, 1), ("10.0.0.2", 3001, 1)]);
+        let new = topology_file("new", &[("10.0.0.2", 3001, 2), ("10.0.0.3", 3001, 1)]);
+        let (old, new) = (old.to_str().unwrap(), new.to_str().unwrap());
+
+        assert_eq!((EXIT_HEALTHY, String::new()), adakai(&["diff", old, old]));
+        let (code, out) = adakai(&["diff", old, new]);
+        assert_eq!(EXIT_UNHEALTHY, code);
+        assert_eq!("+ 10.0.0.3:3001\n- 10.0.0.1:3001\n~ 10.0.0.2:3001 valency 1 -> 2\n", out);
+
+        let (_, out) = adakai(&["--format", "json", "diff", old, new]);
+        let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!("10.0.0.1:3001", json["removed"][0]);
+
+        fs::remove_file(old).unwrap();
+        fs::remove_file(new).unwrap();
+    }
+}
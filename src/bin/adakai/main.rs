@@ -0,0 +1,285 @@
+//! adakai pings cardano nodes and manages topology files from the command line. It is built with
+//! the `cli` feature: `cargo install adakairust --features cli`.
+//!
+//! Exit codes:
+//! * 0: healthy (the nodes are online, the topologies are the same, the peers were selected)
+//! * 1: unhealthy (offline nodes, different topologies, fewer peers selected than asked)
+//! * 2: invalid arguments
+//! * 3: failure (unreadable or invalid topology file, output that cannot be written)
+#[macro_use] extern crate log;
+
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+
+use adakairust::node::{Node, NodeId};
+use adakairust::ping::{ping_vec, PingMode, PingOptions, DEFAULT_CONCURRENCY};
+use adakairust::report::{self, ReportFormat, ReportOptions, SortKey};
+use adakairust::select::{SelectOptions, Selector};
+use adakairust::topology::{diff, AnyTopology};
+use adakairust::types::{AdakaiError, AdakaiResult, NetworkType};
+
+mod adakai_tests;
+
+
+/// EXIT_HEALTHY is returned when the nodes are healthy
+const EXIT_HEALTHY: u8 = 0;
+
+/// EXIT_UNHEALTHY is returned when some nodes are offline or the topologies differ
+const EXIT_UNHEALTHY: u8 = 1;
+
+/// EXIT_FAILURE is returned when the command could not run
+const EXIT_FAILURE: u8 = 3;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "adakai",
+    version,
+    about = "Pings cardano nodes and manages topology files",
+    after_help = "Exit codes: 0 healthy, 1 unhealthy, 2 invalid arguments, 3 failure"
+)]
+struct Cli {
+    /// Cardano network: mainnet, preprod, preview, sanchonet, testnet or magic=N
+    #[arg(long, global = true, default_value = "mainnet")]
+    network: NetworkType,
+
+    /// Maximum number of nodes pinged at the same time
+    #[arg(long, global = true, default_value_t = DEFAULT_CONCURRENCY)]
+    concurrency: usize,
+
+    /// Timeout of the connection and of the handshake, in seconds
+    #[arg(long, global = true, default_value = "5", value_parser = parse_timeout)]
+    timeout: Duration,
+
+    /// Number of new attempts after a failed connection
+    #[arg(long, global = true, default_value_t = 2)]
+    retries: u32,
+
+    /// Only open the TCP connection instead of running the handshake
+    #[arg(long, global = true)]
+    tcp: bool,
+
+    /// Output format of ping and ping-file: table, csv, json or ndjson (diff prints text for
+    /// table and csv)
+    #[arg(long, global = true, default_value = "table")]
+    format: ReportFormat,
+
+    /// Order of the pinged nodes: none, latency, continent or address
+    #[arg(long, global = true, default_value = "none")]
+    sort: SortKey,
+
+    /// Leaves the offline nodes out of the output
+    #[arg(long, global = true)]
+    online_only: bool,
+
+    /// Only outputs the nodes of the continent
+    #[arg(long, global = true)]
+    continent: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Pings a node, exits with 1 if it is offline
+    Ping {
+        /// Address of the node, host:port or [ipv6]:port
+        node: NodeId,
+    },
+
+    /// Pings every node of a topology file (legacy or P2P), exits with 1 if too few are online
+    PingFile {
+        /// Topology file
+        topology: PathBuf,
+
+        /// Number of nodes that must be online (default all)
+        #[arg(long)]
+        min_online: Option<usize>,
+    },
+
+    /// Pings the nodes of a topology file and writes a topology with the best peers, exits with
+    /// 1 if fewer peers than asked could be selected
+    Select {
+        /// Topology file holding the candidates
+        topology: PathBuf,
+
+        /// Number of peers to select
+        #[arg(long, default_value_t = 20)]
+        count: usize,
+
+        /// Maximum number of peers selected from the same continent
+        #[arg(long)]
+        max_per_continent: Option<usize>,
+
+        /// Maximum number of peers selected on the same subnet
+        #[arg(long)]
+        max_per_subnet: Option<usize>,
+
+        /// Writes the topology in the P2P format instead of the legacy one
+        #[arg(long)]
+        p2p: bool,
+
+        /// Topology file written (default standard output)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Compares two topology files, exits with 1 if their nodes differ
+    Diff {
+        /// Current topology file
+        old: PathBuf,
+
+        /// Topology file replacing it
+        new: PathBuf,
+    },
+}
+
+/// DiffReport is the JSON output of diff
+#[derive(Serialize)]
+struct DiffReport {
+    added: Vec<String>,
+    removed: Vec<String>,
+    changed: Vec<String>,
+}
+
+fn parse_timeout(value: &str) -> Result<Duration, String> {
+    let secs: f64 = value.parse().map_err(|_| format!("invalid timeout '{}', expected seconds", value))?;
+    if !secs.is_finite() || secs <= 0.0 {
+        return Err(format!("invalid timeout '{}', expected a positive number of seconds", value));
+    }
+    Ok(Duration::from_secs_f64(secs))
+}
+
+fn ping_options(cli: &Cli) -> PingOptions {
+    PingOptions {
+        connect_timeout: cli.timeout,
+        handshake_timeout: cli.timeout,
+        retries: cli.retries,
+        mode: if cli.tcp { PingMode::TcpConnect } else { PingMode::Handshake },
+        concurrency: cli.concurrency.max(1),
+        ..Default::default()
+    }
+}
+
+fn read_nodes(cli: &Cli, path: &PathBuf) -> AdakaiResult<Vec<Node>> {
+    AnyTopology::new_from_file(cli.network, path)
+        .and_then(|t| t.validate())
+        .map(|t| t.nodes())
+        .map_err(|e| e.in_file(path))
+}
+
+/// run: runs the command, writing its output to out, and returns the exit code
+fn run(cli: &Cli, out: &mut dyn Write) -> AdakaiResult<u8> {
+    match &cli.command {
+        Command::Ping { node } => {
+            let mut target = Node::default();
+            target.set_addr(node.addr.clone());
+            target.set_port(node.port);
+            target.set_network_type(cli.network);
+            let nodes = ping_vec(vec![target], ping_options(cli));
+            write_nodes(cli, &nodes, out)?;
+            Ok(if nodes[0].online() { EXIT_HEALTHY } else { EXIT_UNHEALTHY })
+        }
+        Command::PingFile { topology, min_online } => {
+            let nodes = ping_vec(read_nodes(cli, topology)?, ping_options(cli));
+            write_nodes(cli, &nodes, out)?;
+            let online = nodes.iter().filter(|n| n.online()).count();
+            Ok(if online >= min_online.unwrap_or(nodes.len()) { EXIT_HEALTHY } else { EXIT_UNHEALTHY })
+        }
+        Command::Select {
+            topology,
+            count,
+            max_per_continent,
+            max_per_subnet,
+            p2p,
+            output,
+        } => {
+            let nodes = ping_vec(read_nodes(cli, topology)?, ping_options(cli));
+            let options = SelectOptions {
+                count: *count,
+                max_per_continent: *max_per_continent,
+                max_per_subnet: *max_per_subnet,
+                ..Default::default()
+            };
+            let selection = Selector::new(options).select(&nodes);
+            eprintln!("selected {} of {} peers", selection.selected.len(), nodes.len());
+            for rejection in selection.rejected.iter() {
+                debug!("{} not selected: {}", rejection.node.id(), rejection.reason);
+            }
+
+            let topology = selection.topology();
+            let json = if *p2p { topology.to_p2p().to_json()? } else { topology.to_json()? };
+            match output {
+                Some(path) => std::fs::write(path, json + "\n").map_err(|e| AdakaiError::from(e).in_file(path))?,
+                None => writeln!(out, "{}", json)?,
+            }
+            Ok(if selection.selected.len() < *count { EXIT_UNHEALTHY } else { EXIT_HEALTHY })
+        }
+        Command::Diff { old, new } => {
+            let changes = diff(&read_nodes(cli, old)?, &read_nodes(cli, new)?);
+            let describe = |old: &Node, new: &Node| {
+                let mut fields = Vec::new();
+                if old.valency() != new.valency() {
+                    fields.push(format!("valency {} -> {}", old.valency(), new.valency()));
+                }
+                if old.continent() != new.continent() {
+                    fields.push(format!("continent '{}' -> '{}'", old.continent(), new.continent()));
+                }
+                if old.state() != new.state() {
+                    fields.push(format!("state '{}' -> '{}'", old.state(), new.state()));
+                }
+                format!("{} {}", new.id(), fields.join(", "))
+            };
+            let report = DiffReport {
+                added: changes.added.iter().map(|n| n.id().to_string()).collect(),
+                removed: changes.removed.iter().map(|n| n.id().to_string()).collect(),
+                changed: changes.changed.iter().map(|(o, n)| describe(o, n)).collect(),
+            };
+            match cli.format {
+                ReportFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&report)?)?,
+                ReportFormat::Ndjson => writeln!(out, "{}", serde_json::to_string(&report)?)?,
+                ReportFormat::Table | ReportFormat::Csv => {
+                    for (sign, lines) in [("+", &report.added), ("-", &report.removed), ("~", &report.changed)] {
+                        for line in lines {
+                            writeln!(out, "{} {}", sign, line)?;
+                        }
+                    }
+                }
+            }
+            Ok(if changes.is_empty() { EXIT_HEALTHY } else { EXIT_UNHEALTHY })
+        }
+    }
+}
+
+fn write_nodes(cli: &Cli, nodes: &[Node], out: &mut dyn Write) -> AdakaiResult<()> {
+    let options = ReportOptions {
+        format: cli.format,
+        sort: cli.sort,
+        online_only: cli.online_only,
+        continent: cli.continent.clone(),
+        ..Default::default()
+    };
+    report::write(nodes, &options, out)?;
+    if nodes.len() > 1 {
+        eprintln!("{} of {} nodes online", nodes.iter().filter(|n| n.online()).count(), nodes.len());
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    pretty_env_logger::init();
+    let cli = Cli::parse();
+    let stdout = io::stdout();
+    match run(&cli, &mut stdout.lock()) {
+        Ok(code) => ExitCode::from(code),
+        Err(e) => {
+            eprintln!("adakai: {}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
@@ -0,0 +1,137 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs;
+    use std::time::Duration;
+
+    use crate::history::{now, HistoryStore, PingRecord};
+    use crate::node::NodeId;
+    use crate::ping::mock::PingedNode;
+    use crate::ping::PingError;
+    use crate::select::{LatencyMetric, RejectReason, SelectOptions, Selector};
+
+    const HOUR: u64 = 3600;
+
+    fn record(addr: &str, timestamp: u64, latency_ms: Option<u64>) -> PingRecord {
+        PingRecord {
+            addr: addr.to_string(),
+            port: 3001,
+            timestamp,
+            online: latency_ms.is_some(),
+            con_latency: latency_ms.map(|l| Duration::from_millis(l / 2)),
+            total_latency: latency_ms.map(Duration::from_millis),
+            error: match latency_ms {
+                Some(_) => None,
+                None => Some(PingError::Connect("connection refused".to_string())),
+            },
+        }
+    }
+
+    #[test]
+    fn history_summary() {
+        let now = now();
+        let mut store = HistoryStore::in_memory();
+        // old records, out of the window
+        store.record(record("10.0.0.1", now - 48 * HOUR, Some(500))).unwrap();
+        store.record(record("10.0.0.1", now - 47 * HOUR, Some(500))).unwrap();
+        for (i, latency) in [Some(10), Some(20), None, Some(30), Some(40), Some(50), None, None].iter().enumerate() {
+            store.record(record("10.0.0.1", now - HOUR + i as u64, *latency)).unwrap();
+        }
+
+        let id = NodeId::new("10.0.0.1", 3001);
+        let summary = store.summary(&id, Duration::from_secs(24 * HOUR)).unwrap();
+        assert_eq!(8, summary.checks);
+        assert_eq!(5, summary.online);
+        assert!((summary.uptime - 62.5).abs() < 1e-9);
+        assert_eq!(Duration::from_millis(30), summary.latency.median);
+        assert_eq!(Duration::from_millis(50), summary.latency.p95);
+        assert_eq!(3, summary.latency.failures);
+        assert_eq!(Some(now - HOUR + 5), summary.last_seen_online);
+        assert_eq!(Some(now - HOUR + 7), summary.last_checked);
+
+        // a window without any ping still knows when the node was last online
+        let summary = store.summary_since(&id, now + 1).unwrap();
+        assert_eq!(0, summary.checks);
+        assert_eq!(Some(now - HOUR + 5), summary.last_seen_online);
+
+        assert_eq!(None, store.summary(&NodeId::new("10.0.0.2", 3001), Duration::from_secs(HOUR)));
+    }
+
+    #[test]
+    fn history_file_round_trip() {
+        let path = env::temp_dir().join(format!("adakairust-history-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let offline = PingedNode::new("relay.example.com").offline(PingError::Timeout("handshake".to_string())).build();
+        let mut not_measured = PingedNode::new("10.0.0.3").latency(20).build();
+        not_measured.set_measured(false);
+
+        let mut store = HistoryStore::open(&path).unwrap();
+        assert_eq!(2, store.record_nodes(&[PingedNode::new("10.0.0.1").latency(40).build(), offline, not_measured]).unwrap());
+        store.record(record("10.0.0.1", now() - 100 * HOUR, Some(80))).unwrap();
+        // a line cut by a crash
+        fs::write(&path, fs::read_to_string(&path).unwrap() + "{\"addr\": \"10.0.").unwrap();
+
+        let mut store = HistoryStore::open(&path).unwrap();
+        assert_eq!(vec![NodeId::new("10.0.0.1", 3001), NodeId::new("relay.example.com", 3001)], store.ids());
+        let records = store.records(&NodeId::new("10.0.0.1", 3001));
+        assert_eq!(2, records.len());
+        assert!(records[0].timestamp < records[1].timestamp);
+        assert_eq!(Some(Duration::from_millis(40)), records[1].total_latency);
+
+        let offline = &store.records(&NodeId::new("RELAY.example.com", 3001))[0];
+        assert!(!offline.online);
+        assert_eq!(None, offline.con_latency);
+        assert_eq!(Some(PingError::Timeout("handshake".to_string())), offline.error);
+
+        store.compact(Duration::from_secs(24 * HOUR)).unwrap();
+        let store = HistoryStore::open(&path).unwrap();
+        assert_eq!(1, store.records(&NodeId::new("10.0.0.1", 3001)).len());
+        assert_eq!(2, store.ids().len());
+
+        // the record written after a cut line is kept
+        fs::write(&path, fs::read_to_string(&path).unwrap() + "{\"addr\": \"10.0.").unwrap();
+        let mut store = HistoryStore::open(&path).unwrap();
+        store.record(record("10.0.0.2", now(), Some(10))).unwrap();
+        let store = HistoryStore::open(&path).unwrap();
+        assert_eq!(1, store.records(&NodeId::new("10.0.0.2", 3001)).len());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn select_with_history() {
+        let now = now();
+        let mut store = HistoryStore::in_memory();
+        for i in 0..10 {
+            // flaky but fast right now
+            store.record(record("10.0.0.1", now - i, if i < 5 { Some(10) } else { None })).unwrap();
+            // steady
+            store.record(record("10.0.0.2", now - i, Some(60))).unwrap();
+            // fast today, spikes from time to time
+            store.record(record("10.0.0.3", now - i, Some(if i % 3 == 0 { 400 } else { 30 }))).unwrap();
+        }
+        let nodes = vec![PingedNode::new("10.0.0.1").latency(5).build(), PingedNode::new("10.0.0.2").latency(70).build(), PingedNode::new("10.0.0.3").latency(20).build(), PingedNode::new("10.0.0.4").latency(90).build()];
+        let summaries = store.summaries(Duration::from_secs(HOUR));
+        assert_eq!(3, summaries.len());
+
+        let mut selector = Selector::new(SelectOptions { count: 2, min_uptime: Some(90.0), ..Default::default() });
+        selector.set_history(Box::new(summaries.clone()));
+        let selection = selector.select(&nodes);
+        let addrs: Vec<&str> = selection.selected.iter().map(|n| n.addr()).collect();
+        assert_eq!(vec!["10.0.0.3", "10.0.0.2"], addrs);
+        assert_eq!(RejectReason::LowUptime(50), selection.rejected.iter().find(|r| r.node.addr() == "10.0.0.1").unwrap().reason);
+
+        let options = SelectOptions { count: 4, metric: LatencyMetric::HistoryP95, ..Default::default() };
+        let mut selector = Selector::new(options);
+        selector.set_history(Box::new(summaries));
+        let addrs: Vec<String> = selector.select(&nodes).selected.iter().map(|n| n.addr().to_string()).collect();
+        // 10.0.0.4 has no history and is ranked by its last latency
+        assert_eq!(vec!["10.0.0.1", "10.0.0.2", "10.0.0.4", "10.0.0.3"], addrs);
+
+        let mut selector = Selector::new(SelectOptions { min_uptime: Some(90.0), ..Default::default() });
+        selector.set_history(Box::new(HashMap::new()));
+        assert_eq!(4, selector.select(&nodes).selected.len());
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::node::{Node, NodeId};
+use crate::ping::{LatencyStats, PingError};
+use crate::types::AdakaiResult;
+
+mod history_tests;
+
+/// PingRecord holds the outcome of one ping of a node
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PingRecord {
+    /// addr is the canonical address of the node (see node::NodeId)
+    pub addr: String,
+
+    /// port is the TCP port of the node
+    pub port: u16,
+
+    /// timestamp is the time of the ping, in seconds since the unix epoch
+    pub timestamp: u64,
+
+    /// online is true if the node answered
+    pub online: bool,
+
+    /// con_latency is the connection latency, None if the connection failed
+    #[serde(default)]
+    pub con_latency: Option<Duration>,
+
+    /// total_latency is the connection plus handshake latency, None if the node was offline
+    #[serde(default)]
+    pub total_latency: Option<Duration>,
+
+    /// error is the reason the node was offline
+    #[serde(default)]
+    pub error: Option<PingError>,
+}
+
+impl PingRecord {
+    /// from_node: returns the record of the last ping of the node, None if the node was not
+    /// measured (see Node::measured)
+    /// # Arguments:
+    /// * `node:` the pinged node
+    /// * `timestamp:` the time of the ping, in seconds since the unix epoch
+    pub fn from_node(node: &Node, timestamp: u64) -> Option<PingRecord> {
+        if !node.measured() {
+            return None;
+        }
+        let id = node.id();
+        Some(PingRecord {
+            addr: id.addr,
+            port: id.port,
+            timestamp,
+            online: node.online(),
+            // a node that refused the handshake still has a connection latency
+            con_latency: Some(node.con_latency()).filter(|l| !l.is_zero()),
+            total_latency: Some(node.total_latency()).filter(|_| node.online()),
+            error: node.online_error(),
+        })
+    }
+
+    /// id: returns the identity of the node the record belongs to
+    pub fn id(&self) -> NodeId {
+        NodeId::new(&self.addr, self.port)
+    }
+}
+
+/// HistorySummary holds the aggregates of the pings of a node over a time window
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HistorySummary {
+    /// checks is the number of pings in the window
+    pub checks: usize,
+
+    /// online is the number of pings the node answered
+    pub online: usize,
+
+    /// uptime is the percentage of pings the node answered, between 0 and 100
+    pub uptime: f64,
+
+    /// latency holds the percentiles of the total latency of the pings the node answered; its
+    /// failures are the pings it did not answer
+    pub latency: LatencyStats,
+
+    /// last_seen_online is the time of the last ping the node answered, over the whole history,
+    /// in seconds since the unix epoch
+    pub last_seen_online: Option<u64>,
+
+    /// last_checked is the time of the last ping of the node, in seconds since the unix epoch
+    pub last_checked: Option<u64>,
+}
+
+/// HistoryStore keeps the outcome of every ping of every node in an append-only file of json
+/// lines, one PingRecord per line. The whole history is loaded in memory when the store is
+/// opened; `compact` drops the records that are too old to matter.
+pub struct HistoryStore {
+    path: Option<PathBuf>,
+    records: HashMap<NodeId, Vec<PingRecord>>,
+}
+
+/// now: returns the current time in seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// terminate_last_line: appends a newline to the file if its last line has none
+fn terminate_last_line(path: &Path) -> AdakaiResult<()> {
+    let mut file = File::open(path)?;
+    if file.seek(SeekFrom::End(0))? == 0 {
+        return Ok(());
+    }
+    let mut last = [0u8; 1];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    if last[0] != b'\n' {
+        OpenOptions::new().append(true).open(path)?.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+impl HistoryStore {
+    /// open: opens the store kept in the file, creating it on the first record. Lines that cannot
+    /// be decoded (e.g. a line cut by a crash) are skipped, and a cut last line is terminated so
+    /// that the next record is written on a line of its own.
+    pub fn open<P: AsRef<Path>>(path: P) -> AdakaiResult<HistoryStore> {
+        let path = path.as_ref().to_path_buf();
+        let mut store = HistoryStore { path: Some(path.clone()), records: HashMap::new() };
+
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(store),
+            Err(e) => return Err(e.into()),
+        };
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<PingRecord>(&line) {
+                Ok(record) => store.insert(record),
+                Err(e) => warn!("skipping history record: {}", e),
+            }
+        }
+        terminate_last_line(&path)?;
+        Ok(store)
+    }
+
+    /// in_memory: returns a store that is not written anywhere
+    pub fn in_memory() -> HistoryStore {
+        HistoryStore { path: None, records: HashMap::new() }
+    }
+
+    fn insert(&mut self, record: PingRecord) {
+        let records = self.records.entry(record.id()).or_default();
+        // keeps the records in time order, also when they are not recorded in order
+        let at = records.partition_point(|r| r.timestamp <= record.timestamp);
+        records.insert(at, record);
+    }
+
+    fn append(&self, records: &[PingRecord]) -> AdakaiResult<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut writer = BufWriter::new(file);
+        for record in records {
+            serde_json::to_writer(&mut writer, record)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// record: adds a ping outcome to the store
+    pub fn record(&mut self, record: PingRecord) -> AdakaiResult<()> {
+        self.append(std::slice::from_ref(&record))?;
+        self.insert(record);
+        Ok(())
+    }
+
+    /// record_nodes: adds the outcome of the last ping of every measured node, timestamped now.
+    /// Returns the number of records added.
+    pub fn record_nodes(&mut self, nodes: &[Node]) -> AdakaiResult<usize> {
+        let timestamp = now();
+        let records: Vec<PingRecord> = nodes.iter().filter_map(|n| PingRecord::from_node(n, timestamp)).collect();
+        self.append(&records)?;
+        let count = records.len();
+        for record in records {
+            self.insert(record);
+        }
+        Ok(count)
+    }
+
+    /// records: returns the records of the node, oldest first
+    pub fn records(&self, id: &NodeId) -> &[PingRecord] {
+        self.records.get(id).map_or(&[], |r| r.as_slice())
+    }
+
+    /// ids: returns the identities of every node with a record
+    pub fn ids(&self) -> Vec<NodeId> {
+        let mut ids: Vec<NodeId> = self.records.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// summary: returns the aggregates of the pings of the node over the last `window`, None if
+    /// the node has no record at all
+    pub fn summary(&self, id: &NodeId, window: Duration) -> Option<HistorySummary> {
+        self.summary_since(id, now().saturating_sub(window.as_secs()))
+    }
+
+    /// summary_since: returns the aggregates of the pings of the node made at or after
+    /// `since` (seconds since the unix epoch), None if the node has no record at all
+    pub fn summary_since(&self, id: &NodeId, since: u64) -> Option<HistorySummary> {
+        let records = self.records.get(id)?;
+        let last_seen_online = records.iter().rev().find(|r| r.online).map(|r| r.timestamp);
+        let window: Vec<&PingRecord> = records.iter().filter(|r| r.timestamp >= since).collect();
+
+        let latencies: Vec<Duration> = window.iter().filter(|r| r.online).filter_map(|r| r.total_latency).collect();
+        let online = window.iter().filter(|r| r.online).count();
+        let checks = window.len();
+
+        Some(HistorySummary {
+            checks,
+            online,
+            uptime: if checks == 0 { 0.0 } else { online as f64 * 100.0 / checks as f64 },
+            latency: LatencyStats::from_samples(&latencies, (checks - online) as u32),
+            last_seen_online,
+            last_checked: window.last().map(|r| r.timestamp),
+        })
+    }
+
+    /// summaries: returns the aggregates over the last `window` of every node with a record, to
+    /// be used by the peer selection (see select::Selector::set_history)
+    pub fn summaries(&self, window: Duration) -> HashMap<NodeId, HistorySummary> {
+        let since = now().saturating_sub(window.as_secs());
+        self.records
+            .keys()
+            .filter_map(|id| self.summary_since(id, since).map(|s| (id.clone(), s)))
+            .collect()
+    }
+
+    /// compact: drops the records older than `keep` and rewrites the file with the others
+    pub fn compact(&mut self, keep: Duration) -> AdakaiResult<()> {
+        let since = now().saturating_sub(keep.as_secs());
+        for records in self.records.values_mut() {
+            records.retain(|r| r.timestamp >= since);
+        }
+        self.records.retain(|_, records| !records.is_empty());
+
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let tmp = path.with_extension("compact");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp)?);
+            let mut records: Vec<&PingRecord> = self.records.values().flatten().collect();
+            records.sort_by_key(|r| r.timestamp);
+            for record in records {
+                serde_json::to_writer(&mut writer, record)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+        }
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
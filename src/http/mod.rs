@@ -0,0 +1,84 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// HttpResponse holds the parts of an HTTP response used by the clients of the crate
+#[derive(Clone, Debug, Default)]
+pub struct HttpResponse {
+    /// status is the HTTP status code
+    pub status: u16,
+
+    /// retry_after is the value of the Retry-After header, if any
+    pub retry_after: Option<Duration>,
+
+    /// body is the response body
+    pub body: String,
+}
+
+/// HttpError is returned when an HTTP request could not be performed (DNS, connection, TLS,
+/// timeout...), holds the reason
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpError(pub String);
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HTTP transport error: {}", self.0)
+    }
+}
+
+impl Error for HttpError {}
+
+/// HttpTransport performs HTTP requests, e.g. for the alert webhooks. It lets them run against a
+/// local stand-in of the receiver.
+pub trait HttpTransport {
+    /// get: performs a GET request on the url
+    fn get(&self, url: &str) -> Result<HttpResponse, HttpError>;
+
+    /// post_json: performs a POST request on the url with a JSON body
+    fn post_json(&self, url: &str, body: &str) -> Result<HttpResponse, HttpError>;
+}
+
+/// ReqwestTransport is the HttpTransport used by default, based on the reqwest blocking client
+pub struct ReqwestTransport {
+    client: reqwest::blocking::Client,
+}
+
+impl ReqwestTransport {
+    /// new: returns a transport whose requests time out after the given duration
+    pub fn new(timeout: Duration) -> Result<ReqwestTransport, HttpError> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| HttpError(e.to_string()))?;
+        Ok(ReqwestTransport { client })
+    }
+}
+
+impl HttpTransport for ReqwestTransport {
+    fn get(&self, url: &str) -> Result<HttpResponse, HttpError> {
+        into_response(self.client.get(url).send())
+    }
+
+    fn post_json(&self, url: &str, body: &str) -> Result<HttpResponse, HttpError> {
+        let request = self
+            .client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string());
+        into_response(request.send())
+    }
+}
+
+fn into_response(response: reqwest::Result<reqwest::blocking::Response>) -> Result<HttpResponse, HttpError> {
+    let response = response.map_err(|e| HttpError(e.to_string()))?;
+    let status = response.status().as_u16();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs);
+    let body = response.text().map_err(|e| HttpError(e.to_string()))?;
+
+    Ok(HttpResponse { status, retry_after, body })
+}
@@ -27,9 +27,47 @@ pub mod ping;
 /// pool.
 /// * connection latency
 /// * online availability
+///
 /// (refer to module documentation for all features description)
 pub mod node;
 
+/// topology module reads and writes cardano-node topology files, mapping each of their entries to
+/// a Node
+pub mod topology;
+
+/// select module picks the best peers out of a vector of pinged nodes (lowest latency, quotas per
+/// location, caps per IP / subnet / ASN and pinned peers) and builds a topology with them
+pub mod select;
+
+/// http module holds the HTTP transport used by the clients of the crate (webhooks, topology
+/// updater), pluggable so that they can run against a local stand-in
+pub mod http;
+
+/// updater module is a client of the community topology-updater service: it registers a relay
+/// (push) and fetches the peers suggested for it (fetch)
+pub mod updater;
+
+/// history module keeps the outcome of every ping in a local append-only store and computes
+/// uptime, latency percentiles and last-seen-online over a time window
+pub mod history;
+
+/// monitor module keeps a set of nodes under watch: it pings every node on its own schedule and
+/// raises events (offline, back online, latency above a threshold) to registered handlers
+pub mod monitor;
+
+/// report module renders pinged nodes (latency, online state and error) as a terminal table, CSV,
+/// JSON or NDJSON, with the same filters and order in every format
+pub mod report;
+
+/// alert module evaluates alerting rules (offline, latency, tip lag, upstream peers of a producer)
+/// on the checks of the monitor and delivers the alerts and their recovery notices as JSON webhooks
+pub mod alert;
+
+/// metrics module exports the ping measurements (online status, latencies, handshake version,
+/// tip lag and error counters) on a prometheus `/metrics` endpoint
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
 /// types moduls holds multiple helper types related to all of the adakairust crate functionality
 pub mod types;
 
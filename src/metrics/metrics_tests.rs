@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::metrics::{MetricsExporter, MetricsRegistry};
+    use crate::monitor::{Monitor, MonitorOptions};
+    use crate::ping::mock::{closed_port, local_node, MockNode, PingedNode};
+    use crate::ping::{CancelHandle, PingError, PingOptions, Tip};
+    use crate::types::{NetworkType, NodeType};
+
+    async fn scrape(addr: SocketAddr, request: &str) -> String {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    fn get(path: &str) -> String {
+        format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path)
+    }
+
+    #[test]
+    fn render_metrics() {
+        let registry = MetricsRegistry::new();
+        let mut ahead = PingedNode::new("10.0.0.1").location("North America", "CR").network(NetworkType::Preprod).latency(40).build();
+        ahead.set_tip(Some(Tip { slot: 1_000, block_no: 10, hash: "aa".to_string() }));
+        ahead.set_node_type(NodeType::Producer);
+        let mut behind = PingedNode::new("10.0.0.2").location("North America", "CR").network(NetworkType::Preprod).latency(80).build();
+        behind.set_tip(Some(Tip { slot: 900, block_no: 9, hash: "bb".to_string() }));
+        behind.set_continent("Quote \"Land\"".to_string());
+        let offline = PingedNode::new("10.0.0.3").location("North America", "CR").network(NetworkType::Preprod).offline(PingError::Timeout("handshake".to_string())).build();
+        let mut not_measured = PingedNode::new("10.0.0.4").location("North America", "CR").network(NetworkType::Preprod).latency(10).build();
+        not_measured.set_measured(false);
+
+        registry.update_all(&[ahead, behind, offline.clone(), not_measured]);
+        registry.update(&offline);
+        let text = registry.render();
+
+        let labels = "addr=\"10.0.0.1\",port=\"3001\",continent=\"North America\",state=\"CR\",node_type=\"producer\",network_type=\"preprod\"";
+        assert!(text.contains(&format!("adakai_node_online{{{}}} 1\n", labels)), "{}", text);
+        assert!(text.contains(&format!("adakai_node_total_latency_seconds{{{}}} 0.04\n", labels)));
+        assert!(text.contains(&format!("adakai_node_connect_latency_seconds{{{}}} 0.02\n", labels)));
+        assert!(text.contains(&format!("adakai_node_tip_lag_slots{{{}}} 0\n", labels)));
+        assert!(text.contains("continent=\"Quote \\\"Land\\\"\""));
+        assert!(text.lines().any(|l| l.starts_with("adakai_node_tip_lag_slots{addr=\"10.0.0.2\"") && l.ends_with(" 100")));
+        assert!(text.lines().any(|l| l.starts_with("adakai_node_online{addr=\"10.0.0.3\"") && l.ends_with(" 0")));
+        assert!(!text.contains("adakai_node_total_latency_seconds{addr=\"10.0.0.3\""));
+        assert!(!text.contains("10.0.0.4"));
+
+        assert!(text.contains("# TYPE adakai_ping_errors_total counter\n"));
+        assert!(text.contains("adakai_ping_errors_total{kind=\"timeout\"} 2\n"));
+        assert!(text.contains("adakai_ping_errors_total{kind=\"connect\"} 0\n"));
+        assert!(text.contains("adakai_pings_total 4\n"));
+    }
+
+    #[test]
+    fn exporter_alongside_monitor() {
+        let good = MockNode::accepting(NetworkType::Mainnet);
+        let refused = closed_port();
+        let refused_node = local_node(refused);
+
+        let registry = MetricsRegistry::new();
+        let mut monitor = Monitor::new(MonitorOptions {
+            interval: Duration::from_millis(100),
+            retry_interval: Duration::from_millis(100),
+            ping: PingOptions { retries: 0, ..Default::default() },
+            ..Default::default()
+        });
+        monitor.add(good.node(NetworkType::Mainnet));
+        monitor.add(refused_node);
+        monitor.add_handler(Box::new(registry.clone()));
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let shutdown = CancelHandle::new();
+            let exporter = MetricsExporter::start("127.0.0.1:0".parse().unwrap(), registry, shutdown.clone()).unwrap();
+            let addr = exporter.local_addr();
+
+            let scraper = tokio::spawn({
+                let shutdown = shutdown.clone();
+                async move {
+                    tokio::time::sleep(Duration::from_millis(350)).await;
+                    let metrics = scrape(addr, &get("/metrics")).await;
+                    let not_found = scrape(addr, &get("/")).await;
+                    let post = scrape(addr, "POST /metrics HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").await;
+                    shutdown.cancel();
+                    (metrics, not_found, post)
+                }
+            });
+
+            monitor.run(shutdown).await;
+            let (metrics, not_found, post) = scraper.await.unwrap();
+            tokio::time::timeout(Duration::from_secs(5), exporter.stopped()).await.expect("exporter did not stop");
+
+            assert!(metrics.starts_with("HTTP/1.1 200 OK"), "{}", metrics);
+            assert!(metrics.contains("content-type: text/plain; version=0.0.4"));
+            let good_online = format!("adakai_node_online{{addr=\"127.0.0.1\",port=\"{}\"", good.port());
+            assert!(metrics.lines().any(|l| l.starts_with(&good_online) && l.ends_with(" 1")), "{}", metrics);
+            assert!(metrics.contains(&format!("adakai_node_handshake_version{{addr=\"127.0.0.1\",port=\"{}\"", good.port())));
+            let refused_online = format!("adakai_node_online{{addr=\"127.0.0.1\",port=\"{}\"", refused);
+            assert!(metrics.lines().any(|l| l.starts_with(&refused_online) && l.ends_with(" 0")));
+            let connect_errors = metrics.lines().find(|l| l.starts_with("adakai_ping_errors_total{kind=\"connect\"}")).unwrap();
+            assert!(!connect_errors.ends_with(" 0"), "{}", connect_errors);
+
+            assert!(not_found.starts_with("HTTP/1.1 404"), "{}", not_found);
+            assert!(post.starts_with("HTTP/1.1 405"), "{}", post);
+        });
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+
+use crate::monitor::{MonitorEvent, MonitorHandler, NodeState};
+use crate::node::{Node, NodeId};
+use crate::ping::{tip_lags, CancelHandle};
+use crate::types::{AdakaiError, AdakaiResult};
+
+mod metrics_tests;
+
+/// ERROR_KINDS are the kinds of ping errors counted, see ping::PingError::kind
+const ERROR_KINDS: [&str; 6] = ["resolve", "connect", "timeout", "handshake_refused", "version_mismatch", "io"];
+
+/// CONTENT_TYPE is the content type of the prometheus text exposition format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[derive(Default)]
+struct Measurements {
+    nodes: BTreeMap<NodeId, Node>,
+    pings: u64,
+    errors: BTreeMap<&'static str, u64>,
+}
+
+/// MetricsRegistry holds the last measurement of every node and the ping counters exposed by the
+/// exporter. It is cheap to clone, every clone sharing the same measurements, and it is a
+/// MonitorHandler so that it can be fed by the monitor directly.
+#[derive(Clone, Default)]
+pub struct MetricsRegistry {
+    measurements: Arc<Mutex<Measurements>>,
+}
+
+impl MetricsRegistry {
+    /// new: returns an empty registry
+    pub fn new() -> MetricsRegistry {
+        MetricsRegistry::default()
+    }
+
+    /// update: records the last ping of the node. Nodes that were not measured are ignored.
+    pub fn update(&self, node: &Node) {
+        if !node.measured() {
+            return;
+        }
+        let mut measurements = self.measurements.lock().unwrap();
+        measurements.pings += 1;
+        if let Some(error) = node.online_error() {
+            *measurements.errors.entry(error.kind()).or_insert(0) += 1;
+        }
+        measurements.nodes.insert(node.id(), node.clone());
+    }
+
+    /// update_all: records the last ping of every node (see ping::ping_vec)
+    pub fn update_all(&self, nodes: &[Node]) {
+        for node in nodes {
+            self.update(node);
+        }
+    }
+
+    /// remove: stops exposing the gauges of the node
+    pub fn remove(&self, id: &NodeId) {
+        self.measurements.lock().unwrap().nodes.remove(id);
+    }
+
+    /// render: returns the metrics in the prometheus text format
+    pub fn render(&self) -> String {
+        let measurements = self.measurements.lock().unwrap();
+        let nodes: Vec<Node> = measurements.nodes.values().cloned().collect();
+        let labels: Vec<String> = nodes.iter().map(labels).collect();
+        let lags = tip_lags(&nodes);
+
+        let mut out = String::new();
+        let mut gauge = |name: &str, help: &str, values: Vec<(usize, f64)>| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            for (i, value) in values {
+                let _ = writeln!(out, "{}{{{}}} {}", name, labels[i], value);
+            }
+        };
+
+        gauge(
+            "adakai_node_online",
+            "1 if the node answered its last ping, 0 otherwise",
+            nodes.iter().enumerate().map(|(i, n)| (i, if n.online() { 1.0 } else { 0.0 })).collect(),
+        );
+        gauge(
+            "adakai_node_connect_latency_seconds",
+            "TCP connection latency of the last ping",
+            nodes.iter().enumerate().filter(|(_, n)| !n.con_latency().is_zero()).map(|(i, n)| (i, n.con_latency().as_secs_f64())).collect(),
+        );
+        gauge(
+            "adakai_node_total_latency_seconds",
+            "connection plus handshake latency of the last ping",
+            nodes.iter().enumerate().filter(|(_, n)| n.online()).map(|(i, n)| (i, n.total_latency().as_secs_f64())).collect(),
+        );
+        gauge(
+            "adakai_node_handshake_version",
+            "version negotiated in the handshake",
+            nodes.iter().enumerate().filter_map(|(i, n)| n.version().map(|v| (i, v.version as f64))).collect(),
+        );
+        gauge(
+            "adakai_node_tip_lag_slots",
+            "slots the tip of the node is behind the best tip observed",
+            lags.iter().enumerate().filter_map(|(i, lag)| lag.map(|lag| (i, lag as f64))).collect(),
+        );
+
+        let _ = writeln!(out, "# HELP adakai_pings_total Number of pings recorded");
+        let _ = writeln!(out, "# TYPE adakai_pings_total counter");
+        let _ = writeln!(out, "adakai_pings_total {}", measurements.pings);
+
+        let _ = writeln!(out, "# HELP adakai_ping_errors_total Number of failed pings by kind of error");
+        let _ = writeln!(out, "# TYPE adakai_ping_errors_total counter");
+        for kind in ERROR_KINDS {
+            let count = measurements.errors.get(kind).copied().unwrap_or_default();
+            let _ = writeln!(out, "adakai_ping_errors_total{{kind=\"{}\"}} {}", kind, count);
+        }
+        out
+    }
+}
+
+impl MonitorHandler for MetricsRegistry {
+    fn on_event(&mut self, _event: &MonitorEvent) {}
+
+    fn on_check(&mut self, node: &Node, _state: NodeState) {
+        self.update(node);
+    }
+}
+
+/// escape: escapes a label value (backslash, double quote and line feed)
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn labels(node: &Node) -> String {
+    format!(
+        "addr=\"{}\",port=\"{}\",continent=\"{}\",state=\"{}\",node_type=\"{}\",network_type=\"{}\"",
+        escape(node.addr()),
+        node.port(),
+        escape(node.continent()),
+        escape(node.state()),
+        node.node_type(),
+        escape(&node.network_type().to_string())
+    )
+}
+
+async fn handle(registry: MetricsRegistry, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => Response::builder()
+            .header(hyper::header::CONTENT_TYPE, CONTENT_TYPE)
+            .body(Body::from(registry.render())),
+        (_, "/metrics") => Response::builder().status(StatusCode::METHOD_NOT_ALLOWED).body(Body::empty()),
+        _ => Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()),
+    };
+    Ok(response.expect("valid metrics response"))
+}
+
+/// MetricsExporter is a running HTTP server exposing the registry on `/metrics`
+pub struct MetricsExporter {
+    local_addr: SocketAddr,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl MetricsExporter {
+    /// start: starts serving the registry on the address until the shutdown handle is cancelled
+    /// (e.g. the one stopping the monitor). It must be called from within a tokio runtime.
+    /// # Arguments:
+    /// * `addr:` the address to listen on, e.g. `0.0.0.0:9101` (port 0 picks a free port)
+    /// * `registry:` the measurements to expose
+    /// * `shutdown:` stops the server once cancelled
+    pub fn start(addr: SocketAddr, registry: MetricsRegistry, shutdown: CancelHandle) -> AdakaiResult<MetricsExporter> {
+        let make_service = make_service_fn(move |_| {
+            let registry = registry.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |request| handle(registry.clone(), request))) }
+        });
+
+        let server = Server::try_bind(&addr).map_err(|e| AdakaiError::Network(Box::new(e)))?.serve(make_service);
+        let local_addr = server.local_addr();
+        info!("metrics exporter listening on http://{}/metrics", local_addr);
+
+        let task = tokio::spawn(async move {
+            let server = server.with_graceful_shutdown(async move { shutdown.cancelled().await });
+            if let Err(e) = server.await {
+                warn!("metrics exporter error: {}", e);
+            }
+        });
+        Ok(MetricsExporter { local_addr, task })
+    }
+
+    /// local_addr: returns the address the exporter listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// stopped: completes once the exporter stopped
+    pub async fn stopped(self) {
+        let _ = self.task.await;
+    }
+}
@@ -0,0 +1,323 @@
+use std::time::Duration;
+
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::node::{Node, NodeId};
+use crate::ping::{ping_stream, CancelHandle, PingControl, PingError, PingOptions};
+
+mod monitor_tests;
+
+/// MonitorOptions holds the schedule of the monitor and the thresholds raising events
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MonitorOptions {
+    /// interval is the time between two pings of a node, unless set for the node (default 60 s)
+    pub interval: Duration,
+
+    /// retry_interval is the time between two pings of a node that did not answer, until it
+    /// answers again (default 10 s)
+    pub retry_interval: Duration,
+
+    /// latency_threshold raises LatencyAbove once the total latency of a node goes above it, and
+    /// LatencyBelow once it is back (default none)
+    pub latency_threshold: Option<Duration>,
+
+    /// ping holds the timeouts, retry policy, mode and concurrency of the pings
+    pub ping: PingOptions,
+}
+
+impl Default for MonitorOptions {
+    fn default() -> Self {
+        MonitorOptions {
+            interval: Duration::from_secs(60),
+            retry_interval: Duration::from_secs(10),
+            latency_threshold: None,
+            ping: PingOptions::default(),
+        }
+    }
+}
+
+/// NodeState is the state of a monitored node
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NodeState {
+    /// Unknown: the node was not pinged yet
+    #[default]
+    Unknown,
+
+    /// Online: the node answered its last ping
+    Online,
+
+    /// Offline: the node did not answer its last ping
+    Offline,
+}
+
+/// EventKind is the change that raised an event
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    /// WentOffline: the node stopped answering (or did not answer its first ping), holds the
+    /// ping error
+    WentOffline(Option<PingError>),
+
+    /// CameOnline: the node answers again after being offline
+    CameOnline,
+
+    /// LatencyAbove: the total latency of the node went above the threshold, holds the latency
+    LatencyAbove(Duration),
+
+    /// LatencyBelow: the total latency of the node is back below the threshold, holds the
+    /// latency
+    LatencyBelow(Duration),
+}
+
+/// MonitorEvent is a state change of a monitored node
+#[derive(Clone, Debug)]
+pub struct MonitorEvent {
+    /// node is the node as measured by the ping that raised the event
+    pub node: Node,
+
+    /// kind is the change
+    pub kind: EventKind,
+}
+
+/// MonitorHandler receives the events of the monitor. Handlers are called from the monitor loop,
+/// so they should not block for long.
+pub trait MonitorHandler: Send {
+    /// on_event: called for every state change
+    fn on_event(&mut self, event: &MonitorEvent);
+
+    /// on_check: called after every ping, with the measured node (e.g. for recording the ping
+    /// history). It does nothing by default.
+    fn on_check(&mut self, _node: &Node, _state: NodeState) {}
+}
+
+impl<F> MonitorHandler for F
+where
+    F: FnMut(&MonitorEvent) + Send,
+{
+    fn on_event(&mut self, event: &MonitorEvent) {
+        self(event)
+    }
+}
+
+struct Watched {
+    node: Node,
+    interval: Duration,
+    next_check: Instant,
+    state: NodeState,
+    latency_high: bool,
+}
+
+/// Monitor keeps a set of nodes and pings each of them on its own schedule, faster after a
+/// failure, raising events to its handlers when a node changes state. All the pings go through
+/// the ping engine (see ping::ping_stream) on the runtime the monitor runs on.
+pub struct Monitor {
+    options: MonitorOptions,
+    watched: Vec<Watched>,
+    handlers: Vec<Box<dyn MonitorHandler>>,
+}
+
+impl Monitor {
+    /// new: returns a monitor without nodes
+    pub fn new(options: MonitorOptions) -> Monitor {
+        Monitor {
+            options,
+            watched: Vec::new(),
+            handlers: Vec::new(),
+        }
+    }
+
+    /// options: returns the monitor options
+    pub fn options(&self) -> &MonitorOptions {
+        &self.options
+    }
+
+    /// add: adds a node pinged every `options.interval`. A node with the same identity (see
+    /// Node::id) is replaced.
+    pub fn add(&mut self, node: Node) {
+        self.add_with_interval(node, self.options.interval);
+    }
+
+    /// add_with_interval: adds a node pinged on its own interval. A node with the same identity
+    /// is replaced.
+    pub fn add_with_interval(&mut self, node: Node, interval: Duration) {
+        self.remove(&node.id());
+        self.watched.push(Watched {
+            node,
+            interval,
+            next_check: Instant::now(),
+            state: NodeState::Unknown,
+            latency_high: false,
+        });
+    }
+
+    /// remove: stops monitoring the node, returns false if it was not monitored
+    pub fn remove(&mut self, id: &NodeId) -> bool {
+        let count = self.watched.len();
+        self.watched.retain(|w| &w.node.id() != id);
+        self.watched.len() != count
+    }
+
+    /// add_handler: registers a handler called on every event
+    pub fn add_handler(&mut self, handler: Box<dyn MonitorHandler>) {
+        self.handlers.push(handler);
+    }
+
+    /// nodes: returns the monitored nodes, as measured by their last ping
+    pub fn nodes(&self) -> Vec<Node> {
+        self.watched.iter().map(|w| w.node.clone()).collect()
+    }
+
+    /// state: returns the state of the node, None if it is not monitored
+    pub fn state(&self, id: &NodeId) -> Option<NodeState> {
+        self.watched.iter().find(|w| &w.node.id() == id).map(|w| w.state)
+    }
+
+    /// run: pings the nodes as they are due until the shutdown handle is cancelled. Every node is
+    /// pinged as soon as it is due, whatever the pings of the other nodes still in flight, up to
+    /// `options.ping.concurrency` pings at a time. The pings in flight are aborted on shutdown and
+    /// the monitor returns; it can be run again later.
+    /// It must be called from within a tokio runtime.
+    pub async fn run(&mut self, shutdown: CancelHandle) {
+        info!("monitoring {} nodes", self.watched.len());
+        let concurrency = self.options.ping.concurrency.max(1);
+        let mut pinging = vec![false; self.watched.len()];
+        let mut pings = FuturesUnordered::new();
+
+        while !shutdown.is_cancelled() {
+            let now = Instant::now();
+            for (index, watched) in self.watched.iter().enumerate() {
+                if pings.len() >= concurrency {
+                    break;
+                }
+                if !pinging[index] && watched.next_check <= now {
+                    pinging[index] = true;
+                    pings.push(ping_watched(index, watched.node.clone(), self.options.ping));
+                }
+            }
+
+            // the next node to become due, unless no ping can be started before one ends
+            let next = if pings.len() < concurrency {
+                self.watched
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, _)| !pinging[*index])
+                    .map(|(_, w)| w.next_check)
+                    .min()
+            } else {
+                None
+            };
+            tokio::select! {
+                Some((index, node)) = pings.next(), if !pings.is_empty() => {
+                    pinging[index] = false;
+                    if node.measured() {
+                        self.checked(index, node);
+                    }
+                }
+                _ = async {
+                    match next {
+                        Some(next) => tokio::time::sleep_until(next).await,
+                        None => futures::future::pending().await,
+                    }
+                } => {}
+                _ = shutdown.cancelled() => {}
+            }
+        }
+        // dropping the pings in flight aborts their tasks
+        drop(pings);
+        info!("monitor stopped");
+    }
+
+    /// run_until_signal: runs the monitor on a runtime of its own until the process receives
+    /// SIGINT (ctrl-c) or SIGTERM. It must not be called from within an asynchronous runtime.
+    pub fn run_until_signal(&mut self) {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("failed to start the monitor runtime");
+        runtime.block_on(async {
+            let shutdown = CancelHandle::new();
+            let on_signal = shutdown.clone();
+            tokio::spawn(async move {
+                shutdown_signal().await;
+                info!("signal received, stopping the monitor");
+                on_signal.cancel();
+            });
+            self.run(shutdown).await
+        });
+    }
+
+    fn checked(&mut self, index: usize, node: Node) {
+        let threshold = self.options.latency_threshold;
+        let retry_interval = self.options.retry_interval;
+        let watched = &mut self.watched[index];
+
+        let previous = watched.state;
+        let online = node.online();
+        watched.state = if online { NodeState::Online } else { NodeState::Offline };
+        watched.next_check = Instant::now() + if online { watched.interval } else { retry_interval.min(watched.interval) };
+        watched.node = node;
+
+        let mut events = Vec::new();
+        match (previous, watched.state) {
+            (NodeState::Online | NodeState::Unknown, NodeState::Offline) => {
+                events.push(EventKind::WentOffline(watched.node.online_error()))
+            }
+            (NodeState::Offline, NodeState::Online) => events.push(EventKind::CameOnline),
+            _ => {}
+        }
+        if let (Some(threshold), true) = (threshold, online) {
+            let latency = watched.node.total_latency();
+            if latency > threshold && !watched.latency_high {
+                watched.latency_high = true;
+                events.push(EventKind::LatencyAbove(latency));
+            } else if latency <= threshold && watched.latency_high {
+                watched.latency_high = false;
+                events.push(EventKind::LatencyBelow(latency));
+            }
+        }
+
+        let state = watched.state;
+        let node = &watched.node;
+        for handler in self.handlers.iter_mut() {
+            handler.on_check(node, state);
+        }
+        for kind in events {
+            debug!("monitor event {}: {:?}", node.id(), kind);
+            let event = MonitorEvent { node: node.clone(), kind };
+            for handler in self.handlers.iter_mut() {
+                handler.on_event(&event);
+            }
+        }
+    }
+}
+
+/// ping_watched: pings a single monitored node through the ping engine, returns it with its index
+async fn ping_watched(index: usize, node: Node, options: PingOptions) -> (usize, Node) {
+    let mut pings = Box::pin(ping_stream(vec![node.clone()], options, PingControl::default()));
+    let node = pings.next().await.map_or(node, |(_, measured)| measured);
+    (index, node)
+}
+
+/// shutdown_signal: completes once the process receives SIGINT (ctrl-c) or, on unix, SIGTERM
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => warn!("cannot listen to SIGTERM: {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        warn!("cannot listen to ctrl-c: {}", e);
+        futures::future::pending::<()>().await;
+    }
+}
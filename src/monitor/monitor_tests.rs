@@ -0,0 +1,153 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::monitor::{EventKind, Monitor, MonitorEvent, MonitorHandler, MonitorOptions, NodeState};
+    use crate::node::Node;
+    use crate::ping::mock::{closed_port, local_node, MockConfig, MockNode};
+    use crate::ping::{CancelHandle, PingOptions};
+    use crate::types::NetworkType;
+
+    /// Recorder keeps the events and counts the pings of every port
+    #[derive(Clone, Default)]
+    struct Recorder {
+        events: Arc<Mutex<Vec<(u16, EventKind)>>>,
+        checks: Arc<Mutex<Vec<(u16, NodeState)>>>,
+    }
+
+    impl MonitorHandler for Recorder {
+        fn on_event(&mut self, event: &MonitorEvent) {
+            self.events.lock().unwrap().push((event.node.port(), event.kind.clone()));
+        }
+
+        fn on_check(&mut self, node: &Node, state: NodeState) {
+            self.checks.lock().unwrap().push((node.port(), state));
+        }
+    }
+
+    impl Recorder {
+        fn events_of(&self, port: u16) -> Vec<EventKind> {
+            self.events.lock().unwrap().iter().filter(|(p, _)| *p == port).map(|(_, e)| e.clone()).collect()
+        }
+
+        fn checks_of(&self, port: u16) -> usize {
+            self.checks.lock().unwrap().iter().filter(|(p, _)| *p == port).count()
+        }
+    }
+
+    fn options() -> MonitorOptions {
+        MonitorOptions {
+            interval: Duration::from_millis(400),
+            retry_interval: Duration::from_millis(50),
+            ping: PingOptions { retries: 0, connect_timeout: Duration::from_millis(500), ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    fn run_for(monitor: &mut Monitor, duration: Duration) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let shutdown = CancelHandle::new();
+            let stop = shutdown.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(duration).await;
+                stop.cancel();
+            });
+            tokio::time::timeout(duration * 4, monitor.run(shutdown)).await.expect("monitor did not stop");
+        });
+    }
+
+    #[test]
+    fn monitor_reprobes_failed_nodes_faster() {
+        let good = MockNode::accepting(NetworkType::Mainnet);
+        let refused = closed_port();
+
+        let mut monitor = Monitor::new(options());
+        monitor.add(good.node(NetworkType::Mainnet));
+        monitor.add(local_node(refused));
+        // the same node again replaces the first one
+        monitor.add(local_node(refused));
+        assert_eq!(2, monitor.nodes().len());
+
+        let recorder = Recorder::default();
+        monitor.add_handler(Box::new(recorder.clone()));
+        run_for(&mut monitor, Duration::from_millis(1000));
+
+        assert!(recorder.events_of(good.port()).is_empty());
+        let events = recorder.events_of(refused);
+        assert_eq!(1, events.len(), "{:?}", events);
+        assert!(matches!(events[0], EventKind::WentOffline(Some(_))));
+
+        // every 400 ms for the good one, every 50 ms for the refused one
+        let good_checks = recorder.checks_of(good.port());
+        assert!((2..=4).contains(&good_checks), "{}", good_checks);
+        assert!(recorder.checks_of(refused) > 2 * good_checks);
+
+        assert_eq!(Some(NodeState::Online), monitor.state(&good.node(NetworkType::Mainnet).id()));
+        assert_eq!(Some(NodeState::Offline), monitor.state(&local_node(refused).id()));
+        assert!(monitor.nodes().iter().all(|n| n.measured()));
+    }
+
+    #[test]
+    fn monitor_pings_nodes_independently() {
+        let slow = MockNode::start(MockConfig { delay: Duration::from_millis(600), ..Default::default() });
+        let fast = MockNode::accepting(NetworkType::Mainnet);
+
+        let mut monitor = Monitor::new(options());
+        monitor.add_with_interval(slow.node(NetworkType::Mainnet), Duration::from_millis(100));
+        monitor.add_with_interval(fast.node(NetworkType::Mainnet), Duration::from_millis(100));
+
+        let recorder = Recorder::default();
+        monitor.add_handler(Box::new(recorder.clone()));
+        run_for(&mut monitor, Duration::from_millis(1000));
+
+        // the fast node is not held back by the ping of the slow one
+        let fast_checks = recorder.checks_of(fast.port());
+        assert!(fast_checks >= 5, "{}", fast_checks);
+        assert!((1..=2).contains(&recorder.checks_of(slow.port())));
+    }
+
+    #[test]
+    fn monitor_state_changes() {
+        let port = closed_port();
+        let mut monitor = Monitor::new(options());
+        monitor.add_with_interval(local_node(port), Duration::from_millis(100));
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        monitor.add_handler(Box::new(move |event: &MonitorEvent| sink.lock().unwrap().push(event.kind.clone())));
+
+        // the node comes up after a while
+        let starter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            MockNode::start_on(&format!("127.0.0.1:{}", port), MockConfig::default())
+        });
+        run_for(&mut monitor, Duration::from_millis(800));
+        let _mock = starter.join().unwrap();
+
+        let events = events.lock().unwrap().clone();
+        assert_eq!(2, events.len(), "{:?}", events);
+        assert!(matches!(events[0], EventKind::WentOffline(_)));
+        assert_eq!(EventKind::CameOnline, events[1]);
+    }
+
+    #[test]
+    fn monitor_latency_threshold() {
+        let slow = MockNode::start(MockConfig { delay: Duration::from_millis(120), ..Default::default() });
+        let options = MonitorOptions { latency_threshold: Some(Duration::from_millis(60)), ..options() };
+        let mut monitor = Monitor::new(options);
+        monitor.add_with_interval(slow.node(NetworkType::Mainnet), Duration::from_millis(100));
+
+        let recorder = Recorder::default();
+        monitor.add_handler(Box::new(recorder.clone()));
+        run_for(&mut monitor, Duration::from_millis(700));
+
+        // raised once, not on every ping above the threshold
+        let events = recorder.events_of(slow.port());
+        assert_eq!(1, events.len(), "{:?}", events);
+        assert!(matches!(events[0], EventKind::LatencyAbove(l) if l >= Duration::from_millis(120)));
+        assert!(recorder.checks_of(slow.port()) > 1);
+    }
+}
@@ -0,0 +1,264 @@
+use std::error::Error;
+use std::fmt;
+use std::net::IpAddr;
+
+use crate::node::Node;
+use crate::types::{country_code, Continent, NetworkType, NodeType};
+
+/// MAX_VALENCY is the highest valency accepted for a node
+pub const MAX_VALENCY: u16 = 100;
+
+/// ValidationError is a reason why a node is not valid
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    /// EmptyAddress: the node has no address
+    EmptyAddress,
+
+    /// InvalidAddress: the address is neither an IP address nor an RFC 1123 host name, holds the
+    /// address
+    InvalidAddress(String),
+
+    /// InvalidPort: the port is 0
+    InvalidPort(u16),
+
+    /// InvalidValency: the valency is not between 1 and MAX_VALENCY, holds the valency
+    InvalidValency(u16),
+
+    /// UnknownContinent: the continent is not one of types::Continent, holds the continent
+    UnknownContinent(String),
+
+    /// UnknownCountry: the country is not an ISO 3166-1 country, holds the country
+    UnknownCountry(String),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::EmptyAddress => write!(f, "empty address"),
+            ValidationError::InvalidAddress(addr) => write!(f, "invalid address '{}'", addr),
+            ValidationError::InvalidPort(port) => write!(f, "invalid port {}", port),
+            ValidationError::InvalidValency(valency) => {
+                write!(f, "invalid valency {}, expected 1 to {}", valency, MAX_VALENCY)
+            }
+            ValidationError::UnknownContinent(continent) => write!(f, "unknown continent '{}'", continent),
+            ValidationError::UnknownCountry(country) => write!(f, "unknown country '{}'", country),
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+/// InvalidNode holds the validation errors of a node of a topology
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidNode {
+    /// position is the position of the node in the topology
+    pub position: usize,
+
+    /// addr is the address of the node, as written in the topology
+    pub addr: String,
+
+    /// port is the port of the node
+    pub port: u16,
+
+    /// errors are every reason why the node is not valid
+    pub errors: Vec<ValidationError>,
+}
+
+impl fmt::Display for InvalidNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<String> = self.errors.iter().map(|e| e.to_string()).collect();
+        write!(f, "node #{} '{}:{}': {}", self.position, self.addr, self.port, errors.join(", "))
+    }
+}
+
+/// ValidationErrors holds every invalid node of a topology
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationErrors(pub Vec<InvalidNode>);
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nodes: Vec<String> = self.0.iter().map(|n| n.to_string()).collect();
+        write!(f, "{} invalid nodes: {}", self.0.len(), nodes.join("; "))
+    }
+}
+
+impl Error for ValidationErrors {}
+
+/// valid_hostname: returns true if the name is an RFC 1123 host name: dot separated labels of 1
+/// to 63 letters, digits and hyphens, not starting nor ending with a hyphen, 253 characters at
+/// most. A trailing dot is accepted. The last label may not be all digits, so that a malformed
+/// IPv4 address such as `256.1.1.1` is not taken for a host name.
+pub fn valid_hostname(name: &str) -> bool {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    !name.is_empty()
+        && name.len() <= 253
+        && !name.rsplit('.').next().unwrap_or_default().chars().all(|c| c.is_ascii_digit())
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// NodeBuilder builds a node, checking its address, port and valency and normalizing its
+/// location: the continent is written as in types::Continent and the country (the `state` of
+/// the node) as its ISO 3166-1 alpha-2 code. Every error is reported, not only the first one.
+/// ```
+/// use adakairust::node::NodeBuilder;
+/// let node = NodeBuilder::new("Relay.AdakaiLabs.com", 3001)
+///     .valency(2)
+///     .continent("north_america")
+///     .country("Costa Rica")
+///     .build()
+///     .unwrap();
+/// assert_eq!("North America", node.continent());
+/// assert_eq!("CR", node.state());
+/// ```
+#[derive(Clone, Debug)]
+pub struct NodeBuilder {
+    node: Node,
+    valency: Option<u16>,
+    continent: Option<String>,
+    country: Option<String>,
+}
+
+impl NodeBuilder {
+    /// new: returns a builder of a node with a valency of 1 and no location
+    /// # Arguments:
+    /// * `addr:` an IP address (IPv6 may be bracketed) or a host name
+    /// * `port:` the TCP port, 1 to 65535
+    pub fn new(addr: &str, port: u16) -> NodeBuilder {
+        let mut node = Node::default();
+        node.set_addr(addr.to_string());
+        node.set_port(port);
+        NodeBuilder {
+            node,
+            valency: None,
+            continent: None,
+            country: None,
+        }
+    }
+
+    /// from_node: returns a builder checking an existing node (e.g. read from a topology file),
+    /// keeping its measurements and extra fields. The valency is checked as is: the topology
+    /// parsers give a valency of 1 to the nodes without one, so a valency of 0 was written.
+    pub fn from_node(node: Node) -> NodeBuilder {
+        let valency = Some(node.valency());
+        let continent = Some(node.continent().to_string()).filter(|c| !c.trim().is_empty());
+        let country = Some(node.state().to_string()).filter(|c| !c.trim().is_empty());
+        NodeBuilder {
+            node,
+            valency,
+            continent,
+            country,
+        }
+    }
+
+    /// valency: sets the valency, 1 to MAX_VALENCY
+    pub fn valency(mut self, valency: u16) -> NodeBuilder {
+        self.valency = Some(valency);
+        self
+    }
+
+    /// continent: sets the continent, by name or code (see types::Continent)
+    pub fn continent(mut self, continent: &str) -> NodeBuilder {
+        self.continent = Some(continent.to_string());
+        self
+    }
+
+    /// country: sets the country, by ISO 3166-1 code or name (see types::country_code)
+    pub fn country(mut self, country: &str) -> NodeBuilder {
+        self.country = Some(country.to_string());
+        self
+    }
+
+    /// network_type: sets the network the node runs
+    pub fn network_type(mut self, network_type: NetworkType) -> NodeBuilder {
+        self.node.set_network_type(network_type);
+        self
+    }
+
+    /// node_type: sets the node type (relay or producer)
+    pub fn node_type(mut self, node_type: NodeType) -> NodeBuilder {
+        self.node.set_node_type(node_type);
+        self
+    }
+
+    /// build: returns the node, or every reason why it is not valid
+    pub fn build(self) -> Result<Node, Vec<ValidationError>> {
+        let mut node = self.node;
+        let mut errors = Vec::new();
+
+        let addr = node.addr().trim();
+        let unbracketed = addr.strip_prefix('[').and_then(|a| a.strip_suffix(']'));
+        let addr = match unbracketed.unwrap_or(addr).parse::<IpAddr>() {
+            Ok(IpAddr::V4(_)) if unbracketed.is_some() => {
+                errors.push(ValidationError::InvalidAddress(node.addr().to_string()));
+                addr.to_string()
+            }
+            Ok(ip) => ip.to_string(),
+            Err(_) if addr.is_empty() => {
+                errors.push(ValidationError::EmptyAddress);
+                String::new()
+            }
+            Err(_) if unbracketed.is_some() || !valid_hostname(addr) => {
+                errors.push(ValidationError::InvalidAddress(node.addr().to_string()));
+                addr.to_string()
+            }
+            Err(_) => addr.to_string(),
+        };
+        node.set_addr(addr);
+
+        if node.port() == 0 {
+            errors.push(ValidationError::InvalidPort(node.port()));
+        }
+
+        let valency = self.valency.unwrap_or(1);
+        if !(1..=MAX_VALENCY).contains(&valency) {
+            errors.push(ValidationError::InvalidValency(valency));
+        }
+        node.set_valency(valency);
+
+        match self.continent.as_deref().map(|c| (c, c.parse::<Continent>())) {
+            Some((_, Ok(continent))) => node.set_continent(continent.to_string()),
+            Some((continent, Err(_))) => errors.push(ValidationError::UnknownContinent(continent.to_string())),
+            None => node.set_continent(String::new()),
+        }
+        match self.country.as_deref().map(|c| (c, country_code(c))) {
+            Some((_, Some(code))) => node.set_state(code.to_string()),
+            Some((country, None)) => errors.push(ValidationError::UnknownCountry(country.to_string())),
+            None => node.set_state(String::new()),
+        }
+
+        if errors.is_empty() {
+            Ok(node)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// validate_nodes: checks and normalizes every node (see NodeBuilder::from_node), e.g. the nodes
+/// of a topology file, and returns the normalized nodes or every invalid node with all its errors
+pub fn validate_nodes(nodes: &[Node]) -> Result<Vec<Node>, ValidationErrors> {
+    let mut valid = Vec::with_capacity(nodes.len());
+    let mut invalid = Vec::new();
+    for (position, node) in nodes.iter().enumerate() {
+        match NodeBuilder::from_node(node.clone()).build() {
+            Ok(node) => valid.push(node),
+            Err(errors) => invalid.push(InvalidNode {
+                position,
+                addr: node.addr().to_string(),
+                port: node.port(),
+                errors,
+            }),
+        }
+    }
+    if invalid.is_empty() {
+        Ok(valid)
+    } else {
+        Err(ValidationErrors(invalid))
+    }
+}
@@ -1,11 +1,90 @@
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
+use crate::ping::{FamilyOutcome, LatencyStats, NegotiatedVersion, PingError, RttWindow, Tip};
 use crate::types::{AdakaiResult, NetworkType, NodeType};
 
-///
+mod builder;
 mod node_tests;
+mod resolve;
+
+pub use builder::{valid_hostname, validate_nodes, InvalidNode, NodeBuilder, ValidationError, ValidationErrors, MAX_VALENCY};
+pub use resolve::{dedup, resolve_all, Resolver, SystemResolver};
+
+/// NodeId is the canonical identity of a node: its address and port. Host names are lower cased
+/// without the trailing dot, and IP literals are written in their canonical form.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct NodeId {
+    /// addr is the canonical host name or IP address
+    pub addr: String,
+
+    /// port is the TCP port
+    pub port: u16,
+}
+
+impl NodeId {
+    /// new: returns the canonical identity of the address and port
+    pub fn new(addr: &str, port: u16) -> NodeId {
+        let addr = addr.trim();
+        let unbracketed = addr.strip_prefix('[').and_then(|a| a.strip_suffix(']')).unwrap_or(addr);
+        let addr = match unbracketed.parse::<IpAddr>() {
+            Ok(ip) => ip.to_string(),
+            Err(_) => addr.trim_end_matches('.').to_ascii_lowercase(),
+        };
+        NodeId { addr, port }
+    }
+}
+
+/// ParseNodeIdError is returned when a string is not a `host:port` address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseNodeIdError(String);
+
+impl fmt::Display for ParseNodeIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid node address '{}', expected host:port or [ipv6]:port", self.0)
+    }
+}
+
+impl Error for ParseNodeIdError {}
+
+impl FromStr for NodeId {
+    type Err = ParseNodeIdError;
+
+    /// from_str: parses `host:port`, `ipv4:port` or `[ipv6]:port` into its canonical identity
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseNodeIdError(s.to_string());
+        let (host, port) = s.trim().rsplit_once(':').ok_or_else(error)?;
+        let port: u16 = port.parse().map_err(|_| error())?;
+        let bracketed = host.starts_with('[') && host.ends_with(']');
+        if host.is_empty() || port == 0 || (host.contains(':') && !bracketed) {
+            return Err(error());
+        }
+        Ok(NodeId::new(host, port))
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.addr.contains(':') {
+            write!(f, "[{}]:{}", self.addr, self.port)
+        } else {
+            write!(f, "{}:{}", self.addr, self.port)
+        }
+    }
+}
+
+/// default_valency: the valency of a node whose configuration has none, as cardano-node does
+fn default_valency() -> u16 {
+    1
+}
 
 /// Node contains data for describing a cardano node configuration:
 /// * addr
@@ -17,8 +96,17 @@ mod node_tests;
 /// better managing chain nodes:
 /// * connection latency
 /// * round trip latency
-/// * node type (testnet or mainnet, potencially others)
-#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+/// * latency statistics (min, median, p95, jitter, loss)
+/// * chain tip
+/// * keep-alive round trips
+/// * negotiated node-to-node version
+/// * network type (mainnet, preprod, preview, potencially others)
+/// * resolved addresses (A and AAAA records of its host name)
+/// * IPv4 and IPv6 latency and reachability
+///
+/// Two nodes are equal when they have the same identity, `addr:port` (see Node::id), whatever
+/// their other fields.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Node {
     addr: String,
     port: u16,
@@ -29,7 +117,7 @@ pub struct Node {
     #[serde(default)]
     state: String,
 
-    #[serde(default)]
+    #[serde(default = "default_valency")]
     valency: u16,
 
     #[serde(default)]
@@ -38,6 +126,9 @@ pub struct Node {
     #[serde(default)]
     total_latency: Duration,
 
+    #[serde(default)]
+    latency_stats: Option<LatencyStats>,
+
     #[serde(default)]
     network_type: NetworkType,
 
@@ -49,8 +140,34 @@ pub struct Node {
     online: bool,
 
     #[serde(default)]
-    online_error: String,
+    measured: bool,
+
+    #[serde(default)]
+    online_error: Option<PingError>,
+
+    #[serde(default)]
+    tip: Option<Tip>,
 
+    #[serde(default)]
+    tip_error: Option<PingError>,
+
+    #[serde(default)]
+    rtt: Option<RttWindow>,
+
+    #[serde(default)]
+    version: Option<NegotiatedVersion>,
+
+    #[serde(default)]
+    resolved: Vec<IpAddr>,
+
+    #[serde(default)]
+    ipv4: Option<FamilyOutcome>,
+
+    #[serde(default)]
+    ipv6: Option<FamilyOutcome>,
+
+    #[serde(flatten)]
+    extra: Map<String, Value>,
 }
 
 impl Node {
@@ -98,22 +215,94 @@ impl Node {
         self.total_latency = latency;
     }
 
+    /// set_latency_stats: sets the latency statistics of several pings of the node (see
+    /// ping::PingOptions::samples)
+    #[allow(dead_code)]
+    pub fn set_latency_stats(&mut self, stats: Option<LatencyStats>) {
+        self.latency_stats = stats;
+    }
+
     /// set_online: set online status
     #[allow(dead_code)]
     pub fn set_online(&mut self, online: bool) {
         self.online = online;
     }
 
+    /// set_tip: sets the chain tip announced by the node (see ping::PingOptions::query_tip)
+    #[allow(dead_code)]
+    pub fn set_tip(&mut self, tip: Option<Tip>) {
+        self.tip = tip;
+    }
+
+    /// set_tip_error: sets the reason the chain tip could not be learnt
+    #[allow(dead_code)]
+    pub fn set_tip_error(&mut self, tip_error: Option<PingError>) {
+        self.tip_error = tip_error;
+    }
+
+    /// set_rtt: sets the keep-alive round trips measured on a persistent connection to the node
+    /// (see ping::KeepAliveSession)
+    #[allow(dead_code)]
+    pub fn set_rtt(&mut self, rtt: Option<RttWindow>) {
+        self.rtt = rtt;
+    }
+
+    /// set_version: sets the node-to-node version negotiated with the node
+    #[allow(dead_code)]
+    pub fn set_version(&mut self, version: Option<NegotiatedVersion>) {
+        self.version = version;
+    }
+
+    /// set_resolved: sets the addresses the host name of the node resolves to
+    #[allow(dead_code)]
+    pub fn set_resolved(&mut self, resolved: Vec<IpAddr>) {
+        self.resolved = resolved;
+    }
+
+    /// set_ipv4: sets the result of the ping of the node over IPv4
+    #[allow(dead_code)]
+    pub fn set_ipv4(&mut self, ipv4: Option<FamilyOutcome>) {
+        self.ipv4 = ipv4;
+    }
+
+    /// set_ipv6: sets the result of the ping of the node over IPv6
+    #[allow(dead_code)]
+    pub fn set_ipv6(&mut self, ipv6: Option<FamilyOutcome>) {
+        self.ipv6 = ipv6;
+    }
+
+    /// set_measured: sets whether the ping of the node completed, false when it was cancelled
+    /// before (see ping::ping_stream)
+    #[allow(dead_code)]
+    pub fn set_measured(&mut self, measured: bool) {
+        self.measured = measured;
+    }
+
     /// set_online_error: sets the error that explains non-online status
     #[allow(dead_code)]
-    pub fn set_online_error(&mut self, online_error: String) {
+    pub fn set_online_error(&mut self, online_error: Option<PingError>) {
         self.online_error = online_error;
     }
 
-    /// set_node_type:: sets type of node, (RELAY or PRODUCER)
+    /// set_node_type:: sets type of node, (RELAY or PRODUCER). It used to take a NetworkType and
+    /// set the network of the node, which set_network_type does now.
+    #[allow(dead_code)]
+    pub fn set_node_type(&mut self, ntype: NodeType) {
+        self.node_type = ntype;
+    }
+
+    /// set_network_type: sets the cardano network the node belongs to, its magic is the one used
+    /// when pinging the node
     #[allow(dead_code)]
-    pub fn set_node_type(&mut self, ntype: NetworkType) {
-        self.network_type = ntype;
+    pub fn set_network_type(&mut self, network_type: NetworkType) {
+        self.network_type = network_type;
+    }
+
+    /// set_extra: sets the fields of the node configuration that are not modeled by Node. They are
+    /// written back untouched when the node is serialized.
+    #[allow(dead_code)]
+    pub fn set_extra(&mut self, extra: Map<String, Value>) {
+        self.extra = extra;
     }
 
     /// addr: returns the IP address or DNS name
@@ -161,6 +350,12 @@ impl Node {
         self.total_latency
     }
 
+    /// latency_stats: returns the latency statistics, None if the node was pinged only once
+    #[allow(dead_code)]
+    pub fn latency_stats(&self) -> Option<&LatencyStats> {
+        self.latency_stats.as_ref()
+    }
+
     /// online: returns the online state, true for online, false for offline.
     /// default: offline
     #[allow(dead_code)]
@@ -168,13 +363,109 @@ impl Node {
         self.online
     }
 
-    /// online_error: returns the error associated to the online status.
+    /// tip: returns the chain tip announced by the node, None if it was not queried
     #[allow(dead_code)]
-    pub fn online_error(&self) -> String {
+    pub fn tip(&self) -> Option<&Tip> {
+        self.tip.as_ref()
+    }
+
+    /// tip_error: returns the reason the chain tip could not be learnt, None if it was learnt or
+    /// not queried
+    #[allow(dead_code)]
+    pub fn tip_error(&self) -> Option<PingError> {
+        self.tip_error.clone()
+    }
+
+    /// rtt: returns the keep-alive round trips, None if no keep-alive session was run
+    #[allow(dead_code)]
+    pub fn rtt(&self) -> Option<&RttWindow> {
+        self.rtt.as_ref()
+    }
+
+    /// version: returns the node-to-node version negotiated with the node and its capabilities,
+    /// None if the handshake did not complete
+    #[allow(dead_code)]
+    pub fn version(&self) -> Option<&NegotiatedVersion> {
+        self.version.as_ref()
+    }
+
+    /// offered_versions: returns the versions offered by the node when it refused every version
+    /// proposed by the ping, None otherwise
+    #[allow(dead_code)]
+    pub fn offered_versions(&self) -> Option<Vec<u64>> {
+        match &self.online_error {
+            Some(PingError::VersionMismatch(offered)) => Some(offered.clone()),
+            _ => None,
+        }
+    }
+
+    /// resolved: returns the addresses the node resolved to, empty if it was not resolved (see
+    /// Node::resolve)
+    #[allow(dead_code)]
+    pub fn resolved(&self) -> &[IpAddr] {
+        &self.resolved
+    }
+
+    /// ipv4: returns the latency and reachability of the node over IPv4, None if it was not
+    /// pinged over IPv4 (see ping::PingOptions::family)
+    #[allow(dead_code)]
+    pub fn ipv4(&self) -> Option<&FamilyOutcome> {
+        self.ipv4.as_ref()
+    }
+
+    /// ipv6: returns the latency and reachability of the node over IPv6, None if it was not
+    /// pinged over IPv6 (see ping::PingOptions::family)
+    #[allow(dead_code)]
+    pub fn ipv6(&self) -> Option<&FamilyOutcome> {
+        self.ipv6.as_ref()
+    }
+
+    /// id: returns the canonical identity of the node, `addr:port`
+    pub fn id(&self) -> NodeId {
+        NodeId::new(&self.addr, self.port)
+    }
+
+    /// endpoints: returns the socket addresses of the node: its resolved addresses, or its
+    /// address when it is an IP literal, with its port
+    pub fn endpoints(&self) -> Vec<SocketAddr> {
+        if !self.resolved.is_empty() {
+            return self.resolved.iter().map(|ip| SocketAddr::new(*ip, self.port)).collect();
+        }
+        match self.id().addr.parse::<IpAddr>() {
+            Ok(ip) => vec![SocketAddr::new(ip, self.port)],
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// resolve: looks up the A and AAAA records of the host name of the node and records them on
+    /// the node. An IP literal resolves to itself without any lookup.
+    /// # Arguments:
+    /// * `resolver:` the resolver used for the lookup (see SystemResolver)
+    pub fn resolve(&mut self, resolver: &dyn Resolver) -> io::Result<&[IpAddr]> {
+        let host = self.id().addr;
+        self.resolved = match host.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => resolver.lookup(&host)?,
+        };
+        Ok(&self.resolved)
+    }
+
+    /// measured: returns true if the node was pinged to completion, false if it was never pinged
+    /// or its ping was cancelled. The latencies and the online status of a node that was not
+    /// measured are meaningless.
+    #[allow(dead_code)]
+    pub fn measured(&self) -> bool {
+        self.measured
+    }
+
+    /// online_error: returns the error associated to the online status, None if the node is
+    /// online or was never pinged.
+    #[allow(dead_code)]
+    pub fn online_error(&self) -> Option<PingError> {
         self.online_error.clone()
     }
 
-    /// **network_type**: returns the cardano network type node (TESTNET or MAINNET)
+    /// **network_type**: returns the cardano network of the node (MAINNET, PREPROD, PREVIEW...)
     #[allow(dead_code)]
     pub fn network_type(&self) -> NetworkType {
         self.network_type
@@ -186,9 +477,16 @@ impl Node {
         self.node_type
     }
 
+    /// extra: returns the configuration fields not modeled by Node (e.g. fields added by the explorer
+    /// or by other tools), as found in the json the node was created from.
+    #[allow(dead_code)]
+    pub fn extra(&self) -> &Map<String, Value> {
+        &self.extra
+    }
+
     /// new_from_json:  takes a json encoded string and deserializes it into a Node struct.
     /// # Arguments:
-    /// - **network_type**: TESTNET or MAINNET type.
+    /// - **network_type**: the cardano network of the node (MAINNET, PREPROD, PREVIEW...).
     /// - **json**: a json encoded string with the node configuration as deliver by the official cardano explorer
     /// - **example**:
     ///  ``` [json]
@@ -200,13 +498,51 @@ impl Node {
     ///  }
     /// ```
     pub fn new_from_json(network_type: NetworkType, json: String) -> AdakaiResult<Node> {
-        let top_result: serde_json::Result<Node> = serde_json::from_str(&json);
-        match top_result {
-            Ok(mut node) => {
-                node.network_type = network_type;
-                return Ok(node);
-            }
-            Err(e) => AdakaiResult::Err(Box::try_from(e).unwrap()),
+        let mut node: Node = serde_json::from_str(&json)?;
+        node.network_type = network_type;
+        Ok(node)
+    }
+}
+
+impl Default for Node {
+    /// default: returns a node without address, with the default valency (see default_valency)
+    fn default() -> Self {
+        Node {
+            addr: String::new(),
+            port: 0,
+            continent: String::new(),
+            state: String::new(),
+            valency: default_valency(),
+            con_latency: Duration::default(),
+            total_latency: Duration::default(),
+            latency_stats: None,
+            network_type: NetworkType::default(),
+            node_type: NodeType::default(),
+            online: false,
+            measured: false,
+            online_error: None,
+            tip: None,
+            tip_error: None,
+            rtt: None,
+            version: None,
+            resolved: Vec::new(),
+            ipv4: None,
+            ipv6: None,
+            extra: Map::new(),
         }
     }
 }
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl Eq for Node {}
+
+impl Hash for Node {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
@@ -1,7 +1,10 @@
 #[cfg(test)]
 mod tests {
     // use crate::cardano_topology::{Node, Topology, TopologyResult, TYPE_MAINNET};
-    use crate::node::Node;
+    use std::collections::{HashMap, HashSet};
+    use std::net::IpAddr;
+
+    use crate::node::{dedup, resolve_all, validate_nodes, Node, NodeBuilder, NodeId, ValidationError, MAX_VALENCY};
     use crate::types::{AdakaiResult, NetworkType};
 
     const JSON_NODE_TEST: &str = r#"
@@ -13,6 +16,7 @@ mod tests {
     }"#;
 
     #[test]
+    #[allow(clippy::single_match)]
     fn basic_deserialize_node() {
         let top_result: AdakaiResult<Node> =
             Node::new_from_json(NetworkType::Mainnet, JSON_NODE_TEST.to_string());
@@ -39,4 +43,147 @@ mod tests {
             }
         };
     }
+
+    #[test]
+    fn deserialize_node_keeps_unknown_fields() {
+        let json = r#"{"addr": "54.220.20.40", "port": 3002, "distance": 217}"#;
+        let node = Node::new_from_json(NetworkType::Mainnet, json.to_string()).unwrap();
+
+        assert_eq!(Some(&serde_json::Value::from(217)), node.extra().get("distance"));
+
+        let value = serde_json::to_value(&node).unwrap();
+        assert_eq!(serde_json::Value::from(217), value["distance"]);
+    }
+
+    fn node(addr: &str, port: u16) -> Node {
+        let mut node = Node::default();
+        node.set_addr(addr.to_string());
+        node.set_port(port);
+        node
+    }
+
+    fn fake_resolver() -> HashMap<String, Vec<IpAddr>> {
+        let mut resolver = HashMap::new();
+        resolver.insert(
+            "relay.adakailabs.com".to_string(),
+            vec!["54.220.20.40".parse().unwrap(), "2a05:d018::40".parse().unwrap()],
+        );
+        resolver.insert("other.adakailabs.com".to_string(), vec!["54.220.20.41".parse().unwrap()]);
+        resolver
+    }
+
+    #[test]
+    fn node_identity() {
+        assert_eq!(node("Relay.AdakaiLabs.com.", 3001), node("relay.adakailabs.com", 3001));
+        assert_ne!(node("relay.adakailabs.com", 3001), node("relay.adakailabs.com", 3002));
+        assert_eq!(node("[2a05:d018:0::40]", 3001), node("2a05:d018::40", 3001));
+
+        assert_eq!("relay.adakailabs.com:3001", node("Relay.adakailabs.com", 3001).id().to_string());
+        assert_eq!("[2a05:d018::40]:3001", NodeId::new("2a05:d018::40", 3001).to_string());
+
+        assert_eq!(Ok(NodeId::new("relay.adakailabs.com", 3001)), "Relay.AdakaiLabs.com.:3001".parse());
+        assert_eq!(Ok(NodeId::new("54.220.20.40", 3002)), "54.220.20.40:3002".parse());
+        assert_eq!(Ok(NodeId::new("2a05:d018::40", 3001)), "[2a05:d018:0::40]:3001".parse());
+        for invalid in ["relay.adakailabs.com", ":3001", "relay:0", "relay:port", "2a05:d018::40:3001"] {
+            assert!(invalid.parse::<NodeId>().is_err(), "{}", invalid);
+        }
+
+        let mut set = HashSet::new();
+        set.insert(node("relay.adakailabs.com", 3001));
+        set.insert(node("RELAY.adakailabs.com", 3001));
+        assert_eq!(1, set.len());
+    }
+
+    #[test]
+    fn resolve_and_dedup_nodes() {
+        let mut nodes = vec![
+            node("relay.adakailabs.com", 3001),
+            node("54.220.20.40", 3001),
+            node("2a05:d018::40", 3001),
+            node("54.220.20.40", 3002),
+            node("other.adakailabs.com", 3001),
+            node("unknown.adakailabs.com", 3001),
+            node("Relay.adakailabs.com", 3001),
+        ];
+
+        assert_eq!(1, resolve_all(&mut nodes, &fake_resolver()));
+        assert_eq!(2, nodes[0].resolved().len());
+        assert_eq!(vec!["54.220.20.40".parse::<IpAddr>().unwrap()], nodes[1].resolved());
+        assert!(nodes[5].resolved().is_empty());
+        assert_eq!(3, nodes[0].endpoints().len() + nodes[2].endpoints().len() + nodes[5].endpoints().len());
+
+        let kept: Vec<String> = dedup(nodes).iter().map(|n| n.id().to_string()).collect();
+        assert_eq!(
+            vec![
+                "relay.adakailabs.com:3001",
+                "54.220.20.40:3002",
+                "other.adakailabs.com:3001",
+                "unknown.adakailabs.com:3001"
+            ],
+            kept
+        );
+    }
+
+    #[test]
+    fn node_builder() {
+        let node = NodeBuilder::new("[2a05:d018::40]", 3001).continent("EU").country("ireland").build().unwrap();
+        assert_eq!("2a05:d018::40", node.addr());
+        assert_eq!(1, node.valency());
+        assert_eq!("Europe", node.continent());
+        assert_eq!("IE", node.state());
+
+        for addr in ["10.0.0.1", "relay-1.example.com", "relay.example.com.", "localhost"] {
+            assert!(NodeBuilder::new(addr, 3001).build().is_ok(), "{}", addr);
+        }
+        for addr in ["-relay.example.com", "relay..example.com", "relay_1.example.com", "[10.0.0.1]", "10.0.0.1:3001", "256.1.1.1", "999.999.999.999", &"a".repeat(64)] {
+            assert_eq!(Err(vec![ValidationError::InvalidAddress(addr.to_string())]), NodeBuilder::new(addr, 3001).build(), "{}", addr);
+        }
+        assert_eq!(Err(vec![ValidationError::EmptyAddress]), NodeBuilder::new(" ", 3001).build());
+        assert_eq!(Err(vec![ValidationError::InvalidValency(0)]), NodeBuilder::new("10.0.0.1", 3001).valency(0).build());
+        assert!(NodeBuilder::new("10.0.0.1", 3001).valency(MAX_VALENCY).build().is_ok());
+
+        // every error is reported
+        let errors = NodeBuilder::new("relay!", 0)
+            .valency(MAX_VALENCY + 1)
+            .continent("Atlantis")
+            .country("Narnia")
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            vec![
+                ValidationError::InvalidAddress("relay!".to_string()),
+                ValidationError::InvalidPort(0),
+                ValidationError::InvalidValency(MAX_VALENCY + 1),
+                ValidationError::UnknownContinent("Atlantis".to_string()),
+                ValidationError::UnknownCountry("Narnia".to_string()),
+            ],
+            errors
+        );
+    }
+
+    #[test]
+    fn validate_node_list() {
+        let mut nodes: Vec<Node> = vec![
+            serde_json::from_str(JSON_NODE_TEST).unwrap(),
+            serde_json::from_str(r#"{"addr": "relay.example.com", "port": 0, "continent": "Europe"}"#).unwrap(),
+            serde_json::from_str(r#"{"addr": "10.0.0.2", "port": 3001, "continent": "north america", "state": "usa"}"#).unwrap(),
+        ];
+        // a missing valency is the default one, as for Node::default
+        assert_eq!((1, 1), (Node::default().valency(), nodes[1].valency()));
+        let errors = validate_nodes(&nodes).unwrap_err();
+        assert_eq!(1, errors.0.len());
+        assert_eq!(1, errors.0[0].position);
+        assert_eq!(vec![ValidationError::InvalidPort(0)], errors.0[0].errors);
+        assert_eq!("1 invalid nodes: node #1 'relay.example.com:0': invalid port 0", errors.to_string());
+
+        nodes.remove(1);
+        let valid = validate_nodes(&nodes).unwrap();
+        assert_eq!(("North America", "US", 1), (valid[1].continent(), valid[1].state(), valid[1].valency()));
+        assert_eq!(nodes[0].addr(), valid[0].addr());
+
+        // a missing valency is 1, a valency of 0 is written and rejected
+        nodes.push(serde_json::from_str(r#"{"addr": "10.0.0.3", "port": 3001, "valency": 0}"#).unwrap());
+        let errors = validate_nodes(&nodes).unwrap_err();
+        assert_eq!(vec![ValidationError::InvalidValency(0)], errors.0[0].errors);
+    }
 }
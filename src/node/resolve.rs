@@ -0,0 +1,87 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+
+use crate::node::{Node, NodeId};
+
+/// Resolver maps a host name to the addresses of its A and AAAA records. It is a trait so that
+/// the lookups can be served by a fixed table (e.g. in tests) instead of the system resolver.
+pub trait Resolver {
+    /// lookup: returns the IPv4 and IPv6 addresses of the host name
+    fn lookup(&self, host: &str) -> io::Result<Vec<IpAddr>>;
+}
+
+/// SystemResolver resolves host names with the resolver of the operating system
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn lookup(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        let mut addrs: Vec<IpAddr> = Vec::new();
+        for addr in (host, 0).to_socket_addrs()? {
+            if !addrs.contains(&addr.ip()) {
+                addrs.push(addr.ip());
+            }
+        }
+        Ok(addrs)
+    }
+}
+
+impl Resolver for HashMap<String, Vec<IpAddr>> {
+    fn lookup(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        self.get(&host.to_ascii_lowercase())
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no record for {}", host)))
+    }
+}
+
+/// resolve_all: resolves the address of every node and records the result on the node (see
+/// Node::resolve). Nodes whose name does not resolve are kept, without resolved addresses.
+/// # Arguments:
+/// * `nodes:` the nodes to resolve
+/// * `resolver:` the resolver used for the host names (IP literals are not looked up)
+///
+/// Returns the number of nodes that could not be resolved.
+pub fn resolve_all(nodes: &mut [Node], resolver: &dyn Resolver) -> usize {
+    let mut cache: HashMap<String, Vec<IpAddr>> = HashMap::new();
+    let mut failures = 0;
+    for node in nodes.iter_mut() {
+        let host = node.id().addr;
+        if let Some(addrs) = cache.get(&host) {
+            node.set_resolved(addrs.clone());
+            continue;
+        }
+        match node.resolve(resolver) {
+            Ok(addrs) => {
+                cache.insert(host, addrs.to_vec());
+            }
+            Err(e) => {
+                debug!("resolve error: {}: {}", node.addr(), e);
+                failures += 1;
+            }
+        }
+    }
+    failures
+}
+
+/// dedup: removes the duplicate nodes, keeping the first occurrence. Two nodes are duplicates
+/// when they have the same identity (see Node::id) or when they share a resolved endpoint, such
+/// as a relay listed under its host name and under its IP. Resolve the nodes first (see
+/// resolve_all) for the latter to be found.
+pub fn dedup(nodes: Vec<Node>) -> Vec<Node> {
+    let mut ids: HashSet<NodeId> = HashSet::new();
+    let mut endpoints: HashSet<SocketAddr> = HashSet::new();
+    let mut kept = Vec::with_capacity(nodes.len());
+
+    for node in nodes {
+        let node_endpoints = node.endpoints();
+        if ids.contains(&node.id()) || node_endpoints.iter().any(|e| endpoints.contains(e)) {
+            debug!("duplicate node: {}", node.id());
+            continue;
+        }
+        ids.insert(node.id());
+        endpoints.extend(node_endpoints);
+        kept.push(node);
+    }
+    kept
+}
@@ -0,0 +1,230 @@
+use cardano_ouroboros_network::{Agency, Protocol};
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value;
+
+use crate::node::Node;
+
+const MSG_REQUEST_NEXT: i128 = 0;
+const MSG_AWAIT_REPLY: i128 = 1;
+const MSG_ROLL_FORWARD: i128 = 2;
+const MSG_ROLL_BACKWARD: i128 = 3;
+const MSG_FIND_INTERSECT: i128 = 4;
+const MSG_INTERSECT_FOUND: i128 = 5;
+const MSG_INTERSECT_NOT_FOUND: i128 = 6;
+const MSG_DONE: i128 = 7;
+
+/// Tip holds the tip of the chain as seen by a node
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tip {
+    /// slot is the slot of the tip block, 0 at origin
+    pub slot: u64,
+
+    /// block_no is the block number of the tip block, 0 at origin
+    pub block_no: u64,
+
+    /// hash is the hex encoded hash of the tip block, empty at origin
+    pub hash: String,
+}
+
+impl Tip {
+    /// from_cbor: decodes a chain-sync tip, `[point, blockNo]` where point is `[]` (origin) or
+    /// `[slot, hash]`
+    pub(crate) fn from_cbor(value: &Value) -> Option<Tip> {
+        let items = match value {
+            Value::Array(items) if items.len() == 2 => items,
+            _ => return None,
+        };
+
+        let block_no = match &items[1] {
+            Value::Integer(b) if *b >= 0 => *b as u64,
+            _ => return None,
+        };
+
+        match &items[0] {
+            Value::Array(point) if point.is_empty() => Some(Tip::default()),
+            Value::Array(point) => match (point.first(), point.get(1)) {
+                (Some(Value::Integer(slot)), Some(Value::Bytes(hash))) if *slot >= 0 => Some(Tip {
+                    slot: *slot as u64,
+                    block_no,
+                    hash: hash.iter().map(|b| format!("{:02x}", b)).collect(),
+                }),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// to_cbor: encodes the tip as sent in chain-sync messages
+    #[cfg(test)]
+    pub(crate) fn to_cbor(&self) -> Value {
+        if self.hash.is_empty() {
+            return Value::Array(vec![Value::Array(vec![]), Value::Integer(0)]);
+        }
+        let hash = (0..self.hash.len())
+            .step_by(2)
+            .filter_map(|i| u8::from_str_radix(&self.hash[i..i + 2], 16).ok())
+            .collect();
+        Value::Array(vec![
+            Value::Array(vec![Value::Integer(self.slot as i128), Value::Bytes(hash)]),
+            Value::Integer(self.block_no as i128),
+        ])
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum State {
+    FindIntersect,
+    Intersect,
+    RequestNext,
+    Next,
+    SendDone,
+    Done,
+}
+
+/// ChainSync is a chain-sync client that only learns the tip of the peer: it looks for an
+/// intersection with no point, which the peer answers with its tip, then requests the next
+/// header once, whose answer carries a fresher tip, and leaves.
+pub(crate) struct ChainSync {
+    protocol_id: u16,
+    state: State,
+    tip: Option<Tip>,
+    error: Option<String>,
+}
+
+impl ChainSync {
+    /// new: returns a chain-sync client for the mini-protocol id (node-to-node or node-to-client)
+    pub fn new(protocol_id: u16) -> ChainSync {
+        ChainSync {
+            protocol_id,
+            state: State::FindIntersect,
+            tip: None,
+            error: None,
+        }
+    }
+
+    /// tip: returns the last tip announced by the peer, or why it could not be learnt
+    pub fn tip(&self) -> Result<Tip, String> {
+        match (&self.tip, &self.error) {
+            (Some(tip), _) => Ok(tip.clone()),
+            (None, Some(e)) => Err(e.clone()),
+            (None, None) => Err("chain-sync not completed".to_string()),
+        }
+    }
+
+    fn message(items: Vec<Value>) -> Vec<u8> {
+        serde_cbor::to_vec(&Value::Array(items)).unwrap()
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Option<i128> {
+        let items = match serde_cbor::from_slice(data) {
+            Ok(Value::Array(items)) => items,
+            _ => {
+                self.error = Some("invalid chain-sync message".to_string());
+                return None;
+            }
+        };
+        let tag = match items.first() {
+            Some(Value::Integer(tag)) => *tag,
+            _ => return None,
+        };
+
+        let tip = match tag {
+            MSG_INTERSECT_NOT_FOUND => items.get(1),
+            MSG_INTERSECT_FOUND | MSG_ROLL_FORWARD | MSG_ROLL_BACKWARD => items.get(2),
+            _ => None,
+        };
+        if let Some(tip) = tip.and_then(Tip::from_cbor) {
+            self.tip = Some(tip);
+        }
+        Some(tag)
+    }
+}
+
+impl Protocol for ChainSync {
+    fn protocol_id(&self) -> u16 {
+        self.protocol_id
+    }
+
+    fn result(&self) -> Result<String, String> {
+        self.tip().map(|tip| format!("tip: slot {}, block {}", tip.slot, tip.block_no))
+    }
+
+    fn role(&self) -> Agency {
+        Agency::Client
+    }
+
+    fn agency(&self) -> Agency {
+        match self.state {
+            State::FindIntersect | State::RequestNext | State::SendDone => Agency::Client,
+            State::Intersect | State::Next => Agency::Server,
+            State::Done => Agency::None,
+        }
+    }
+
+    fn state(&self) -> String {
+        format!("{:?}", self.state)
+    }
+
+    fn send_data(&mut self) -> Option<Vec<u8>> {
+        match self.state {
+            State::FindIntersect => {
+                self.state = State::Intersect;
+                Some(ChainSync::message(vec![Value::Integer(MSG_FIND_INTERSECT), Value::Array(vec![])]))
+            }
+            State::RequestNext => {
+                self.state = State::Next;
+                Some(ChainSync::message(vec![Value::Integer(MSG_REQUEST_NEXT)]))
+            }
+            State::SendDone => {
+                self.state = State::Done;
+                Some(ChainSync::message(vec![Value::Integer(MSG_DONE)]))
+            }
+            _ => None,
+        }
+    }
+
+    fn receive_data(&mut self, data: Vec<u8>) {
+        let tag = self.decode(&data);
+        self.state = match (&self.state, tag) {
+            (State::Intersect, Some(MSG_INTERSECT_FOUND | MSG_INTERSECT_NOT_FOUND)) => State::RequestNext,
+            (State::Next, Some(MSG_ROLL_FORWARD | MSG_ROLL_BACKWARD)) => State::SendDone,
+            // the peer is at the tip and will answer once a new block arrives: the tip is known
+            (State::Next, Some(MSG_AWAIT_REPLY)) => State::Done,
+            _ => {
+                if self.error.is_none() {
+                    self.error = Some(format!("unexpected chain-sync message in state {:?}", self.state));
+                }
+                State::Done
+            }
+        };
+    }
+}
+
+/// best_tip: returns the most advanced tip (highest slot) among the nodes, None if no node has a
+/// tip
+pub fn best_tip(nodes: &[Node]) -> Option<Tip> {
+    nodes.iter().filter_map(|n| n.tip()).max_by_key(|t| t.slot).cloned()
+}
+
+/// tip_lags: returns, for every node, the number of slots its tip is behind the best observed
+/// tip, None for the nodes without a tip
+pub fn tip_lags(nodes: &[Node]) -> Vec<Option<u64>> {
+    let best = best_tip(nodes).map(|t| t.slot).unwrap_or_default();
+    nodes.iter().map(|n| n.tip().map(|t| best.saturating_sub(t.slot))).collect()
+}
+
+/// lagging: returns the positions of the nodes whose tip is more than `max_lag` slots behind the
+/// best observed tip. Nodes without a tip are not flagged.
+/// # Arguments:
+/// * `nodes:` the nodes pinged with PingOptions::query_tip
+/// * `max_lag:` the number of slots a node may be behind
+pub fn lagging(nodes: &[Node], max_lag: u64) -> Vec<usize> {
+    tip_lags(nodes)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, lag)| match lag {
+            Some(lag) if lag > max_lag => Some(i),
+            _ => None,
+        })
+        .collect()
+}
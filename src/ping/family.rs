@@ -0,0 +1,224 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+
+use crate::node::Node;
+use crate::ping::{PingError, PingOutcome};
+
+/// HAPPY_EYEBALLS_DELAY is the head start given to a connection attempt before the next address
+/// is tried (RFC 8305 connection attempt delay)
+pub const HAPPY_EYEBALLS_DELAY: Duration = Duration::from_millis(250);
+
+/// AddressFamily selects the IP family used to reach a node whose name resolves to IPv4 and IPv6
+/// addresses
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AddressFamily {
+    /// Any tries the addresses one after the other, in the order given by the resolver
+    #[default]
+    Any,
+
+    /// V4 only connects over IPv4
+    V4,
+
+    /// V6 only connects over IPv6
+    V6,
+
+    /// HappyEyeballs races the addresses, IPv6 first, starting a new attempt every
+    /// HAPPY_EYEBALLS_DELAY until one connects (RFC 8305)
+    HappyEyeballs,
+
+    /// Both measures IPv4 and IPv6 separately, the node being online if either answers. The
+    /// latencies of the node are the ones of the fastest family.
+    Both,
+}
+
+/// FamilyOutcome holds the result of pinging a node over one IP family
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FamilyOutcome {
+    /// addr is the address connected to, or the first address tried when none answered
+    pub addr: Option<SocketAddr>,
+
+    /// con_latency is the time taken to establish the TCP connection, None if it failed
+    pub con_latency: Option<Duration>,
+
+    /// total_latency is the time taken by the connection plus the handshake, None if the
+    /// handshake did not complete
+    pub total_latency: Option<Duration>,
+
+    /// error is the reason of the failure, None if the node answered
+    pub error: Option<PingError>,
+}
+
+impl FamilyOutcome {
+    /// online: returns true if the node answered over the family
+    pub fn online(&self) -> bool {
+        self.error.is_none() && self.total_latency.is_some()
+    }
+
+    pub(crate) fn from_outcome(outcome: &PingOutcome, tried: Option<SocketAddr>) -> FamilyOutcome {
+        FamilyOutcome {
+            addr: outcome.addr.or(tried),
+            con_latency: outcome.con_latency,
+            total_latency: outcome.total_latency,
+            error: outcome.error.clone(),
+        }
+    }
+}
+
+/// PeerAddr returns the address of the peer a stream is connected to, if it has one
+pub(crate) trait PeerAddr {
+    fn peer(&self) -> Option<SocketAddr>;
+}
+
+impl PeerAddr for TcpStream {
+    fn peer(&self) -> Option<SocketAddr> {
+        self.peer_addr().ok()
+    }
+}
+
+#[cfg(unix)]
+impl PeerAddr for tokio::net::UnixStream {
+    fn peer(&self) -> Option<SocketAddr> {
+        None
+    }
+}
+
+async fn connect_addr(addr: SocketAddr) -> Result<TcpStream, PingError> {
+    match TcpStream::connect(addr).await {
+        Ok(stream) => {
+            let _ = stream.set_nodelay(true);
+            Ok(stream)
+        }
+        Err(e) => Err(PingError::Connect(format!("{}: {}", addr, e))),
+    }
+}
+
+/// interleave: orders the addresses IPv6 first, alternating the families (RFC 8305)
+fn interleave(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<SocketAddr>, Vec<SocketAddr>) = addrs.iter().partition(|a| a.is_ipv6());
+    let mut ordered = Vec::with_capacity(addrs.len());
+    for i in 0..v6.len().max(v4.len()) {
+        ordered.extend(v6.get(i));
+        ordered.extend(v4.get(i));
+    }
+    ordered
+}
+
+async fn connect_happy_eyeballs(addrs: &[SocketAddr]) -> Result<TcpStream, PingError> {
+    let mut pending = interleave(addrs).into_iter().peekable();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = PingError::Connect("no address to connect to".to_string());
+
+    attempts.extend(pending.next().map(connect_addr));
+    while !attempts.is_empty() {
+        let delay = tokio::time::sleep(HAPPY_EYEBALLS_DELAY);
+        tokio::select! {
+            result = attempts.next() => match result {
+                Some(Ok(stream)) => return Ok(stream),
+                Some(Err(e)) => {
+                    debug!("happy eyeballs: {}", e);
+                    last_error = e;
+                    // a failed attempt gives its turn to the next address right away
+                    attempts.extend(pending.next().map(connect_addr));
+                }
+                None => {}
+            },
+            _ = delay, if pending.peek().is_some() => attempts.extend(pending.next().map(connect_addr)),
+        }
+    }
+    Err(last_error)
+}
+
+/// connect_addrs: connects to one of the addresses of a node, as selected by the family
+pub(crate) async fn connect_addrs(addrs: &[SocketAddr], family: AddressFamily) -> Result<TcpStream, PingError> {
+    let addrs: Vec<SocketAddr> = match family {
+        AddressFamily::V4 => addrs.iter().filter(|a| a.is_ipv4()).copied().collect(),
+        AddressFamily::V6 => addrs.iter().filter(|a| a.is_ipv6()).copied().collect(),
+        _ => addrs.to_vec(),
+    };
+    if addrs.is_empty() {
+        return Err(PingError::Resolve(format!("no {} address", family_name(family))));
+    }
+
+    if family == AddressFamily::HappyEyeballs {
+        return connect_happy_eyeballs(&addrs).await;
+    }
+
+    let mut last_error = PingError::Connect("no address to connect to".to_string());
+    for addr in addrs {
+        match connect_addr(addr).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+fn family_name(family: AddressFamily) -> &'static str {
+    match family {
+        AddressFamily::V4 => "IPv4",
+        AddressFamily::V6 => "IPv6",
+        _ => "IP",
+    }
+}
+
+/// record_family: records the outcome as the result of the family of the address connected to,
+/// or of the family the ping was restricted to. A family without any address is not recorded.
+pub(crate) fn record_family(outcome: &mut PingOutcome, family: AddressFamily) {
+    let v6 = match (family, outcome.addr) {
+        (_, Some(addr)) => addr.is_ipv6(),
+        (AddressFamily::V4, None) => false,
+        (AddressFamily::V6, None) => true,
+        _ => return,
+    };
+    if matches!(outcome.error, Some(PingError::Resolve(_))) {
+        return;
+    }
+
+    let result = Some(FamilyOutcome::from_outcome(outcome, None));
+    if v6 {
+        outcome.ipv6 = result;
+    } else {
+        outcome.ipv4 = result;
+    }
+}
+
+/// combine: merges the outcomes of the pings over IPv4 and IPv6 of the same node: the node takes
+/// the result of the fastest family that answered, or the IPv4 failure when neither answered
+pub(crate) fn combine(v4: Option<(PingOutcome, SocketAddr)>, v6: Option<(PingOutcome, SocketAddr)>) -> PingOutcome {
+    let ipv4 = v4.as_ref().map(|(o, tried)| FamilyOutcome::from_outcome(o, Some(*tried)));
+    let ipv6 = v6.as_ref().map(|(o, tried)| FamilyOutcome::from_outcome(o, Some(*tried)));
+
+    let mut outcomes: Vec<PingOutcome> = v4.into_iter().chain(v6).map(|(o, _)| o).collect();
+    let best = outcomes
+        .iter()
+        .enumerate()
+        .filter(|(_, o)| o.online())
+        .min_by_key(|(_, o)| o.total_latency)
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+
+    let mut outcome = if outcomes.is_empty() {
+        PingOutcome::failed(None, PingError::Resolve("no address found".to_string()))
+    } else {
+        outcomes.swap_remove(best)
+    };
+    outcome.ipv4 = ipv4;
+    outcome.ipv6 = ipv6;
+    outcome
+}
+
+/// broken_ipv6: returns the positions of the nodes that have an IPv6 address but did not answer
+/// on it, although they may answer over IPv4. Only the nodes pinged with AddressFamily::Both (or
+/// V6) have an IPv6 result.
+pub fn broken_ipv6(nodes: &[Node]) -> Vec<usize> {
+    nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, n)| n.ipv6().is_some_and(|r| !r.online()))
+        .map(|(i, _)| i)
+        .collect()
+}
@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+
+use cardano_ouroboros_network::{Agency, Protocol};
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value;
+
+use crate::node::Node;
+use crate::ping::PingError;
+
+/// NODE_TO_NODE_VERSIONS are the node-to-node protocol versions proposed in the handshake
+const NODE_TO_NODE_VERSIONS: std::ops::RangeInclusive<u64> = 7..=14;
+
+/// NODE_TO_CLIENT_VERSIONS are the node-to-client protocol versions proposed in the handshake,
+/// without NODE_TO_CLIENT_BIT
+const NODE_TO_CLIENT_VERSIONS: std::ops::RangeInclusive<u64> = 9..=16;
+
+/// NODE_TO_CLIENT_BIT is set in the node-to-client version numbers
+pub(crate) const NODE_TO_CLIENT_BIT: u64 = 0x8000;
+
+/// FIRST_CLIENT_VERSION_WITH_QUERY is the first node-to-client version whose version data carries
+/// the query flag
+const FIRST_CLIENT_VERSION_WITH_QUERY: u64 = 15;
+
+/// FIRST_VERSION_WITH_PEER_SHARING is the first node-to-node version whose version data carries
+/// the peer sharing and query flags
+const FIRST_VERSION_WITH_PEER_SHARING: u64 = 11;
+
+const MSG_PROPOSE_VERSIONS: i128 = 0;
+const MSG_ACCEPT_VERSION: i128 = 1;
+const MSG_REFUSE: i128 = 2;
+
+const REFUSE_VERSION_MISMATCH: i128 = 0;
+const REFUSE_DECODE_ERROR: i128 = 1;
+const REFUSE_REFUSED: i128 = 2;
+
+/// Suite selects the mini-protocol suite spoken with the peer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Suite {
+    /// NodeToNode is spoken between nodes, over TCP
+    NodeToNode,
+
+    /// NodeToClient is spoken between a node and its local clients, over its Unix socket
+    NodeToClient,
+}
+
+impl Suite {
+    /// chain_sync_id: returns the mini-protocol id of chain-sync in the suite
+    pub fn chain_sync_id(&self) -> u16 {
+        match self {
+            Suite::NodeToNode => 2,
+            Suite::NodeToClient => 5,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum State {
+    Propose,
+    Confirm,
+    Done,
+}
+
+/// Accepted holds the version accepted by the peer and the version data it sent along
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Accepted {
+    pub version: u64,
+    pub version_data: Value,
+}
+
+/// NegotiatedVersion holds the version accepted by a peer and the version data it
+/// answered with
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NegotiatedVersion {
+    /// version is the accepted version (for node-to-client, without the 0x8000 bit)
+    pub version: u64,
+
+    /// network_magic is the magic of the network the peer runs
+    pub network_magic: u32,
+
+    /// initiator_only is true when the peer runs in initiator only mode, false for duplex (always
+    /// false for node-to-client)
+    pub initiator_only: bool,
+
+    /// peer_sharing is true when the peer takes part in peer sharing, None before version 11
+    pub peer_sharing: Option<bool>,
+
+    /// query is true when the peer answered a version query, None before version 11 (15 for
+    /// node-to-client)
+    pub query: Option<bool>,
+
+    /// node_to_client is true when the version is a node-to-client one (see ping_local)
+    pub node_to_client: bool,
+}
+
+impl Accepted {
+    /// negotiated: decodes the version data, `[magic, initiatorOnly]` up to version 10 and
+    /// `[magic, initiatorOnly, peerSharing, query]` after for node-to-node, `magic` up to version
+    /// 14 and `[magic, query]` after for node-to-client
+    pub fn negotiated(&self) -> NegotiatedVersion {
+        let data: &[Value] = match &self.version_data {
+            Value::Array(data) => data,
+            other => std::slice::from_ref(other),
+        };
+
+        let int = |i: usize| match data.get(i) {
+            Some(Value::Integer(v)) => Some(*v),
+            _ => None,
+        };
+        let boolean = |i: usize| match data.get(i) {
+            Some(Value::Bool(b)) => Some(*b),
+            _ => None,
+        };
+
+        if self.version & NODE_TO_CLIENT_BIT != 0 {
+            return NegotiatedVersion {
+                version: self.version & !NODE_TO_CLIENT_BIT,
+                network_magic: int(0).unwrap_or_default() as u32,
+                query: boolean(1),
+                node_to_client: true,
+                ..Default::default()
+            };
+        }
+
+        NegotiatedVersion {
+            version: self.version,
+            network_magic: int(0).unwrap_or_default() as u32,
+            initiator_only: boolean(1).unwrap_or_default(),
+            peer_sharing: int(2).map(|p| p != 0),
+            query: boolean(3),
+            node_to_client: false,
+        }
+    }
+}
+
+/// VersionHistogram summarizes the node-to-node versions found over pinged nodes
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionHistogram {
+    /// accepted counts the nodes by the version they accepted
+    pub accepted: BTreeMap<u64, usize>,
+
+    /// offered counts, over the nodes that refused every proposed version, the versions they
+    /// offered instead
+    pub offered: BTreeMap<u64, usize>,
+
+    /// mismatched is the number of nodes that refused every proposed version
+    pub mismatched: usize,
+
+    /// unknown is the number of nodes whose version is not known (offline, refused, not pinged)
+    pub unknown: usize,
+}
+
+/// version_histogram: counts the node-to-node versions of the pinged nodes
+pub fn version_histogram(nodes: &[Node]) -> VersionHistogram {
+    let mut histogram = VersionHistogram::default();
+    for node in nodes {
+        if let Some(version) = node.version() {
+            *histogram.accepted.entry(version.version).or_insert(0) += 1;
+        } else if let Some(offered) = node.offered_versions() {
+            histogram.mismatched += 1;
+            for v in offered {
+                *histogram.offered.entry(v).or_insert(0) += 1;
+            }
+        } else {
+            histogram.unknown += 1;
+        }
+    }
+    histogram
+}
+
+/// Handshake is the client side of the handshake mini-protocol, node-to-node or node-to-client.
+/// Unlike the HandshakeProtocol of cardano_ouroboros_network it keeps the outcome typed, so that
+/// a refusal can be told apart from a version mismatch.
+pub(crate) struct Handshake {
+    network_magic: u32,
+    suite: Suite,
+    state: State,
+    outcome: Option<Result<Accepted, PingError>>,
+}
+
+impl Handshake {
+    /// with_suite: returns a handshake proposing every supported version of the suite for the
+    /// network magic
+    pub fn with_suite(network_magic: u32, suite: Suite) -> Handshake {
+        Handshake {
+            network_magic,
+            suite,
+            state: State::Propose,
+            outcome: None,
+        }
+    }
+
+    /// outcome: returns the accepted version, or the reason the handshake failed
+    pub fn outcome(&self) -> Result<Accepted, PingError> {
+        self.outcome
+            .clone()
+            .unwrap_or_else(|| Err(PingError::Io("handshake not completed".to_string())))
+    }
+
+    fn version_data(&self, version: u64) -> Value {
+        let magic = Value::Integer(self.network_magic as i128);
+        if self.suite == Suite::NodeToClient {
+            return if version < FIRST_CLIENT_VERSION_WITH_QUERY {
+                magic
+            } else {
+                Value::Array(vec![magic, Value::Bool(false)])
+            };
+        }
+
+        // initiator only: a ping does not answer the mini-protocols of the peer
+        let initiator_only = Value::Bool(true);
+        if version < FIRST_VERSION_WITH_PEER_SHARING {
+            Value::Array(vec![magic, initiator_only])
+        } else {
+            Value::Array(vec![magic, initiator_only, Value::Integer(0), Value::Bool(false)])
+        }
+    }
+
+    fn msg_propose_versions(&self) -> Vec<u8> {
+        let versions: BTreeMap<Value, Value> = match self.suite {
+            Suite::NodeToNode => NODE_TO_NODE_VERSIONS
+                .map(|v| (Value::Integer(v as i128), self.version_data(v)))
+                .collect(),
+            Suite::NodeToClient => NODE_TO_CLIENT_VERSIONS
+                .map(|v| (Value::Integer((v | NODE_TO_CLIENT_BIT) as i128), self.version_data(v)))
+                .collect(),
+        };
+
+        let message = Value::Array(vec![Value::Integer(MSG_PROPOSE_VERSIONS), Value::Map(versions)]);
+        serde_cbor::to_vec(&message).unwrap()
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Accepted, PingError> {
+        let invalid = |what: &str| PingError::Io(format!("invalid handshake reply: {}", what));
+
+        let message: Value = serde_cbor::from_slice(data).map_err(|e| invalid(&e.to_string()))?;
+        let items = match message {
+            Value::Array(items) => items,
+            _ => return Err(invalid("not an array")),
+        };
+
+        match items.first() {
+            Some(Value::Integer(MSG_ACCEPT_VERSION)) => {
+                let version = match items.get(1) {
+                    Some(Value::Integer(v)) if *v >= 0 => *v as u64,
+                    _ => return Err(invalid("missing accepted version")),
+                };
+                let version_data = items.get(2).cloned().ok_or_else(|| invalid("missing version data"))?;
+
+                let magic = match &version_data {
+                    Value::Array(data) => data.first(),
+                    other => Some(other),
+                };
+                match magic {
+                    Some(Value::Integer(m)) if *m == self.network_magic as i128 => Ok(Accepted { version, version_data }),
+                    Some(Value::Integer(m)) => Err(PingError::HandshakeRefused(format!(
+                        "network magic mismatch: expected {}, peer runs {}",
+                        self.network_magic, m
+                    ))),
+                    _ => Err(invalid("missing network magic")),
+                }
+            }
+            Some(Value::Integer(MSG_REFUSE)) => Err(Handshake::decode_refuse(items.get(1))),
+            _ => Err(invalid("unexpected message")),
+        }
+    }
+
+    fn decode_refuse(reason: Option<&Value>) -> PingError {
+        let reason = match reason {
+            Some(Value::Array(reason)) => reason,
+            _ => return PingError::HandshakeRefused("unknown reason".to_string()),
+        };
+
+        let text = reason.iter().find_map(|v| match v {
+            Value::Text(t) => Some(t.clone()),
+            _ => None,
+        });
+
+        match reason.first() {
+            Some(Value::Integer(REFUSE_VERSION_MISMATCH)) => {
+                let offered = match reason.get(1) {
+                    Some(Value::Array(versions)) => versions
+                        .iter()
+                        .filter_map(|v| match v {
+                            Value::Integer(v) if *v >= 0 => Some(*v as u64),
+                            _ => None,
+                        })
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                PingError::VersionMismatch(offered)
+            }
+            Some(Value::Integer(REFUSE_DECODE_ERROR)) => {
+                PingError::HandshakeRefused(format!("decode error: {}", text.unwrap_or_default()))
+            }
+            Some(Value::Integer(REFUSE_REFUSED)) => PingError::HandshakeRefused(text.unwrap_or_default()),
+            _ => PingError::HandshakeRefused("unknown reason".to_string()),
+        }
+    }
+}
+
+impl Protocol for Handshake {
+    fn protocol_id(&self) -> u16 {
+        0x0000
+    }
+
+    fn result(&self) -> Result<String, String> {
+        match self.outcome() {
+            Ok(accepted) => Ok(format!("accepted version {}", accepted.version)),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn role(&self) -> Agency {
+        Agency::Client
+    }
+
+    fn agency(&self) -> Agency {
+        match self.state {
+            State::Propose => Agency::Client,
+            State::Confirm => Agency::Server,
+            State::Done => Agency::None,
+        }
+    }
+
+    fn state(&self) -> String {
+        format!("{:?}", self.state)
+    }
+
+    fn send_data(&mut self) -> Option<Vec<u8>> {
+        match self.state {
+            State::Propose => {
+                self.state = State::Confirm;
+                Some(self.msg_propose_versions())
+            }
+            _ => None,
+        }
+    }
+
+    fn receive_data(&mut self, data: Vec<u8>) {
+        if self.state == State::Confirm {
+            self.outcome = Some(self.decode(&data));
+            self.state = State::Done;
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value;
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+
+use crate::node::Node;
+use crate::ping::handshake::Suite;
+use crate::ping::{connect, mux, run_handshake, CancelHandle, LatencyStats, PingError, PingOptions};
+
+/// KEEP_ALIVE_ID is the mini-protocol id of keep-alive between nodes
+const KEEP_ALIVE_ID: u16 = 8;
+
+const MSG_KEEP_ALIVE: i128 = 0;
+const MSG_KEEP_ALIVE_RESPONSE: i128 = 1;
+const MSG_DONE: i128 = 2;
+
+/// KeepAliveOptions holds the settings of a keep-alive session
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeepAliveOptions {
+    /// interval is the time between two keep-alive messages (default 1 s)
+    pub interval: Duration,
+
+    /// window is the number of round trips kept, the oldest ones are discarded (default 60)
+    pub window: usize,
+
+    /// response_timeout is the time the node has to answer a keep-alive message before the
+    /// connection is considered dropped (default 5 s)
+    pub response_timeout: Duration,
+
+    /// ping holds the connect and handshake timeouts used to open the connection
+    pub ping: PingOptions,
+}
+
+impl Default for KeepAliveOptions {
+    fn default() -> Self {
+        KeepAliveOptions {
+            interval: Duration::from_secs(1),
+            window: 60,
+            response_timeout: Duration::from_secs(5),
+            ping: PingOptions::default(),
+        }
+    }
+}
+
+/// RttSample holds the round trip of one keep-alive message
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RttSample {
+    /// cookie is the cookie of the keep-alive message
+    pub cookie: u16,
+
+    /// rtt is the time between the message and its response
+    pub rtt: Duration,
+}
+
+/// RttWindow holds the last keep-alive round trips of a node, oldest first
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RttWindow {
+    capacity: usize,
+    samples: VecDeque<RttSample>,
+}
+
+impl RttWindow {
+    /// new: returns an empty window keeping up to `capacity` samples (at least 1)
+    pub fn new(capacity: usize) -> RttWindow {
+        RttWindow {
+            capacity: capacity.max(1),
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// push: adds a sample, discarding the oldest one when the window is full
+    pub fn push(&mut self, sample: RttSample) {
+        while self.samples.len() >= self.capacity.max(1) {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// samples: returns the samples, oldest first
+    pub fn samples(&self) -> &VecDeque<RttSample> {
+        &self.samples
+    }
+
+    /// last: returns the most recent sample
+    pub fn last(&self) -> Option<&RttSample> {
+        self.samples.back()
+    }
+
+    /// len: returns the number of samples in the window
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// is_empty: returns true when no round trip was measured
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// stats: returns the statistics of the round trips in the window
+    pub fn stats(&self) -> LatencyStats {
+        let rtts: Vec<Duration> = self.samples.iter().map(|s| s.rtt).collect();
+        LatencyStats::from_samples(&rtts, 0)
+    }
+}
+
+/// SessionState holds the state of the connection of a keep-alive session
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SessionState {
+    /// Connecting: the connection or the handshake is in progress
+    Connecting,
+
+    /// Connected: keep-alive messages are being exchanged
+    Connected,
+
+    /// Dropped: the connection failed or was lost, holds the reason
+    Dropped(PingError),
+
+    /// Closed: the session was closed with KeepAliveSession::close
+    Closed,
+}
+
+/// KeepAliveSession keeps a connection to a node open and measures the round trip of keep-alive
+/// messages sent at a regular interval. The session runs in its own task until it is closed,
+/// dropped, or the connection is lost.
+pub struct KeepAliveSession {
+    window: Arc<Mutex<RttWindow>>,
+    state: watch::Receiver<SessionState>,
+    cancel: CancelHandle,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl KeepAliveSession {
+    /// start: opens a session to the node, using the magic of its network. It must be called from
+    /// within a tokio runtime.
+    /// # Arguments:
+    /// * `node:` the node to measure
+    /// * `options:` the interval, window size and timeouts of the session
+    pub fn start(node: &Node, options: KeepAliveOptions) -> KeepAliveSession {
+        let window = Arc::new(Mutex::new(RttWindow::new(options.window)));
+        let (state_tx, state) = watch::channel(SessionState::Connecting);
+        let cancel = CancelHandle::new();
+
+        let host = node.addr().to_string();
+        let port = node.port();
+        let network_magic = node.network_type().magic();
+        let task_window = window.clone();
+        let task_cancel = cancel.clone();
+
+        let task = tokio::spawn(async move {
+            let result = run(&host, port, network_magic, options, &task_window, &state_tx, &task_cancel).await;
+            let state = match result {
+                Ok(()) => SessionState::Closed,
+                Err(e) => {
+                    debug!("keep-alive {}:{} dropped: {}", host, port, e);
+                    SessionState::Dropped(e)
+                }
+            };
+            state_tx.send_replace(state);
+        });
+
+        KeepAliveSession { window, state, cancel, task }
+    }
+
+    /// window: returns a copy of the round trips measured so far
+    pub fn window(&self) -> RttWindow {
+        self.window.lock().unwrap().clone()
+    }
+
+    /// state: returns the state of the connection
+    pub fn state(&self) -> SessionState {
+        self.state.borrow().clone()
+    }
+
+    /// ended: completes once the session is dropped or closed and returns its final state
+    pub async fn ended(&self) -> SessionState {
+        let mut state = self.state.clone();
+        let ended = state
+            .wait_for(|s| matches!(s, SessionState::Dropped(_) | SessionState::Closed))
+            .await
+            .map(|s| s.clone());
+        // the sender is only gone once the task ended, after publishing its final state
+        ended.unwrap_or_else(|_| self.state())
+    }
+
+    /// close: ends the session, politely telling the node
+    pub fn close(&self) {
+        self.cancel.cancel();
+    }
+
+    /// update: records the round trips and the connection state of the session on the node: the
+    /// node is online while connected, and holds the reason of the drop otherwise
+    pub fn update(&self, node: &mut Node) {
+        let state = self.state();
+        node.set_rtt(Some(self.window()));
+        node.set_online(state == SessionState::Connected);
+        node.set_online_error(match state {
+            SessionState::Dropped(e) => Some(e),
+            _ => None,
+        });
+    }
+}
+
+impl Drop for KeepAliveSession {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn message(tag: i128, cookie: Option<u16>) -> Vec<u8> {
+    let mut items = vec![Value::Integer(tag)];
+    if let Some(cookie) = cookie {
+        items.push(Value::Integer(cookie as i128));
+    }
+    serde_cbor::to_vec(&Value::Array(items)).unwrap()
+}
+
+fn response_cookie(payload: &[u8]) -> Result<u16, PingError> {
+    match serde_cbor::from_slice(payload) {
+        Ok(Value::Array(items)) => match (items.first(), items.get(1)) {
+            (Some(Value::Integer(MSG_KEEP_ALIVE_RESPONSE)), Some(Value::Integer(cookie))) => Ok(*cookie as u16),
+            _ => Err(PingError::Io("unexpected keep-alive message".to_string())),
+        },
+        _ => Err(PingError::Io("invalid keep-alive message".to_string())),
+    }
+}
+
+async fn run(
+    host: &str,
+    port: u16,
+    network_magic: u32,
+    options: KeepAliveOptions,
+    window: &Mutex<RttWindow>,
+    state: &watch::Sender<SessionState>,
+    cancel: &CancelHandle,
+) -> Result<(), PingError> {
+    let stream: TcpStream = match tokio::time::timeout(options.ping.connect_timeout, connect(host, port)).await {
+        Ok(connected) => connected?,
+        Err(_) => return Err(PingError::Timeout("connect".to_string())),
+    };
+    let mut channel = mux::Channel::new(stream);
+    run_handshake(&mut channel, network_magic, Suite::NodeToNode, options.ping.handshake_timeout).await?;
+    state.send_replace(SessionState::Connected);
+
+    let io = |e: std::io::Error| PingError::Io(e.to_string());
+    let mut ticker = tokio::time::interval(options.interval);
+    let mut cookie: u16 = 0;
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => {
+                let _ = channel.send(KEEP_ALIVE_ID, &message(MSG_DONE, None)).await;
+                return Ok(());
+            }
+            _ = ticker.tick() => {}
+        }
+
+        cookie = cookie.wrapping_add(1);
+        let start = Instant::now();
+        channel.send(KEEP_ALIVE_ID, &message(MSG_KEEP_ALIVE, Some(cookie))).await.map_err(io)?;
+
+        loop {
+            // the wait for the response is cut short by close
+            let received = tokio::select! {
+                _ = cancel.cancelled() => None,
+                received = tokio::time::timeout(options.response_timeout, channel.recv(KEEP_ALIVE_ID)) => Some(received),
+            };
+            let payload = match received {
+                Some(Ok(payload)) => payload.map_err(io)?,
+                Some(Err(_)) => return Err(PingError::Timeout(format!("keep-alive response {}", cookie))),
+                None => {
+                    let _ = channel.send(KEEP_ALIVE_ID, &message(MSG_DONE, None)).await;
+                    return Ok(());
+                }
+            };
+            if response_cookie(&payload)? == cookie {
+                break;
+            }
+        }
+
+        let rtt = start.elapsed();
+        trace!("keep-alive {}:{} cookie {}: {} us", host, port, cookie, rtt.as_micros());
+        window.lock().unwrap().push(RttSample { cookie, rtt });
+    }
+}
@@ -0,0 +1,472 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use serde_cbor::Value;
+
+use crate::node::Node;
+use crate::ping::{PingError, Tip};
+use crate::types::NetworkType;
+
+const RESPONDER_BIT: u16 = 0x8000;
+const HANDSHAKE_ID: u16 = 0;
+const CHAIN_SYNC_ID: u16 = 2;
+const LOCAL_CHAIN_SYNC_ID: u16 = 5;
+const NODE_TO_CLIENT_BIT: u64 = 0x8000;
+const KEEP_ALIVE_ID: u16 = 8;
+
+/// local_node: returns a Node pointing to the local port, e.g. a mock node or a closed port
+pub(crate) fn local_node(port: u16) -> Node {
+    let mut node = Node::default();
+    node.set_addr("127.0.0.1".to_string());
+    node.set_port(port);
+    node
+}
+
+/// closed_port: returns a local port nobody listens on
+pub(crate) fn closed_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// PingedNode builds a Node as left by a ping, for the tests of the modules working on ping
+/// results. The node listens on port 3001, is measured and stays offline until given a latency.
+pub(crate) struct PingedNode(Node);
+
+impl PingedNode {
+    /// new: returns the builder of a measured node at the address
+    pub fn new(addr: &str) -> PingedNode {
+        let mut node = Node::default();
+        node.set_addr(addr.to_string());
+        node.set_port(3001);
+        node.set_measured(true);
+        PingedNode(node)
+    }
+
+    /// port: sets the port
+    pub fn port(mut self, port: u16) -> PingedNode {
+        self.0.set_port(port);
+        self
+    }
+
+    /// location: sets the continent and the state
+    pub fn location(mut self, continent: &str, state: &str) -> PingedNode {
+        self.0.set_continent(continent.to_string());
+        self.0.set_state(state.to_string());
+        self
+    }
+
+    /// network: sets the network type
+    #[allow(dead_code)]
+    pub fn network(mut self, network_type: NetworkType) -> PingedNode {
+        self.0.set_network_type(network_type);
+        self
+    }
+
+    /// latency: sets the node online with the total latency, half of it taken by the connection
+    pub fn latency(mut self, latency_ms: u64) -> PingedNode {
+        self.0.set_con_latency(Duration::from_millis(latency_ms / 2));
+        self.0.set_total_latency(Duration::from_millis(latency_ms));
+        self.0.set_online(true);
+        self.0.set_online_error(None);
+        self
+    }
+
+    /// con_latency: sets the connection latency
+    pub fn con_latency(mut self, con_latency: Duration) -> PingedNode {
+        self.0.set_con_latency(con_latency);
+        self
+    }
+
+    /// offline: sets the node offline with the error, the latencies are kept
+    pub fn offline(mut self, error: PingError) -> PingedNode {
+        self.0.set_online(false);
+        self.0.set_online_error(Some(error));
+        self
+    }
+
+    /// refused: sets the node offline, its connection refused
+    pub fn refused(self) -> PingedNode {
+        self.offline(PingError::Connect("connection refused".to_string()))
+    }
+
+    /// tip: sets the chain tip announced by the node
+    pub fn tip(mut self, tip: Tip) -> PingedNode {
+        self.0.set_tip(Some(tip));
+        self
+    }
+
+    /// build: returns the node
+    pub fn build(self) -> Node {
+        self.0
+    }
+}
+
+/// Reply selects how the mock node answers a handshake proposal
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Reply {
+    /// Accept accepts the highest proposed version (or MockConfig::version)
+    Accept,
+
+    /// RefuseVersion refuses with a version mismatch, offering the versions given instead
+    RefuseVersion(Vec<u64>),
+
+    /// Refuse refuses the proposal with the reason given
+    Refuse(String),
+
+    /// Drop reads the proposal and closes the connection without answering
+    Drop,
+
+    /// HangUp closes the connection as soon as it is accepted
+    HangUp,
+
+    /// Silent reads the proposal and never answers
+    Silent,
+}
+
+/// MockConfig scripts the behaviour of a mock node
+#[derive(Clone, Debug)]
+pub(crate) struct MockConfig {
+    /// reply is the answer to the handshake proposal (default Accept)
+    pub reply: Reply,
+
+    /// delay is the wait before answering the proposal (default none)
+    pub delay: Duration,
+
+    /// magic is the network magic the node runs, a proposal for another magic is refused. None
+    /// accepts any magic.
+    pub magic: Option<u32>,
+
+    /// version is the version accepted, None accepts the highest proposed one
+    pub version: Option<u64>,
+
+    /// tip is the chain tip announced over chain-sync, None leaves chain-sync unanswered
+    pub tip: Option<Tip>,
+
+    /// keep_alive_replies is the number of keep-alive messages answered before the connection
+    /// is dropped, None answers them all
+    pub keep_alive_replies: Option<usize>,
+
+    /// keep_alive_silent keeps the connection open without answering keep-alive messages
+    pub keep_alive_silent: bool,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        MockConfig {
+            reply: Reply::Accept,
+            delay: Duration::default(),
+            magic: None,
+            version: None,
+            tip: None,
+            keep_alive_replies: None,
+            keep_alive_silent: false,
+        }
+    }
+}
+
+/// Connection is a stream the mock node can serve, over TCP or over a Unix socket
+trait Connection: Read + Write + Send + 'static {
+    fn close(&self) -> std::io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn close(&self) -> std::io::Result<()> {
+        self.shutdown(Shutdown::Both)
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn close(&self) -> std::io::Result<()> {
+        self.shutdown(Shutdown::Both)
+    }
+}
+
+/// MockNode is a running mock node, it serves connections until the test process exits
+pub(crate) struct MockNode {
+    port: u16,
+    #[cfg(unix)]
+    socket_path: Option<PathBuf>,
+    connections: Arc<AtomicUsize>,
+}
+
+fn accept_loop<C: Connection>(mut accept: impl FnMut() -> std::io::Result<C> + Send + 'static, config: MockConfig, counter: Arc<AtomicUsize>) {
+    thread::spawn(move || {
+        loop {
+            let stream = match accept() {
+                Ok(stream) => stream,
+                Err(_) => return,
+            };
+            counter.fetch_add(1, Ordering::SeqCst);
+            let config = config.clone();
+            thread::spawn(move || {
+                let _ = serve(stream, &config);
+            });
+        }
+    });
+}
+
+impl MockNode {
+    /// start: starts a mock node on a free local port
+    pub fn start(config: MockConfig) -> MockNode {
+        MockNode::start_on("127.0.0.1:0", config)
+    }
+
+    /// start_on: starts a mock node listening on the address, e.g. `[::1]:0` for IPv6
+    pub fn start_on(bind: &str, config: MockConfig) -> MockNode {
+        let listener = TcpListener::bind(bind).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        accept_loop(move || listener.accept().map(|(stream, _)| stream), config, connections.clone());
+
+        MockNode {
+            port,
+            #[cfg(unix)]
+            socket_path: None,
+            connections,
+        }
+    }
+
+    /// start_local: starts a mock local node listening on a fresh Unix socket, which speaks the
+    /// node-to-client protocols
+    #[cfg(unix)]
+    pub fn start_local(config: MockConfig) -> MockNode {
+        static SOCKETS: AtomicUsize = AtomicUsize::new(0);
+        let socket_path = std::env::temp_dir().join(format!(
+            "adakai-mock-{}-{}.socket",
+            std::process::id(),
+            SOCKETS.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = std::fs::remove_file(&socket_path);
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        accept_loop(move || listener.accept().map(|(stream, _)| stream), config, connections.clone());
+
+        MockNode {
+            port: 0,
+            socket_path: Some(socket_path),
+            connections,
+        }
+    }
+
+    /// accepting: starts a mock node accepting the handshakes for the network
+    pub fn accepting(network_type: NetworkType) -> MockNode {
+        MockNode::start(MockConfig {
+            magic: Some(network_type.magic()),
+            ..Default::default()
+        })
+    }
+
+    /// replying: starts a mock node answering every handshake with the reply
+    pub fn replying(reply: Reply) -> MockNode {
+        MockNode::start(MockConfig {
+            reply,
+            ..Default::default()
+        })
+    }
+
+    /// port: returns the local port the mock node listens on
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// node: returns a Node pointing to the mock node, on the network given
+    pub fn node(&self, network_type: NetworkType) -> Node {
+        let mut node = local_node(self.port);
+        node.set_network_type(network_type);
+        node
+    }
+
+    /// socket_path: returns the path of the socket of a mock local node
+    #[cfg(unix)]
+    pub fn socket_path(&self) -> &Path {
+        self.socket_path.as_deref().expect("not a local mock node")
+    }
+
+    /// connections: returns the number of connections accepted so far
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(unix)]
+impl Drop for MockNode {
+    fn drop(&mut self) {
+        if let Some(socket_path) = &self.socket_path {
+            let _ = std::fs::remove_file(socket_path);
+        }
+    }
+}
+
+fn read_segment(stream: &mut impl Connection) -> std::io::Result<(u16, Vec<u8>)> {
+    let mut header = [0u8; 8];
+    stream.read_exact(&mut header)?;
+    let id = u16::from_be_bytes([header[4], header[5]]);
+    let mut payload = vec![0u8; u16::from_be_bytes([header[6], header[7]]) as usize];
+    stream.read_exact(&mut payload)?;
+    Ok((id & !RESPONDER_BIT, payload))
+}
+
+fn write_segment(stream: &mut impl Connection, protocol_id: u16, message: &Value) -> std::io::Result<()> {
+    let payload = serde_cbor::to_vec(message).unwrap();
+    let mut segment = vec![0u8; 4];
+    segment.extend_from_slice(&(protocol_id | RESPONDER_BIT).to_be_bytes());
+    segment.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    segment.extend_from_slice(&payload);
+    stream.write_all(&segment)
+}
+
+fn int(value: i128) -> Value {
+    Value::Integer(value)
+}
+
+fn serve(mut stream: impl Connection, config: &MockConfig) -> std::io::Result<()> {
+    if config.reply == Reply::HangUp {
+        return stream.close();
+    }
+
+    let (id, payload) = read_segment(&mut stream)?;
+    if id != HANDSHAKE_ID {
+        return stream.close();
+    }
+    let proposed = proposed_versions(&payload);
+
+    thread::sleep(config.delay);
+
+    let reply = match &config.reply {
+        Reply::Accept => accept(config, &proposed),
+        Reply::RefuseVersion(offered) => refuse(int(0), vec![Value::Array(offered.iter().map(|v| int(*v as i128)).collect())]),
+        Reply::Refuse(reason) => refuse(int(2), vec![int(highest(&proposed) as i128), Value::Text(reason.clone())]),
+        Reply::Drop | Reply::HangUp => return stream.close(),
+        Reply::Silent => {
+            // holds the connection open until the client gives up
+            let mut sink = Vec::new();
+            return stream.read_to_end(&mut sink).map(|_| ());
+        }
+    };
+    write_segment(&mut stream, HANDSHAKE_ID, &reply)?;
+
+    // serves the mini-protocols until the client closes the connection
+    let mut keep_alive_replies = 0;
+    while let Ok((id, payload)) = read_segment(&mut stream) {
+        match id {
+            CHAIN_SYNC_ID | LOCAL_CHAIN_SYNC_ID => {
+                if let Some(tip) = &config.tip {
+                    serve_chain_sync(&mut stream, id, &payload, tip)?;
+                }
+            }
+            KEEP_ALIVE_ID if config.keep_alive_silent => {}
+            KEEP_ALIVE_ID => {
+                if config.keep_alive_replies.is_some_and(|max| keep_alive_replies >= max) {
+                    return stream.close();
+                }
+                keep_alive_replies += 1;
+                serve_keep_alive(&mut stream, &payload)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn serve_keep_alive(stream: &mut impl Connection, payload: &[u8]) -> std::io::Result<()> {
+    if let Ok(Value::Array(items)) = serde_cbor::from_slice(payload) {
+        if let (Some(Value::Integer(0)), Some(cookie)) = (items.first(), items.get(1)) {
+            return write_segment(stream, KEEP_ALIVE_ID, &Value::Array(vec![int(1), cookie.clone()]));
+        }
+    }
+    Ok(())
+}
+
+fn serve_chain_sync(stream: &mut impl Connection, protocol_id: u16, payload: &[u8], tip: &Tip) -> std::io::Result<()> {
+    let tag = match serde_cbor::from_slice(payload) {
+        Ok(Value::Array(items)) => items.first().cloned(),
+        _ => None,
+    };
+    let reply = match tag {
+        // find intersect: no point given, no intersection
+        Some(Value::Integer(4)) => Value::Array(vec![int(6), tip.to_cbor()]),
+        // request next: roll back to origin
+        Some(Value::Integer(0)) => Value::Array(vec![int(3), Value::Array(vec![]), tip.to_cbor()]),
+        _ => return Ok(()),
+    };
+    write_segment(stream, protocol_id, &reply)
+}
+
+fn proposed_versions(payload: &[u8]) -> BTreeMap<u64, Value> {
+    let message: Value = serde_cbor::from_slice(payload).unwrap_or(Value::Null);
+    match message {
+        Value::Array(items) => match items.get(1) {
+            Some(Value::Map(versions)) => versions
+                .iter()
+                .filter_map(|(k, v)| match k {
+                    Value::Integer(k) => Some((*k as u64, v.clone())),
+                    _ => None,
+                })
+                .collect(),
+            _ => BTreeMap::new(),
+        },
+        _ => BTreeMap::new(),
+    }
+}
+
+fn highest(proposed: &BTreeMap<u64, Value>) -> u64 {
+    proposed.keys().last().copied().unwrap_or_default()
+}
+
+fn refuse(kind: Value, mut details: Vec<Value>) -> Value {
+    let mut reason = vec![kind];
+    reason.append(&mut details);
+    Value::Array(vec![int(2), Value::Array(reason)])
+}
+
+fn accept(config: &MockConfig, proposed: &BTreeMap<u64, Value>) -> Value {
+    let version = config.version.unwrap_or_else(|| highest(proposed));
+
+    // node-to-client versions before 15 carry the bare magic
+    let proposed_magic = match proposed.get(&version) {
+        Some(Value::Array(data)) => match data.first() {
+            Some(Value::Integer(m)) => Some(*m as u32),
+            _ => None,
+        },
+        Some(Value::Integer(m)) => Some(*m as u32),
+        _ => None,
+    };
+    let proposed_magic = match proposed_magic {
+        Some(m) => m,
+        None => return refuse(int(0), vec![Value::Array(proposed.keys().map(|v| int(*v as i128)).collect())]),
+    };
+
+    if let Some(magic) = config.magic {
+        if magic != proposed_magic {
+            return refuse(
+                int(2),
+                vec![int(version as i128), Value::Text(format!("version data mismatch: NetworkMagic {} /= {}", magic, proposed_magic))],
+            );
+        }
+    }
+
+    let magic = int(config.magic.unwrap_or(proposed_magic) as i128);
+    let version_data = if version & NODE_TO_CLIENT_BIT != 0 {
+        if version & !NODE_TO_CLIENT_BIT < 15 {
+            magic
+        } else {
+            Value::Array(vec![magic, Value::Bool(false)])
+        }
+    } else if version < 11 {
+        Value::Array(vec![magic, Value::Bool(false)])
+    } else {
+        Value::Array(vec![magic, Value::Bool(false), int(1), Value::Bool(false)])
+    };
+    Value::Array(vec![int(1), int(version as i128), version_data])
+}
@@ -2,374 +2,726 @@
 
 extern crate pretty_env_logger;
 
-use std::{thread, time};
-use std::sync::{Arc, Mutex};
-use std::sync::mpsc::{channel, Sender};
-use std::thread::JoinHandle;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
-use cardano_ouroboros_network::mux;
-use futures::executor::block_on;
+use futures::StreamExt;
 use log::debug;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
 
 use crate::node::Node;
-use crate::types::{MAINNET_MAGIC, NetworkType, TESTNET_MAGIC};
+use crate::types::{AdakaiResult, NetworkType};
 
 mod ping_tests;
 
-/// MessageOut holds the message crafted with the information of the node that wants to be pinged.
-/// It is sent to a worker that can process it and respond by sending a MessageOut.
-#[derive(Debug)]
-pub enum MessageIn {
-    /// Quit signal the worker thread to quit
-    Quit,
+mod mux;
 
-    /// Node contains the message body
-    Node {
-        /// name: IP or valid DNS address of the node
-        name: String,
+mod handshake;
 
-        /// port: TCP port of the node
-        port: u16 ,
+mod stats;
 
-        /// network_magic of the cardano network the node belongs to
-        network_magic: u32,
+mod chainsync;
 
-        /// id is the position in the input vector
-        id:usize},
-}
+mod keepalive;
 
-/// MessageOut holds the message crafted with the information return by the ping function.
-/// It is sent to a worker the response vector of nodes with calculated latencies.
-#[derive(Debug)]
-pub enum MessageOut {
-    #[allow(dead_code)]
-    /// Quit signal the worker thread to quit
-    Quit,
-    /// Latency is the message body of the ping response
-    Latency {
+mod stream;
 
-        /// conn_latency is the elapsed time measured from the start of the request until a connection
-        /// was stablished
-        conn_latency: Duration,
+mod family;
 
-        /// total_latency is the total elapsed time taken by the node to respond
-        total_latency: Duration,
+/// mock is an in-process cardano node speaking the node-to-node mux and handshake protocol, so
+/// that the ping module can be tested without network
+#[cfg(test)]
+pub(crate) mod mock;
 
-        /// online is true if the node responded to the ping connection request
-        online: bool,
+pub use chainsync::{best_tip, lagging, tip_lags, Tip};
+pub use family::{broken_ipv6, AddressFamily, FamilyOutcome, HAPPY_EYEBALLS_DELAY};
+pub use handshake::{version_histogram, NegotiatedVersion, VersionHistogram};
+pub use keepalive::{KeepAliveOptions, KeepAliveSession, RttSample, RttWindow, SessionState};
+pub use stats::LatencyStats;
+pub use stream::{ping_iter, ping_stream, CancelHandle, PingControl, PingIter, Progress, ProgressCallback};
 
-        /// id is the position of the node in the input vector of nodes
-        id: usize,
+/// DEFAULT_CONCURRENCY is the number of nodes pinged at the same time by default
+pub const DEFAULT_CONCURRENCY: usize = 256;
 
-        /// is_error is true if there was any kind of connection error
-        is_error: bool,
+/// PingMode selects how far the ping goes with the node
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PingMode {
+    /// TcpConnect only opens the TCP connection, the total latency is the connection latency
+    TcpConnect,
 
-        /// error is the description of the error detected
-        error: String},
+    /// Handshake opens the TCP connection and runs the Ouroboros handshake
+    #[default]
+    Handshake,
 }
 
-// pub(crate) type PingResult<T> = Result<T, Box<dyn Error>>;
+/// PingOptions holds the timeouts, the retry policy and the mode used when pinging nodes
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PingOptions {
+    /// connect_timeout bounds the name resolution plus the TCP connection (default 5 s)
+    pub connect_timeout: Duration,
 
-/// Pinger holds the internal functionality used for pinging multiple nodes concurrently
-#[derive(Debug)]
-pub(crate) struct Pinger {
-    fan_in: Option<Sender<MessageOut>>,
-    fan_out: Vec<Sender<MessageIn>>,
-    cpus: usize,
-    next_cpu: usize,
-    msg_vec: Arc<Mutex<Vec<MessageOut>>>,
-    size: usize,
+    /// handshake_timeout bounds the handshake, once connected (default 5 s)
+    pub handshake_timeout: Duration,
+
+    /// retries is the number of new attempts made after a failed connection (default 2). A
+    /// failed handshake is never retried: the node answered.
+    pub retries: u32,
+
+    /// backoff_base is the wait before the first retry, doubled on every following retry
+    /// (default 100 ms)
+    pub backoff_base: Duration,
+
+    /// backoff_max caps the wait between two attempts (default 2 s)
+    pub backoff_max: Duration,
+
+    /// jitter is the fraction of the wait that is randomized, between 0 and 1 (default 0.5), so
+    /// that the retries of many nodes do not happen at the same time
+    pub jitter: f64,
+
+    /// mode selects a raw TCP connect or the full handshake (default Handshake)
+    pub mode: PingMode,
+
+    /// concurrency is the maximum number of pings in flight for ping_many and ping_vec
+    /// (default DEFAULT_CONCURRENCY)
+    pub concurrency: usize,
+
+    /// samples is the number of times every node is pinged (default 1). With more than one sample
+    /// the latencies of the node are the median ones and its LatencyStats are recorded.
+    pub samples: u32,
+
+    /// sample_spacing is the wait between two samples of the same node (default 200 ms)
+    pub sample_spacing: Duration,
+
+    /// query_tip runs chain-sync after the handshake to learn the tip of the node (default
+    /// false). It is bounded by handshake_timeout and does not count in the latencies; a node
+    /// whose tip cannot be learnt is still online, without a tip.
+    pub query_tip: bool,
+
+    /// family selects the IP family used to reach nodes with both IPv4 and IPv6 addresses, or
+    /// measures both separately (default Any)
+    pub family: AddressFamily,
 }
 
-impl Pinger {
-
-    /// new return a ping with the internal fields initialized to it's default values.
-    ///
-    /// # Arguments
-    /// * `size` - size is the size of the vector of nodes that will be pinged
-    pub fn new (size: usize) -> Pinger {
-        debug!("input vector size: {}", size);
-
-        let mut p: Pinger = Pinger {
-            fan_in: Option::None,
-            fan_out: Vec::new(),
-            cpus: num_cpus::get(),
-            next_cpu : 0,
-            msg_vec: Arc::new(Mutex::new(Vec::new())),
-            size,
-        };
+impl Default for PingOptions {
+    fn default() -> Self {
+        PingOptions {
+            connect_timeout: Duration::from_secs(5),
+            handshake_timeout: Duration::from_secs(5),
+            retries: 2,
+            backoff_base: Duration::from_millis(100),
+            backoff_max: Duration::from_secs(2),
+            jitter: 0.5,
+            mode: PingMode::default(),
+            concurrency: DEFAULT_CONCURRENCY,
+            samples: 1,
+            sample_spacing: Duration::from_millis(200),
+            query_tip: false,
+            family: AddressFamily::default(),
+        }
+    }
+}
 
-        if p.cpus > 1 {
-            p.cpus = p.cpus - 1
+impl PingOptions {
+    /// backoff: returns the wait before the retry number `retry` (starting at 0): backoff_base
+    /// doubled on every retry, capped by backoff_max, and reduced by up to `jitter` of its value
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let wait = self
+            .backoff_base
+            .checked_mul(2u32.saturating_pow(retry))
+            .unwrap_or(self.backoff_max)
+            .min(self.backoff_max);
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return wait;
         }
-        return p;
+        wait.mul_f64(1.0 - jitter * rand::thread_rng().gen::<f64>())
     }
+}
+
+/// PingError holds the reason why a node could not be pinged. A failed ping is a result of the
+/// ping, not a failure of the call: it is held by PingOutcome and the nodes, serialized in the
+/// reports and the history, so it stays a plain value. It converts into AdakaiError::Network,
+/// e.g. with PingOutcome::result.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PingError {
+    /// Resolve: the node address could not be resolved (DNS failure)
+    Resolve(String),
+
+    /// Connect: the TCP connection could not be established (refused, unreachable...)
+    Connect(String),
 
-    /// run: starts the internal workers (threads) in charged of performing pings.
-    /// It will start one thread per available cpus - 1.
-    pub fn run(&mut self) -> (JoinHandle<()>, Vec<JoinHandle<()>>) {
-        let a = self.go_producer();
-        let b  = self.go_workers();
-        thread::sleep(time::Duration::from_secs(1)); //FIXME
-        return (a,b);
+    /// Timeout: the node did not answer in time, holds the step that timed out
+    Timeout(String),
+
+    /// HandshakeRefused: the node refused the handshake, holds the refusal reason
+    HandshakeRefused(String),
+
+    /// VersionMismatch: the node supports none of the proposed versions, holds the versions it
+    /// offered instead
+    VersionMismatch(Vec<u64>),
+
+    /// Io: the connection failed during the handshake (closed, reset, invalid reply...)
+    Io(String),
+}
+
+impl fmt::Display for PingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PingError::Resolve(e) => write!(f, "resolve error: {}", e),
+            PingError::Connect(e) => write!(f, "connect error: {}", e),
+            PingError::Timeout(e) => write!(f, "timeout: {}", e),
+            PingError::HandshakeRefused(e) => write!(f, "handshake refused: {}", e),
+            PingError::VersionMismatch(v) => write!(f, "version mismatch, peer offers: {:?}", v),
+            PingError::Io(e) => write!(f, "io error: {}", e),
+        }
     }
+}
 
-    /// fan_out: returns a vector with the sender channel for each worker (pinger).  It is used for
-    /// sending the nodes data (MessageIn) for pinging a node
-    pub fn fan_out(&self) -> &Vec<Sender<MessageIn>> {
-        &self.fan_out
+impl PingError {
+    /// kind: returns the name of the kind of error, without its details (e.g. `timeout`)
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PingError::Resolve(_) => "resolve",
+            PingError::Connect(_) => "connect",
+            PingError::Timeout(_) => "timeout",
+            PingError::HandshakeRefused(_) => "handshake_refused",
+            PingError::VersionMismatch(_) => "version_mismatch",
+            PingError::Io(_) => "io",
+        }
     }
+}
+
+impl Error for PingError {}
 
-    /// cpus: return the number of cpus that will be used
-    pub fn cpus(&mut self) -> usize {
-        return self.cpus;
+/// PingOutcome holds the result of pinging a node
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PingOutcome {
+    /// con_latency is the time taken to establish the TCP connection, None if it failed
+    pub con_latency: Option<Duration>,
+
+    /// total_latency is the time taken by the connection plus the handshake, None if the
+    /// handshake did not complete
+    pub total_latency: Option<Duration>,
+
+    /// error is the reason of the failure, None if the node answered
+    pub error: Option<PingError>,
+
+    /// stats holds the latency statistics when the node was pinged more than once (see
+    /// PingOptions::samples)
+    pub stats: Option<LatencyStats>,
+
+    /// tip is the chain tip of the node, when queried (see PingOptions::query_tip)
+    pub tip: Option<Tip>,
+
+    /// tip_error is the reason the tip could not be learnt when it was queried, e.g. the node
+    /// did not answer chain-sync in time. The node is still online.
+    pub tip_error: Option<PingError>,
+
+    /// version is the node-to-node version negotiated with the node, None if the handshake did
+    /// not complete (or in TcpConnect mode). The versions offered by a node refusing all of ours
+    /// are held by PingError::VersionMismatch.
+    pub version: Option<NegotiatedVersion>,
+
+    /// addr is the address the connection was made to, None if no connection was made (or over
+    /// a Unix socket)
+    pub addr: Option<SocketAddr>,
+
+    /// ipv4 is the result over IPv4, None if the node was not reached over IPv4 (see
+    /// PingOptions::family)
+    pub ipv4: Option<FamilyOutcome>,
+
+    /// ipv6 is the result over IPv6, None if the node was not reached over IPv6 (see
+    /// PingOptions::family)
+    pub ipv6: Option<FamilyOutcome>,
+}
+
+impl PingOutcome {
+    /// online: returns true if the node completed the handshake
+    pub fn online(&self) -> bool {
+        self.error.is_none() && self.total_latency.is_some()
     }
 
-    /// next_cpu: used as an iterator for distributing work among all available cpus
-    pub fn next_cpu(&mut self) -> usize {
-        self.next_cpu += 1;
-        if self.next_cpu == self.cpus {
-            self.next_cpu = 0
+    /// result: returns the total latency of the node, or the reason it could not be pinged as an
+    /// AdakaiError::Network holding the PingError
+    pub fn result(&self) -> AdakaiResult<Duration> {
+        match (&self.error, self.total_latency) {
+            (None, Some(latency)) => Ok(latency),
+            (Some(error), _) => Err(error.clone().into()),
+            (None, None) => Err(PingError::Io("no answer".to_string()).into()),
         }
-        self.next_cpu
     }
 
-    /// msg_vec: returns the vector of MessageOut contain the ping results for each node.
-    pub fn msg_vec(&self) -> Arc<Mutex<Vec<MessageOut>>> {
-        return self.msg_vec.clone();
+    fn failed(con_latency: Option<Duration>, error: PingError) -> PingOutcome {
+        PingOutcome {
+            con_latency,
+            error: Some(error),
+            ..Default::default()
+        }
     }
+}
 
-    fn go_workers(&mut self) -> Vec<JoinHandle<()>> {
+/// MessageOut holds the message crafted with the information return by the ping function.
+/// It is sent by the ping engine for every node of the input vector, once its ping is done.
+#[derive(Debug)]
+pub enum MessageOut {
+    /// Latency is the message body of the ping response
+    Latency {
 
-        let mut out: Vec<JoinHandle<()>> = Vec::new();
+        /// conn_latency is the elapsed time measured from the start of the request until a connection
+        /// was stablished, None if it could not be established
+        conn_latency: Option<Duration>,
 
-        for i in 0..self.cpus {
-            let (tx,out_i) = self.go_worker(self.fan_in.clone(), i);
-            self.fan_out.push(tx);
-            out.push(out_i);
-        }
-        thread::sleep(time::Duration::from_secs(1));
-        return out
-    }
+        /// total_latency is the total elapsed time taken by the node to respond, None if it did
+        /// not respond
+        total_latency: Option<Duration>,
 
-    fn go_producer(&mut self) -> JoinHandle<()>{
-        let (tx, rx): (
-            std::sync::mpsc::Sender<MessageOut>,
-            std::sync::mpsc::Receiver<MessageOut>,
-        ) = channel();
+        /// online is true if the node responded to the ping connection request
+        online: bool,
 
-        self.fan_in = Some(tx.clone());
+        /// id is the position of the node in the input vector of nodes
+        id: usize,
 
-        let local_node_vec = self.msg_vec.clone();
-        let mut local_counter:usize = 0;
-        let size = self.size;
+        /// error is the error detected, None if the node is online
+        error: Option<PingError>,
 
+        /// stats holds the latency statistics, None if the node was pinged only once
+        stats: Option<LatencyStats>,
 
-        let _guard = thread::spawn(move || loop {
-            let msg = rx.recv();
+        /// tip is the chain tip of the node, None if it was not queried or could not be learnt
+        tip: Option<Tip>,
 
-            match msg {
-                Ok(decoded) => match decoded {
-                    MessageOut::Quit => {
-                        debug!("msg_out: QUIT: {:?}", decoded);
-                        break
-                    }
-                    MessageOut::Latency { .. } => {
-                        let mut protected = local_node_vec.lock().unwrap();  // FIXME: mutex probably unneeded ???
-                        protected.push(decoded);
-
-                        local_counter += 1;
-                        if local_counter == size  {
-                            debug!("breaking go_producer");
-                            break
-                        }
-                    }
-                },
-                Err(e) => {
-                    error!("err: {:?}", e);
-                    break;
-                }
-            }
-        });
+        /// tip_error is the reason the tip could not be learnt, None if it was not queried
+        tip_error: Option<PingError>,
 
+        /// version is the negotiated node-to-node version, None if the handshake did not complete
+        version: Option<NegotiatedVersion>,
 
+        /// ipv4 is the result over IPv4, None if the node was not reached over IPv4
+        ipv4: Option<FamilyOutcome>,
 
-        return _guard
+        /// ipv6 is the result over IPv6, None if the node was not reached over IPv6
+        ipv6: Option<FamilyOutcome>},
+}
 
+async fn resolve(host: &str, port: u16) -> Result<Vec<SocketAddr>, PingError> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| PingError::Resolve(e.to_string()))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(PingError::Resolve(format!("no address found for {}", host)));
     }
+    Ok(addrs)
+}
 
-    fn go_worker(&mut self, output_opt: Option<Sender<MessageOut>>, i: usize) -> (Sender<MessageIn>, JoinHandle<()>) {
-        let output = output_opt.unwrap();
-        debug!("starting worker {}", i);
-        let (tx, rx): (
-            std::sync::mpsc::Sender<MessageIn>,
-            std::sync::mpsc::Receiver<MessageIn>,
-        ) = channel();
-
-        let _guard = thread::spawn(move || loop {
-            let msg = rx.recv();
-            match msg {
-                Ok(decoded) => match decoded {
-                    MessageIn::Quit => {
-                        debug!("msg: QUIT: {:?} --> worker: {:?}", decoded, i);
-                        break;
-                    }
-                    MessageIn::Node { name,port,network_magic, id} => {
-                        debug!("msg: NODE: {} --> worker: {} - {} - {} ",i, name, port, network_magic);
-                        let (conn_duration, total_duration, is_error, error) = ping(name, port, NetworkType::TestNet); //fixme
-                        output.send(MessageOut::Latency {
-                            conn_latency: conn_duration,
-                            total_latency: total_duration,
-                            id,
-                            online: !is_error,
-                            is_error,
-                            error,
-                        }).unwrap();
-                    }
-                },
-                Err(e) => {
-                    error!("err: {:?} --> worker: {:?}", e, i);
-                    return;
-                }
-            }
-        });
+/// connect: connects to the first address of the host that accepts the connection
+async fn connect(host: &str, port: u16) -> Result<TcpStream, PingError> {
+    connect_family(host, port, AddressFamily::Any).await
+}
+
+/// connect_family: connects to an address of the host, as selected by the family
+async fn connect_family(host: &str, port: u16, family: AddressFamily) -> Result<TcpStream, PingError> {
+    let addrs = resolve(host, port).await?;
+    family::connect_addrs(&addrs, family).await
+}
 
-        return (tx.clone(),_guard);
+/// connect_local: connects to the Unix socket of a local node
+#[cfg(unix)]
+async fn connect_local(socket_path: &Path) -> Result<tokio::net::UnixStream, PingError> {
+    tokio::net::UnixStream::connect(socket_path)
+        .await
+        .map_err(|e| PingError::Connect(format!("{}: {}", socket_path.display(), e)))
+}
+
+/// run_handshake: runs the handshake of the suite on the channel, bounded by the timeout
+async fn run_handshake<S>(channel: &mut mux::Channel<S>, network_magic: u32, suite: handshake::Suite, timeout: Duration) -> Result<handshake::Accepted, PingError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut handshake = handshake::Handshake::with_suite(network_magic, suite);
+    match tokio::time::timeout(timeout, channel.execute(&mut handshake)).await {
+        Ok(Ok(_)) => handshake.outcome(),
+        Ok(Err(e)) => Err(PingError::Io(e.to_string())),
+        Err(_) => Err(PingError::Timeout(format!("handshake after {} ms", timeout.as_millis()))),
+    }
+}
+
+async fn call_ping(host: String, port: u16, network_magic: u32, options: PingOptions) -> PingOutcome {
+    let target = format!("{}:{}", host, port);
+    if options.family == AddressFamily::Both {
+        let addrs = match tokio::time::timeout(options.connect_timeout, resolve(&host, port)).await {
+            Ok(Ok(addrs)) => addrs,
+            Ok(Err(e)) => return PingOutcome::failed(None, e),
+            Err(_) => return PingOutcome::failed(None, PingError::Timeout("resolve".to_string())),
+        };
+        return ping_families(&target, &addrs, network_magic, options).await;
     }
+
+    let mut outcome = ping_with(&target, || connect_family(&host, port, options.family), network_magic, handshake::Suite::NodeToNode, options).await;
+    family::record_family(&mut outcome, options.family);
+    outcome
 }
 
-async fn call_ping(host: String, port: u16, network_magic: u32) -> (Duration, Duration, bool, String) {
+/// ping_families: pings the IPv4 and the IPv6 addresses of the node separately, at the same time
+async fn ping_families(target: &str, addrs: &[SocketAddr], network_magic: u32, options: PingOptions) -> PingOutcome {
+    let ping_family = |family: AddressFamily| async move {
+        let tried = addrs.iter().find(|a| if family == AddressFamily::V4 { a.is_ipv4() } else { a.is_ipv6() })?;
+        let open = || family::connect_addrs(addrs, family);
+        let outcome = ping_with(target, open, network_magic, handshake::Suite::NodeToNode, options).await;
+        Some((outcome, *tried))
+    };
+
+    let (v4, v6) = tokio::join!(ping_family(AddressFamily::V4), ping_family(AddressFamily::V6));
+    family::combine(v4, v6)
+}
 
-    const RETRY_WAIT_NS: u32 = 100000000;
+/// call_ping_local: pings the local node listening on the Unix socket
+#[cfg(unix)]
+async fn call_ping_local(socket_path: PathBuf, network_magic: u32, options: PingOptions) -> PingOutcome {
+    let target = socket_path.display().to_string();
+    ping_with(&target, || connect_local(&socket_path), network_magic, handshake::Suite::NodeToClient, options).await
+}
+
+/// ping_with: connects with `open`, retrying as set by the options, then runs the handshake of
+/// the suite and, if asked to, the tip query
+async fn ping_with<S, F, Fut>(target: &str, mut open: F, network_magic: u32, suite: handshake::Suite, options: PingOptions) -> PingOutcome
+where
+    S: AsyncRead + AsyncWrite + Unpin + family::PeerAddr,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<S, PingError>>,
+{
+    let mut outcome = PingOutcome::default();
+
+    for attempt in 0..=options.retries {
+        if attempt > 0 {
+            let wait = options.backoff(attempt - 1);
+            debug!("retry {} of {} in {} ms", attempt, target, wait.as_millis());
+            tokio::time::sleep(wait).await;
+        }
 
-    for i in 0..4 {
         let start = Instant::now();
-        match mux::connection::connect(&*host, port).await {
-            Ok(channel) => {
-                let connect_duration = start.elapsed();
-                match channel.handshake(network_magic).await {
-                    Ok(_) => {
-                        let total_duration = start.elapsed();
-                        debug!("ping: connect elapsed: {}, total elapsed: {} -- {}",
-                        connect_duration.as_millis(), total_duration.as_millis(), port);
-                        return (connect_duration, total_duration, false, "".to_string());
-                    }
-                    Err(e) => {
-                        debug!("error 1: {}", e.to_string());
-                        return (Duration::new(10, 0), Duration::new(10, 0), true, e.to_string());
+        let connected = match tokio::time::timeout(options.connect_timeout, open()).await {
+            Ok(connected) => connected,
+            Err(_) => Err(PingError::Timeout(format!("connect after {} ms", options.connect_timeout.as_millis()))),
+        };
+
+        let stream = match connected {
+            Ok(stream) => stream,
+            Err(e) => {
+                debug!("connect error: {}", e);
+                outcome = PingOutcome::failed(None, e);
+                continue;
+            }
+        };
+
+        let connect_duration = start.elapsed();
+        let addr = stream.peer();
+        if options.mode == PingMode::TcpConnect {
+            debug!("ping: connect elapsed: {} -- {}", connect_duration.as_millis(), target);
+            return PingOutcome {
+                con_latency: Some(connect_duration),
+                total_latency: Some(connect_duration),
+                addr,
+                ..Default::default()
+            };
+        }
+
+        let mut channel = mux::Channel::new(stream);
+        let result = run_handshake(&mut channel, network_magic, suite, options.handshake_timeout).await;
+
+        return match result {
+            Ok(accepted) => {
+                let total_duration = start.elapsed();
+                debug!("ping: connect elapsed: {}, total elapsed: {}, version: {} -- {}",
+                connect_duration.as_millis(), total_duration.as_millis(), accepted.version, target);
+
+                let (tip, tip_error) = if options.query_tip {
+                    match query_tip(&mut channel, suite, options.handshake_timeout).await {
+                        Ok(tip) => (Some(tip), None),
+                        Err(e) => (None, Some(e)),
                     }
+                } else {
+                    (None, None)
+                };
+
+                PingOutcome {
+                    con_latency: Some(connect_duration),
+                    total_latency: Some(total_duration),
+                    tip,
+                    tip_error,
+                    version: Some(accepted.negotiated()),
+                    addr,
+                    ..Default::default()
                 }
             }
             Err(e) => {
-                debug!("error 2: {}", e.to_string());
-                if i >= 2 {
-                    debug!("retry failed");
-                    return (Duration::new(10, 0), Duration::new(10, 0), true, e.to_string());
-                }else {
-                    debug!("retry: {}", i);
-                    thread::sleep(Duration::new(0, RETRY_WAIT_NS))
+                debug!("handshake error: {}", e);
+                PingOutcome {
+                    addr,
+                    ..PingOutcome::failed(Some(connect_duration), e)
                 }
-            },
-        }
+            }
+        };
     }
-    return (Duration::new(0,0), Duration::new(0,0), true, "".to_string());
+    debug!("retry failed");
+    outcome
 }
 
-/// ping: sends a ping message to a node, return the ping result
-/// # Arguments:
-/// * `host:` the node IP address or DNS name
-/// * `port:` the TCP port of the node to ping.
-/// * `net_type:` network magic of the cardano network that the node belongs to
-///
-/// # Example:
-/// ```
-/// use crate::ping;
-/// let (conn_duration, total_duration, is_error, error) = ping(name, port, NetworkType::TestNet); //fixme
-/// ```
-pub fn ping(host: String, port: u16, net_type: NetworkType) -> (Duration, Duration, bool, String) {
-    debug!("ping node: {}:{}", host,port);
-    let mut network_magic= MAINNET_MAGIC;
-
-    match net_type {
-        NetworkType::TestNet => {
-            network_magic = TESTNET_MAGIC;
-            debug!("network type: testnet")
-        }
-        NetworkType::Mainnet => {
-            debug!("network type: mainnet")
-        }
+/// query_tip: runs chain-sync on the channel to learn the tip of the node
+async fn query_tip<S>(channel: &mut mux::Channel<S>, suite: handshake::Suite, timeout: Duration) -> Result<Tip, PingError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut chain_sync = chainsync::ChainSync::new(suite.chain_sync_id());
+    let result = match tokio::time::timeout(timeout, channel.execute(&mut chain_sync)).await {
+        Ok(Ok(_)) => chain_sync.tip().map_err(|e| PingError::Io(format!("chain-sync: {}", e))),
+        Ok(Err(e)) => chain_sync.tip().map_err(|_| PingError::Io(format!("chain-sync: {}", e))),
+        Err(_) => chain_sync
+            .tip()
+            .map_err(|_| PingError::Timeout(format!("chain-sync after {} ms", timeout.as_millis()))),
+    };
+    if let Err(e) = &result {
+        debug!("chain-sync error: {}", e);
     }
-    let future_ping = call_ping(host.to_string(), port, network_magic);
-    block_on(future_ping)
+    result
 }
 
-/// ping_vec: sends a ping message to each of the nodes contained in the passed vector.
-/// It returns the same vector with the connection and total latencies updated.
-/// # Arguments:
-///
-pub fn ping_vec(mut in_node_vec: Vec<Node>,net_type: NetworkType) -> Vec<Node> {
+/// sample_ping: pings the node `options.samples` times, `options.sample_spacing` apart. The
+/// outcome holds the median latencies of the successful samples and their statistics, or the
+/// last error when every sample failed.
+async fn sample_ping(host: String, port: u16, network_magic: u32, options: PingOptions) -> PingOutcome {
+    let target = format!("{}:{}", host, port);
+    sample(&target, || call_ping(host.clone(), port, network_magic, options), options).await
+}
 
-    let mut network_magic= MAINNET_MAGIC;
-    match net_type {
-        NetworkType::TestNet => {
-            network_magic = TESTNET_MAGIC;
+/// sample: runs `ping_once` `options.samples` times and merges the outcomes (see sample_ping)
+async fn sample<F, Fut>(target: &str, mut ping_once: F, options: PingOptions) -> PingOutcome
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = PingOutcome>,
+{
+    if options.samples <= 1 {
+        return ping_once().await;
+    }
+
+    let mut con_latencies = Vec::new();
+    let mut total_latencies = Vec::new();
+    let mut failures = 0;
+    let mut last_failure = None;
+    let mut tip = None;
+    let mut tip_error = None;
+    let mut version = None;
+    let mut last_online = None;
+
+    for sample in 0..options.samples {
+        if sample > 0 {
+            tokio::time::sleep(options.sample_spacing).await;
         }
-        NetworkType::Mainnet => {
+        let outcome = ping_once().await;
+        match (outcome.online(), outcome.con_latency, outcome.total_latency) {
+            (true, Some(con), Some(total)) => {
+                con_latencies.push(con);
+                total_latencies.push(total);
+                tip = outcome.tip.clone().or(tip);
+                tip_error = outcome.tip_error.clone().or(tip_error);
+                version = outcome.version.clone().or(version);
+                last_online = Some(outcome);
+            }
+            _ => {
+                failures += 1;
+                last_failure = Some(outcome);
+            }
         }
     }
 
-    let mut pinger : Pinger = Pinger::new( in_node_vec.len());
-    let (a, _) = pinger.run();
-
-    for (id, node) in in_node_vec.iter().enumerate() {
+    let stats = LatencyStats::from_samples(&total_latencies, failures);
+    debug!("ping stats {} -- median: {} ms, loss: {}", target, stats.median.as_millis(), stats.loss());
 
-        debug!("node to ping: {} --> {}:{}",id, node.addr().to_string(),node.port());
+    if total_latencies.is_empty() {
+        let mut outcome = last_failure.unwrap_or_default();
+        outcome.stats = Some(stats);
+        return outcome;
+    }
 
-        let cpu = pinger.next_cpu();
-        pinger.fan_out()[cpu]
-            .send(MessageIn::Node {
-                name: node.addr().to_string(),
-                port: node.port(),
-                network_magic,
-                id,
-            })
-            .unwrap();
+    // the addresses and the per-family results are the ones of the last successful sample
+    let last_online = last_online.unwrap_or_default();
+    PingOutcome {
+        con_latency: Some(LatencyStats::from_samples(&con_latencies, 0).median),
+        total_latency: Some(stats.median),
+        error: None,
+        stats: Some(stats),
+        tip_error: if tip.is_some() { None } else { tip_error },
+        tip,
+        version,
+        addr: last_online.addr,
+        ipv4: last_online.ipv4,
+        ipv6: last_online.ipv6,
     }
+}
 
-    a.join().unwrap();
+/// AbortOnDrop aborts the ping task when the ping is dropped before completion (e.g. cancelled)
+struct AbortOnDrop(tokio::task::JoinHandle<PingOutcome>);
 
-    let arc_msg_vec = pinger.msg_vec().clone();
-    let msg_vec = arc_msg_vec.lock().unwrap();
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
 
-    assert_eq!(in_node_vec.len(), msg_vec.len());
+/// ping_node: pings a single node on the current runtime and crafts the MessageOut for it. The
+/// ping runs in its own task so that a misbehaving peer cannot bring down the whole engine.
+async fn ping_node(id: usize, host: String, port: u16, network_magic: u32, options: PingOptions) -> MessageOut {
+    let mut task = AbortOnDrop(tokio::spawn(sample_ping(host, port, network_magic, options)));
+    let outcome = match (&mut task.0).await {
+        Ok(outcome) => outcome,
+        Err(e) => PingOutcome::failed(None, PingError::Io(format!("ping task failed: {}", e))),
+    };
+
+    MessageOut::Latency {
+        conn_latency: outcome.con_latency,
+        total_latency: outcome.total_latency,
+        online: outcome.online(),
+        id,
+        error: outcome.error,
+        stats: outcome.stats,
+        tip: outcome.tip,
+        tip_error: outcome.tip_error,
+        version: outcome.version,
+        ipv4: outcome.ipv4,
+        ipv6: outcome.ipv6,
+    }
+}
 
-    for msg in msg_vec.iter() {
-        match msg {
-            MessageOut::Latency { conn_latency, total_latency, online, id,is_error, error  } => {
-                let n_id = id.clone();
-                in_node_vec[n_id].set_total_latency(total_latency.clone());
-                in_node_vec[n_id].set_con_latency(conn_latency.clone());
-                in_node_vec[n_id].set_online(online.clone());
-                in_node_vec[n_id].set_online_error(error.clone());
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("failed to start the ping runtime")
+}
 
-                if !online {
-                    assert_ne!("", error);
-                    assert_eq!(true, *is_error);
-                }
-            },
-            _ => {}
-        }
+/// block_on: runs the future to completion on a runtime of its own. Called from within an
+/// asynchronous runtime, where nesting runtimes panics, the future runs on a helper thread and
+/// the calling thread waits for it.
+fn block_on<F>(future: F) -> F::Output
+where
+    F: std::future::Future + Send,
+    F::Output: Send,
+{
+    if tokio::runtime::Handle::try_current().is_err() {
+        return runtime().block_on(future);
     }
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| runtime().block_on(future))
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))
+    })
+}
 
-    debug!("joing done, continuing");
+/// ping: sends a ping message to a node, return the ping result
+/// # Arguments:
+/// * `host:` the node IP address or DNS name
+/// * `port:` the TCP port of the node to ping.
+/// * `net_type:` network magic of the cardano network that the node belongs to
+/// * `options:` the timeouts, retry policy and mode of the ping
+///
+/// It blocks the calling thread, from within an asynchronous runtime prefer ping_many.
+///
+/// # Example:
+/// ```no_run
+/// use adakairust::ping::{ping, PingOptions};
+/// use adakairust::types::NetworkType;
+/// let outcome = ping("costa-rica.adakailabs.com".to_string(), 5001, NetworkType::TestNet, PingOptions::default());
+/// if let Some(error) = outcome.error {
+///     println!("offline: {}", error);
+/// }
+/// ```
+pub fn ping(host: String, port: u16, net_type: NetworkType, options: PingOptions) -> PingOutcome {
+    debug!("ping node: {}:{} ({:?})", host, port, net_type);
+    block_on(sample_ping(host, port, net_type.magic(), options))
+}
 
-    for i in 0..pinger.cpus() {
-        pinger.fan_out()[i].send(MessageIn::Quit).unwrap();
-    }
+/// ping_local: pings the local node listening on the Unix socket (e.g. a block producer only
+/// reachable through its `node.socket`), with the node-to-client handshake. The outcome is the
+/// same as for remote nodes: the connection latency is the time taken to connect to the socket,
+/// with `options.samples` the node is pinged several times (see PingOptions::samples), and with
+/// `options.query_tip` the tip of the local node is queried over chain-sync.
+/// # Arguments:
+/// * `socket_path:` the path of the socket of the node
+/// * `net_type:` the cardano network the node runs
+/// * `options:` the timeouts, retry policy and mode of the ping
+///
+/// It blocks the calling thread until the ping is done.
+///
+/// # Example:
+/// ```no_run
+/// use adakairust::ping::{ping_local, PingOptions};
+/// use adakairust::types::NetworkType;
+/// let options = PingOptions { query_tip: true, ..Default::default() };
+/// let outcome = ping_local("/opt/cardano/cnode/sockets/node0.socket", NetworkType::Mainnet, options);
+/// if let Some(tip) = outcome.tip {
+///     println!("local tip: slot {}", tip.slot);
+/// }
+/// ```
+#[cfg(unix)]
+pub fn ping_local<P: AsRef<Path>>(socket_path: P, net_type: NetworkType, options: PingOptions) -> PingOutcome {
+    let socket_path = socket_path.as_ref().to_path_buf();
+    debug!("ping local node: {} ({:?})", socket_path.display(), net_type);
+    let target = socket_path.display().to_string();
+    let magic = net_type.magic();
+    block_on(sample(&target, || call_ping_local(socket_path.clone(), magic, options), options))
+}
+
+/// ping_many: pings every node of the vector, running up to `options.concurrency` pings at the
+/// same time. Every node is pinged with the magic of its own network, so the vector may mix
+/// networks.
+/// It returns the same vector, in the same order, with the connection and total latencies and
+/// the online status updated.
+/// # Arguments:
+/// * `in_node_vec:` the nodes to ping
+/// * `options:` the timeouts, retry policy, mode and concurrency (at least 1) of the pings
+pub async fn ping_many(in_node_vec: Vec<Node>, options: PingOptions) -> Vec<Node> {
+    let mut results: Vec<(usize, Node)> = ping_stream(in_node_vec, options, PingControl::default()).collect().await;
+    results.sort_by_key(|(id, _)| *id);
 
     debug!("all done");
 
-    return in_node_vec
+    results.into_iter().map(|(_, node)| node).collect()
+}
 
+/// apply: records the result of the ping on the node
+fn apply(node: &mut Node, msg: MessageOut) {
+    let MessageOut::Latency { conn_latency, total_latency, online, error, stats, tip, tip_error, version, ipv4, ipv6, .. } = msg;
+    node.set_total_latency(total_latency.unwrap_or_default());
+    node.set_con_latency(conn_latency.unwrap_or_default());
+    node.set_online(online);
+    node.set_online_error(error);
+    node.set_latency_stats(stats);
+    node.set_tip(tip);
+    node.set_tip_error(tip_error);
+    node.set_version(version);
+    node.set_ipv4(ipv4);
+    node.set_ipv6(ipv6);
+    node.set_measured(true);
+}
+
+/// ping_vec: sends a ping message to each of the nodes contained in the passed vector, using the
+/// network of each node.
+/// It returns the same vector with the connection and total latencies updated.
+/// It runs ping_many on a runtime of its own and blocks the calling thread, from within an
+/// asynchronous runtime prefer ping_many.
+/// # Arguments:
+/// * `in_node_vec:` the nodes to ping
+/// * `options:` the timeouts, retry policy, mode and concurrency of the pings
+pub fn ping_vec(in_node_vec: Vec<Node>, options: PingOptions) -> Vec<Node> {
+    block_on(ping_many(in_node_vec, options))
 }
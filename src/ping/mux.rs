@@ -0,0 +1,93 @@
+use std::io;
+use std::io::{Error, ErrorKind};
+use std::time::Instant;
+
+use cardano_ouroboros_network::{Agency, Protocol};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// MUX_HEADER_SIZE is the size of the header of every mux segment: timestamp (u32), mini-protocol
+/// id and mode bit (u16) and payload length (u16)
+const MUX_HEADER_SIZE: usize = 8;
+
+/// RESPONDER_BIT is set in the mini-protocol id of the segments sent by the responder side
+const RESPONDER_BIT: u16 = 0x8000;
+
+/// Channel is an asynchronous multiplexer connection to a cardano node. It runs the mini-protocols
+/// of cardano_ouroboros_network (Protocol implementations) one after the other on the same stream.
+pub(crate) struct Channel<S> {
+    stream: S,
+    start_time: Instant,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Channel<S> {
+    /// new: returns a channel over an established stream
+    pub fn new(stream: S) -> Channel<S> {
+        Channel {
+            stream,
+            start_time: Instant::now(),
+        }
+    }
+
+    /// execute: runs the protocol until it has no agency left. The outcome of the protocol is read
+    /// from the protocol itself once execute returns.
+    pub async fn execute<P: Protocol + ?Sized>(&mut self, protocol: &mut P) -> io::Result<()> {
+        loop {
+            let agency = protocol.agency();
+            if agency == Agency::None {
+                return Ok(());
+            }
+
+            if agency == protocol.role() {
+                match protocol.send_data() {
+                    Some(payload) => self.send(protocol.protocol_id(), &payload).await?,
+                    None => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!("protocol {:04x} has agency but nothing to send", protocol.protocol_id()),
+                        ))
+                    }
+                }
+            } else {
+                let payload = self.recv(protocol.protocol_id()).await?;
+                protocol.receive_data(payload);
+            }
+        }
+    }
+
+    /// send: writes a single segment carrying the payload for the mini-protocol
+    pub async fn send(&mut self, protocol_id: u16, payload: &[u8]) -> io::Result<()> {
+        if payload.len() > u16::MAX as usize {
+            return Err(Error::new(ErrorKind::InvalidInput, "mux payload too large"));
+        }
+
+        let mut segment = Vec::with_capacity(MUX_HEADER_SIZE + payload.len());
+        segment.extend_from_slice(&(self.start_time.elapsed().as_micros() as u32).to_be_bytes());
+        segment.extend_from_slice(&protocol_id.to_be_bytes());
+        segment.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        segment.extend_from_slice(payload);
+
+        trace!("tx segment: {:04x} {} bytes", protocol_id, payload.len());
+        self.stream.write_all(&segment).await?;
+        self.stream.flush().await
+    }
+
+    /// recv: reads segments until one for the mini-protocol arrives and returns its payload.
+    /// Segments of other mini-protocols (e.g. started by the peer) are discarded.
+    pub async fn recv(&mut self, protocol_id: u16) -> io::Result<Vec<u8>> {
+        loop {
+            let mut header = [0u8; MUX_HEADER_SIZE];
+            self.stream.read_exact(&mut header).await?;
+
+            let id = u16::from_be_bytes([header[4], header[5]]);
+            let length = u16::from_be_bytes([header[6], header[7]]) as usize;
+
+            let mut payload = vec![0u8; length];
+            self.stream.read_exact(&mut payload).await?;
+            trace!("rx segment: {:04x} {} bytes", id, length);
+
+            if id & !RESPONDER_BIT == protocol_id & !RESPONDER_BIT {
+                return Ok(payload);
+            }
+        }
+    }
+}
@@ -3,13 +3,29 @@
 
 #[cfg(test)]
 mod tests {
-    use std::{env, thread, time};
+    use std::env;
+    use std::net::{SocketAddr, TcpListener};
     //use test_env_log::test;
-        use std::sync::Once;
+    use std::sync::Once;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use futures::StreamExt;
+    use serde_cbor::Value;
+
+    use cardano_ouroboros_network::Protocol;
 
     use crate::node::Node;
-    use crate::ping::{MessageIn, ping, ping_vec, Pinger};
-    use crate::types::NetworkType;
+    use crate::ping::family::connect_addrs;
+    use crate::ping::handshake::{Handshake, Suite};
+    use crate::ping::mock::{closed_port, local_node, MockConfig, MockNode, Reply};
+    use crate::ping::{
+        best_tip, broken_ipv6, lagging, ping, ping_iter, ping_local, ping_many, ping_stream, ping_vec, tip_lags, version_histogram, CancelHandle,
+        AddressFamily, KeepAliveOptions, KeepAliveSession, LatencyStats, NegotiatedVersion, PingControl, PingError, PingMode, PingOptions,
+        Progress, RttSample, RttWindow, SessionState, Tip, HAPPY_EYEBALLS_DELAY,
+    };
+    use crate::ping::{ping_families, runtime};
+    use crate::types::{AdakaiError, NetworkType, MAINNET_MAGIC};
 
     extern crate pretty_env_logger;
 
@@ -22,155 +38,130 @@ mod tests {
         });
     }
 
-    const JSON_TESTNET_NODE_TEST_BAD_0: &str = r#"
-    {
-      "addr": "costa-rica.adakailabs.com",
-      "port": 2323,
-      "continent": "North America",
-      "state": "CR"
-    }"#;
-
+    #[test]
+    fn test_ping_node_bad_0() {
+        initialize();
+        let outcome = ping("127.0.0.1".to_string(), closed_port(), NetworkType::TestNet, PingOptions::default());
 
+        assert!(!outcome.online());
 
-    const JSON_TESTNET_NODE_TEST_GOOD_0: &str = r#"
-    {
-      "addr": "costa-rica.adakailabs.com",
-      "port": 5001,
-      "continent": "North America",
-      "state": "CR"
-    }"#;
+        info!("the error: {}", outcome.error.unwrap())
 
-    const JSON_TESTNET_NODE_TEST_GOOD_1: &str = r#"
-        {
-      "addr": "north-america.relays-new.cardano-testnet.iohkdev.io",
-      "port": 3001,
-      "continent": "Europe",
-      "state": "IE"
-    }"#;
+    }
 
     #[test]
-    fn test_ping_node_bad_0() {
+    fn test_ping_node_magic_mismatch() {
         initialize();
-        let node = Node::new_from_json(NetworkType::Mainnet, JSON_TESTNET_NODE_TEST_BAD_0.to_string()).unwrap();
-        let host = node.addr().to_string();
-        let port = node.port();
-        let (_,_,is_error, the_error) = ping(host, port, NetworkType::TestNet);
-
-        assert_eq!(true, is_error);
-
-        info!("the error: {}", the_error)
+        let mock = MockNode::accepting(NetworkType::Mainnet);
+        let outcome = ping("127.0.0.1".to_string(), mock.port(), NetworkType::TestNet, PingOptions::default());
 
+        assert!(!outcome.online());
+        assert!(matches!(&outcome.error, Some(PingError::HandshakeRefused(e)) if e.contains("NetworkMagic")), "{:?}", outcome.error);
     }
 
 
     #[test]
     fn test_ping_node_good_0() {
         initialize();
-        let node = Node::new_from_json(NetworkType::Mainnet, JSON_TESTNET_NODE_TEST_GOOD_0.to_string()).unwrap();
-        let host = node.addr().to_string();
-        let port = node.port();
-
-        let (con_duration,total_duration,is_error, the_error) = ping(host, port, NetworkType::TestNet);
+        let mock = MockNode::start(MockConfig {
+            delay: Duration::from_millis(20),
+            magic: Some(NetworkType::TestNet.magic()),
+            ..Default::default()
+        });
 
-        let x = con_duration.as_millis();
-        let y = total_duration.as_millis();
-        assert!(x > 10, "a = {}, b = {} ", x.to_string(), y.to_string());
-        assert!(y > 10, "a = {}, b = {} ", x, y);
+        let outcome = ping("127.0.0.1".to_string(), mock.port(), NetworkType::TestNet, PingOptions::default());
+        assert_eq!(outcome.total_latency, outcome.result().ok());
 
-        if is_error  {
+        if let Some(the_error) = outcome.error {
             panic!("error: {}", the_error)
         }
+
+        let x = outcome.con_latency.unwrap().as_millis();
+        let y = outcome.total_latency.unwrap().as_millis();
+        assert!(x <= y, "a = {}, b = {} ", x, y);
+        assert!(y >= 20, "a = {}, b = {} ", x, y);
     }
 
     #[test]
     fn test_ping_node_good_1() {
+        // a node that only speaks an old version
+        let mock = MockNode::start(MockConfig {
+            version: Some(7),
+            magic: Some(NetworkType::TestNet.magic()),
+            ..Default::default()
+        });
 
-        let node = Node::new_from_json(NetworkType::Mainnet, JSON_TESTNET_NODE_TEST_GOOD_1.to_string()).unwrap();
-
-        let host = node.addr().to_string();
-        let port = node.port();
-
-        let (con_duration,total_duration,is_error, the_error) = ping(host, port, NetworkType::TestNet);
-
-        let x = con_duration.as_millis();
-        let y = total_duration.as_millis();
-        assert!(x > 10, "a = {}, b = {} ", x.to_string(), y.to_string());
-        assert!(y > 10, "a = {}, b = {} ", x, y);
+        let outcome = ping("127.0.0.1".to_string(), mock.port(), NetworkType::TestNet, PingOptions::default());
 
-        if is_error  {
+        if let Some(the_error) = outcome.error {
             panic!("error: {}", the_error)
         }
-    }
-
-    #[test]
-    fn test_channels() {
-        //Arc<RwLock<&mut StompSession>>
-        let mut pinger = Pinger::new(num_cpus::get());
-        let (_, _) = pinger.run();
-
-        for i in 0..pinger.cpus() {
-            pinger.fan_out()[i]
-                .send(MessageIn::Node {
-                    name: "adakai".to_string(),
-                    port: 2,
-                    network_magic: 0,
-                    id:0
-                })
-                .unwrap();
-        }
 
-        for i in 0..pinger.cpus() {
-            pinger.fan_out()[i].send(MessageIn::Quit).unwrap();
-        }
-
-        thread::sleep(time::Duration::from_secs(5));
+        let x = outcome.con_latency.unwrap();
+        let y = outcome.total_latency.unwrap();
+        assert!(x <= y, "a = {:?}, b = {:?} ", x, y);
     }
 
     #[test]
+    #[allow(clippy::unused_enumerate_index, clippy::to_string_in_format_args)]
     fn ping_vector() {
         initialize();
         const VEC_SIZE: usize= 20;
+        let mock = MockNode::start(MockConfig {
+            delay: Duration::from_millis(20),
+            magic: Some(NetworkType::TestNet.magic()),
+            ..Default::default()
+        });
         let mut node_vec = Vec::new();
 
         for _ in 0..VEC_SIZE {
-            node_vec.push(Node::new_from_json(NetworkType::TestNet, JSON_TESTNET_NODE_TEST_GOOD_1.to_string()).unwrap());
+            node_vec.push(mock.node(NetworkType::TestNet));
         }
 
-        let new_vec = ping_vec(node_vec, NetworkType::TestNet);
+        let new_vec = ping_vec(node_vec, PingOptions::default());
 
         assert_eq!(new_vec.len(), VEC_SIZE);
+        assert_eq!(VEC_SIZE, mock.connections());
 
         for (_,node) in new_vec.iter().enumerate() {
             info!("node: {} {} -->", node.addr().to_string(), node.con_latency().as_millis());
 
-            //assert_eq!(true,node.online() );
-
-            if node.online() {
-                assert!(node.con_latency().as_millis() > 10, "con_latency: {}", node.con_latency().as_millis());
-                assert!(node.total_latency().as_millis() > 10);
-            }else {
-                error!("offline: {} ",node.addr() )
-            }
+            assert!(node.online(), "{:?}", node.online_error());
+            assert!(node.con_latency() <= node.total_latency());
+            assert!(node.total_latency().as_millis() >= 20);
         }
 
     }
 
+    #[tokio::test(flavor = "current_thread")]
+    async fn ping_vector_from_async_context() {
+        // the blocking calls must not nest runtimes when called from an asynchronous task
+        let mock = MockNode::accepting(NetworkType::Mainnet);
+        let new_vec = ping_vec(vec![mock.node(NetworkType::Mainnet)], PingOptions::default());
+        assert!(new_vec[0].online(), "{:?}", new_vec[0].online_error());
+        let outcome = ping("127.0.0.1".to_string(), mock.port(), NetworkType::Mainnet, PingOptions::default());
+        assert!(outcome.online(), "{:?}", outcome.error);
+    }
+
     #[test]
+    #[allow(clippy::unused_enumerate_index)]
     fn ping_vector_with_error() {
         initialize();
         const VEC_SIZE: usize= 20;
+        let good = MockNode::accepting(NetworkType::TestNet);
+        let bad = MockNode::replying(Reply::RefuseVersion(vec![1, 2]));
         let mut node_vec = Vec::new();
 
         // Inject correct nodes
         for _ in 0..VEC_SIZE-1 {
-            node_vec.push(Node::new_from_json(NetworkType::TestNet, JSON_TESTNET_NODE_TEST_GOOD_1.to_string()).unwrap());
+            node_vec.push(good.node(NetworkType::TestNet));
         }
 
         // Inject bad node
-        node_vec.push(Node::new_from_json(NetworkType::TestNet, JSON_TESTNET_NODE_TEST_BAD_0.to_string()).unwrap());
+        node_vec.push(bad.node(NetworkType::TestNet));
 
 
-        let new_vec = ping_vec(node_vec, NetworkType::TestNet);
+        let new_vec = ping_vec(node_vec, PingOptions::default());
 
         assert_eq!(new_vec.len(), VEC_SIZE);
 
@@ -178,15 +169,769 @@ mod tests {
 
         for (_,node) in new_vec.iter().enumerate() {
             if node.online() {
-                assert!(node.con_latency().as_millis() > 10, "con_latency: {}", node.con_latency().as_millis());
-                assert!(node.total_latency().as_millis() > 10);
+                assert!(node.con_latency() <= node.total_latency());
             }else {
                 error_count += 1;
-                info!("expected error: {}" , node.online_error());
-                assert_ne!("", node.online_error());
+                info!("expected error: {}" , node.online_error().unwrap());
+                assert_eq!(Some(PingError::VersionMismatch(vec![1, 2])), node.online_error());
             }
         }
         assert_eq!(1, error_count);
     }
 
+    #[test]
+    fn ping_many_keeps_input_order() {
+        initialize();
+        const VEC_SIZE: usize = 40;
+        let refused = closed_port();
+        let hang_up = MockNode::replying(Reply::HangUp);
+
+        let node_vec: Vec<Node> = (0..VEC_SIZE)
+            .map(|i| local_node(if i % 2 == 0 { refused } else { hang_up.port() }))
+            .collect();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let new_vec = rt.block_on(ping_many(node_vec, PingOptions { concurrency: 8, ..Default::default() }));
+
+        assert_eq!(VEC_SIZE, new_vec.len());
+        for (i, node) in new_vec.iter().enumerate() {
+            assert!(!node.online());
+            if i % 2 == 0 {
+                assert_eq!(refused, node.port());
+                assert!(matches!(node.online_error(), Some(PingError::Connect(_))), "{:?}", node.online_error());
+            } else {
+                // the connection was established: the handshake is what failed
+                assert_eq!(hang_up.port(), node.port());
+                assert!(matches!(node.online_error(), Some(PingError::Io(_))), "{:?}", node.online_error());
+            }
+        }
+    }
+
+    #[test]
+    fn ping_vec_local_errors() {
+        initialize();
+        let node_vec = vec![local_node(closed_port()), local_node(MockNode::replying(Reply::HangUp).port())];
+
+        let new_vec = ping_vec(node_vec, PingOptions::default());
+        assert_eq!(2, new_vec.len());
+        assert!(new_vec.iter().all(|n| !n.online()));
+    }
+
+    #[test]
+    fn ping_scripted_replies() {
+        initialize();
+        let ping_mock = |mock: &MockNode| ping("127.0.0.1".to_string(), mock.port(), NetworkType::Mainnet, PingOptions::default());
+
+        let outcome = ping_mock(&MockNode::replying(Reply::Refuse("no thanks".to_string())));
+        assert_eq!(Some(PingError::HandshakeRefused("no thanks".to_string())), outcome.error);
+
+        let outcome = ping_mock(&MockNode::replying(Reply::Drop));
+        assert!(matches!(outcome.error, Some(PingError::Io(_))), "{:?}", outcome.error);
+        assert!(outcome.con_latency.is_some());
+
+        let outcome = ping_mock(&MockNode::start(MockConfig { version: Some(99), ..Default::default() }));
+        assert!(matches!(outcome.error, Some(PingError::VersionMismatch(_))), "{:?}", outcome.error);
+
+        let mock = MockNode::accepting(NetworkType::Mainnet);
+        assert!(ping_mock(&mock).online());
+        assert_eq!(1, mock.connections());
+    }
+
+    #[test]
+    fn ping_typed_errors() {
+        initialize();
+        let outcome = ping("127.0.0.1".to_string(), closed_port(), NetworkType::Mainnet, PingOptions::default());
+        assert!(matches!(outcome.error, Some(PingError::Connect(_))), "{:?}", outcome.error);
+        assert_eq!(None, outcome.con_latency);
+        assert_eq!(None, outcome.total_latency);
+        match outcome.result() {
+            Err(AdakaiError::Network(e)) => assert_eq!(Some("connect"), e.downcast_ref::<PingError>().map(PingError::kind)),
+            result => panic!("{:?}", result),
+        }
+
+        let outcome = ping("127.0.0.1".to_string(), MockNode::replying(Reply::HangUp).port(), NetworkType::Mainnet, PingOptions::default());
+        assert!(matches!(outcome.error, Some(PingError::Io(_))), "{:?}", outcome.error);
+        assert!(outcome.con_latency.is_some());
+        assert_eq!(None, outcome.total_latency);
+
+        // an IP literal is not looked up, so it has no IPv6 address without any DNS query
+        let options = PingOptions { family: AddressFamily::V6, retries: 0, ..Default::default() };
+        let outcome = ping("127.0.0.1".to_string(), 3001, NetworkType::Mainnet, options);
+        assert!(matches!(outcome.error, Some(PingError::Resolve(_))), "{:?}", outcome.error);
+        assert_eq!(None, outcome.con_latency);
+    }
+
+    fn handshake_reply(reply: Value) -> Result<u64, PingError> {
+        let mut handshake = Handshake::with_suite(MAINNET_MAGIC, Suite::NodeToNode);
+        handshake.send_data().unwrap();
+        handshake.receive_data(serde_cbor::to_vec(&reply).unwrap());
+        handshake.outcome().map(|accepted| accepted.version)
+    }
+
+    #[test]
+    fn handshake_accept() {
+        let accept = Value::Array(vec![
+            Value::Integer(1),
+            Value::Integer(13),
+            Value::Array(vec![Value::Integer(MAINNET_MAGIC as i128), Value::Bool(false), Value::Integer(1), Value::Bool(false)]),
+        ]);
+        assert_eq!(Ok(13), handshake_reply(accept));
+    }
+
+    #[test]
+    fn handshake_refusals() {
+        let mismatch = Value::Array(vec![
+            Value::Integer(2),
+            Value::Array(vec![Value::Integer(0), Value::Array(vec![Value::Integer(15), Value::Integer(16)])]),
+        ]);
+        assert_eq!(Err(PingError::VersionMismatch(vec![15, 16])), handshake_reply(mismatch));
+
+        let refused = Value::Array(vec![
+            Value::Integer(2),
+            Value::Array(vec![Value::Integer(2), Value::Integer(13), Value::Text("version data mismatch".to_string())]),
+        ]);
+        assert_eq!(Err(PingError::HandshakeRefused("version data mismatch".to_string())), handshake_reply(refused));
+
+        let wrong_magic = Value::Array(vec![
+            Value::Integer(1),
+            Value::Integer(10),
+            Value::Array(vec![Value::Integer(1), Value::Bool(false)]),
+        ]);
+        assert!(matches!(handshake_reply(wrong_magic), Err(PingError::HandshakeRefused(_))));
+
+        let mut handshake = Handshake::with_suite(MAINNET_MAGIC, Suite::NodeToNode);
+        handshake.send_data().unwrap();
+        handshake.receive_data(vec![0xff, 0x00]);
+        assert!(matches!(handshake.outcome(), Err(PingError::Io(_))));
+    }
+
+    #[test]
+    fn handshake_proposes_versions() {
+        let mut handshake = Handshake::with_suite(MAINNET_MAGIC, Suite::NodeToNode);
+        let propose: Value = serde_cbor::from_slice(&handshake.send_data().unwrap()).unwrap();
+
+        match propose {
+            Value::Array(items) => {
+                assert_eq!(Value::Integer(0), items[0]);
+                match &items[1] {
+                    Value::Map(versions) => {
+                        assert!(versions.contains_key(&Value::Integer(13)));
+                        assert!(versions.contains_key(&Value::Integer(14)));
+                    }
+                    _ => panic!("expected a version table"),
+                }
+            }
+            _ => panic!("expected an array"),
+        }
+    }
+
+    #[test]
+    fn ping_options_defaults() {
+        let options = PingOptions::default();
+        assert_eq!(Duration::from_secs(5), options.connect_timeout);
+        assert_eq!(Duration::from_secs(5), options.handshake_timeout);
+        assert_eq!(2, options.retries);
+        assert_eq!(PingMode::Handshake, options.mode);
+        assert_eq!(crate::ping::DEFAULT_CONCURRENCY, options.concurrency);
+    }
+
+    #[test]
+    fn ping_options_backoff() {
+        let options = PingOptions {
+            backoff_base: Duration::from_millis(100),
+            backoff_max: Duration::from_millis(500),
+            jitter: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(Duration::from_millis(100), options.backoff(0));
+        assert_eq!(Duration::from_millis(200), options.backoff(1));
+        assert_eq!(Duration::from_millis(400), options.backoff(2));
+        assert_eq!(Duration::from_millis(500), options.backoff(3));
+        assert_eq!(Duration::from_millis(500), options.backoff(40));
+
+        let options = PingOptions { jitter: 0.5, ..options };
+        for retry in 0..5 {
+            let wait = options.backoff(retry);
+            let max = Duration::from_millis(100 * 2u64.pow(retry)).min(Duration::from_millis(500));
+            assert!(wait <= max && wait >= max / 2, "retry {}: {:?}", retry, wait);
+        }
+    }
+
+    /// silent_port returns the port of a mock node that accepts connections and never answers
+    fn silent_port() -> u16 {
+        MockNode::replying(Reply::Silent).port()
+    }
+
+    #[test]
+    fn ping_handshake_timeout() {
+        initialize();
+        let options = PingOptions { handshake_timeout: Duration::from_millis(200), ..Default::default() };
+
+        let start = Instant::now();
+        let outcome = ping("127.0.0.1".to_string(), silent_port(), NetworkType::Mainnet, options);
+        assert!(matches!(outcome.error, Some(PingError::Timeout(_))), "{:?}", outcome.error);
+        assert!(outcome.con_latency.is_some());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn ping_tcp_connect_mode() {
+        initialize();
+        let options = PingOptions { mode: PingMode::TcpConnect, ..Default::default() };
+
+        let outcome = ping("127.0.0.1".to_string(), silent_port(), NetworkType::Mainnet, options);
+        assert!(outcome.online(), "{:?}", outcome.error);
+        assert_eq!(outcome.con_latency, outcome.total_latency);
+
+        let outcome = ping("127.0.0.1".to_string(), closed_port(), NetworkType::Mainnet, options);
+        assert!(matches!(outcome.error, Some(PingError::Connect(_))), "{:?}", outcome.error);
+    }
+
+    #[test]
+    fn ping_retries_with_backoff() {
+        initialize();
+        let options = PingOptions {
+            retries: 3,
+            backoff_base: Duration::from_millis(50),
+            jitter: 0.0,
+            ..Default::default()
+        };
+
+        // 50 + 100 + 200 ms of backoff before giving up
+        let start = Instant::now();
+        let outcome = ping("127.0.0.1".to_string(), closed_port(), NetworkType::Mainnet, options);
+        assert!(!outcome.online());
+        assert!(start.elapsed() >= Duration::from_millis(350), "{:?}", start.elapsed());
+
+        let options = PingOptions { retries: 0, ..options };
+        let start = Instant::now();
+        ping("127.0.0.1".to_string(), closed_port(), NetworkType::Mainnet, options);
+        assert!(start.elapsed() < Duration::from_millis(50), "{:?}", start.elapsed());
+    }
+
+    #[test]
+    fn ping_vec_mixes_networks() {
+        initialize();
+        let networks = [NetworkType::Preprod, NetworkType::Preview, NetworkType::Custom(42), NetworkType::Mainnet];
+
+        let mut node_vec: Vec<Node> = networks
+            .iter()
+            .map(|network| {
+                MockNode::accepting(*network).node(*network)
+            })
+            .collect();
+
+        // a preview node wrongly declared as preprod is refused
+        node_vec.push(MockNode::accepting(NetworkType::Preview).node(NetworkType::Preprod));
+
+        let new_vec = ping_vec(node_vec, PingOptions::default());
+        for node in &new_vec[..networks.len()] {
+            assert!(node.online(), "{}: {:?}", node.network_type(), node.online_error());
+        }
+        assert!(matches!(new_vec[networks.len()].online_error(), Some(PingError::HandshakeRefused(_))));
+    }
+
+    #[test]
+    fn latency_stats_from_samples() {
+        let ms = Duration::from_millis;
+        let samples: Vec<Duration> = [50, 10, 40, 20, 30].iter().map(|m| ms(*m)).collect();
+
+        let stats = LatencyStats::from_samples(&samples, 1);
+        assert_eq!(6, stats.samples);
+        assert_eq!(1, stats.failures);
+        assert_eq!(ms(10), stats.min);
+        assert_eq!(ms(30), stats.median);
+        assert_eq!(ms(30), stats.mean);
+        assert_eq!(ms(50), stats.p95);
+        // population standard deviation of 10, 20, 30, 40, 50 ms
+        assert_eq!(14142, stats.jitter.as_micros());
+        assert!((stats.loss() - 1.0 / 6.0).abs() < 1e-9);
+
+        let stats = LatencyStats::from_samples(&[ms(10), ms(20)], 0);
+        assert_eq!(ms(15), stats.median);
+        assert_eq!(ms(20), stats.p95);
+
+        let stats = LatencyStats::from_samples(&[], 3);
+        assert_eq!(Duration::default(), stats.median);
+        assert_eq!(1.0, stats.loss());
+        assert_eq!(0.0, LatencyStats::default().loss());
+    }
+
+    #[test]
+    fn ping_many_samples() {
+        initialize();
+        let options = PingOptions {
+            mode: PingMode::TcpConnect,
+            samples: 4,
+            sample_spacing: Duration::from_millis(10),
+            retries: 0,
+            ..Default::default()
+        };
+
+        let node_vec = vec![local_node(silent_port()), local_node(closed_port())];
+        let new_vec = ping_vec(node_vec, options);
+
+        assert!(new_vec[0].online());
+        let stats = new_vec[0].latency_stats().unwrap();
+        assert_eq!(4, stats.samples);
+        assert_eq!(0, stats.failures);
+        assert!(stats.min <= stats.median && stats.median <= stats.p95);
+        assert_eq!(stats.median, new_vec[0].total_latency());
+
+        assert!(!new_vec[1].online());
+        assert!(matches!(new_vec[1].online_error(), Some(PingError::Connect(_))));
+        assert_eq!(1.0, new_vec[1].latency_stats().unwrap().loss());
+
+        // a single sample records no statistics
+        let new_vec = ping_vec(vec![local_node(silent_port())], PingOptions { samples: 1, ..options });
+        assert!(new_vec[0].latency_stats().is_none());
+    }
+
+    #[test]
+    fn ping_stream_progress() {
+        initialize();
+        let refused = closed_port();
+        let node_vec: Vec<Node> = (0..6)
+            .map(|i| local_node(if i < 4 { silent_port() } else { refused }))
+            .collect();
+
+        let reports: Arc<Mutex<Vec<Progress>>> = Arc::new(Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        let control = PingControl {
+            progress: Some(Box::new(move |p| sink.lock().unwrap().push(p))),
+            ..Default::default()
+        };
+        let options = PingOptions { mode: PingMode::TcpConnect, retries: 0, ..Default::default() };
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let mut results: Vec<(usize, Node)> = rt.block_on(ping_stream(node_vec, options, control).collect());
+
+        results.sort_by_key(|(id, _)| *id);
+        assert_eq!((0..6).collect::<Vec<usize>>(), results.iter().map(|(id, _)| *id).collect::<Vec<usize>>());
+        assert!(results.iter().all(|(_, n)| n.measured()));
+        assert!(results.iter().all(|(id, n)| n.online() == (*id < 4)));
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(6, reports.len());
+        assert!(reports.iter().all(|p| p.total == 6));
+        assert_eq!((1..=6).collect::<Vec<usize>>(), reports.iter().map(|p| p.done).collect::<Vec<usize>>());
+        assert_eq!(Progress { done: 6, total: 6, failed: 2 }, reports[5]);
+    }
+
+    #[test]
+    fn ping_stream_cancel() {
+        initialize();
+        let silent = silent_port();
+        let refused = closed_port();
+        let mut node_vec: Vec<Node> = vec![local_node(refused)];
+        node_vec.extend((0..5).map(|_| local_node(silent)));
+
+        let cancel = CancelHandle::new();
+        let control = PingControl { cancel: Some(cancel.clone()), ..Default::default() };
+        let options = PingOptions { handshake_timeout: Duration::from_secs(30), retries: 0, concurrency: 2, ..Default::default() };
+
+        let start = Instant::now();
+        let results: Vec<(usize, Node)> = ping_iter(node_vec, options, control)
+            .inspect(|_| cancel.cancel())
+            .collect();
+        assert!(start.elapsed() < Duration::from_secs(5), "{:?}", start.elapsed());
+        assert!(cancel.is_cancelled());
+
+        // the first node yielded is the only one measured: the refused one
+        assert_eq!(6, results.len());
+        assert_eq!(0, results[0].0);
+        assert!(results[0].1.measured());
+        assert!(results[1..].iter().all(|(_, n)| !n.measured() && !n.online()));
+        assert_eq!(vec![1, 2, 3, 4, 5], results[1..].iter().map(|(id, _)| *id).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn ping_stream_deadline() {
+        initialize();
+        let silent = silent_port();
+        let node_vec: Vec<Node> = (0..3).map(|_| local_node(silent)).collect();
+
+        let control = PingControl { deadline: Some(Duration::from_millis(200)), ..Default::default() };
+        let options = PingOptions { handshake_timeout: Duration::from_secs(30), ..Default::default() };
+
+        let start = Instant::now();
+        let results: Vec<(usize, Node)> = ping_iter(node_vec, options, control).collect();
+        assert!(start.elapsed() < Duration::from_secs(5), "{:?}", start.elapsed());
+        assert_eq!(3, results.len());
+        assert!(results.iter().all(|(_, n)| !n.measured()));
+    }
+
+    fn tip(slot: u64) -> Tip {
+        Tip {
+            slot,
+            block_no: slot / 20,
+            hash: format!("{:064x}", slot),
+        }
+    }
+
+    #[test]
+    fn ping_query_tip() {
+        initialize();
+        let options = PingOptions { query_tip: true, ..Default::default() };
+        let mock = MockNode::start(MockConfig { tip: Some(tip(1000)), ..Default::default() });
+
+        let outcome = ping("127.0.0.1".to_string(), mock.port(), NetworkType::Mainnet, options);
+        assert!(outcome.online(), "{:?}", outcome.error);
+        assert_eq!(Some(tip(1000)), outcome.tip);
+
+        // not queried by default
+        let outcome = ping("127.0.0.1".to_string(), mock.port(), NetworkType::Mainnet, PingOptions::default());
+        assert_eq!(None, outcome.tip);
+        assert_eq!(None, outcome.tip_error);
+
+        // a node that does not answer chain-sync is online, without a tip but with the reason
+        let options = PingOptions { handshake_timeout: Duration::from_millis(200), ..options };
+        let mock = MockNode::accepting(NetworkType::Mainnet);
+        let outcome = ping("127.0.0.1".to_string(), mock.port(), NetworkType::Mainnet, options);
+        assert!(outcome.online());
+        assert_eq!(None, outcome.tip);
+        assert_eq!(Some("timeout"), outcome.tip_error.as_ref().map(|e| e.kind()));
+
+        let new_vec = ping_vec(vec![mock.node(NetworkType::Mainnet)], PingOptions { samples: 2, ..options });
+        assert!(new_vec[0].online());
+        assert_eq!(Some("timeout"), new_vec[0].tip_error().map(|e| e.kind()));
+    }
+
+    #[test]
+    fn tip_cbor_round_trip() {
+        let value = tip(4242).to_cbor();
+        assert_eq!(Some(tip(4242)), Tip::from_cbor(&value));
+
+        let origin = Value::Array(vec![Value::Array(vec![]), Value::Integer(0)]);
+        assert_eq!(Some(Tip::default()), Tip::from_cbor(&origin));
+        assert_eq!(None, Tip::from_cbor(&Value::Integer(3)));
+    }
+
+    #[test]
+    fn ping_vec_flags_lagging_nodes() {
+        initialize();
+        let mocks: Vec<MockNode> = [5000, 4990, 1000]
+            .iter()
+            .map(|slot| MockNode::start(MockConfig { tip: Some(tip(*slot)), ..Default::default() }))
+            .collect();
+        let mut node_vec: Vec<Node> = mocks.iter().map(|m| m.node(NetworkType::Mainnet)).collect();
+        node_vec.push(local_node(closed_port()));
+
+        let options = PingOptions { query_tip: true, retries: 0, ..Default::default() };
+        let new_vec = ping_vec(node_vec, options);
+
+        assert_eq!(Some(tip(5000)), best_tip(&new_vec));
+        assert_eq!(vec![Some(0), Some(10), Some(4000), None], tip_lags(&new_vec));
+        assert_eq!(vec![2], lagging(&new_vec, 100));
+        assert_eq!(vec![1, 2], lagging(&new_vec, 5));
+        assert_eq!(None, best_tip(&[]));
+    }
+
+    #[test]
+    fn rtt_window_rolls() {
+        let mut window = RttWindow::new(3);
+        assert!(window.is_empty());
+        for cookie in 1..=5u16 {
+            window.push(RttSample { cookie, rtt: Duration::from_millis(cookie as u64 * 10) });
+        }
+        assert_eq!(3, window.len());
+        assert_eq!(vec![3, 4, 5], window.samples().iter().map(|s| s.cookie).collect::<Vec<u16>>());
+        assert_eq!(5, window.last().unwrap().cookie);
+        assert_eq!(Duration::from_millis(40), window.stats().median);
+        assert_eq!(Duration::from_millis(30), window.stats().min);
+    }
+
+    #[test]
+    fn keep_alive_session() {
+        initialize();
+        let mock = MockNode::accepting(NetworkType::Preview);
+        let mut node = mock.node(NetworkType::Preview);
+        let options = KeepAliveOptions { interval: Duration::from_millis(20), window: 4, ..Default::default() };
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let session = KeepAliveSession::start(&node, options);
+            while session.window().len() < options.window {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            assert_eq!(SessionState::Connected, session.state());
+
+            session.update(&mut node);
+            assert!(node.online());
+            assert_eq!(4, node.rtt().unwrap().len());
+
+            tokio::time::sleep(Duration::from_millis(60)).await;
+            let window = session.window();
+            assert_eq!(4, window.len());
+            assert!(window.last().unwrap().cookie > 4);
+
+            session.close();
+            assert_eq!(SessionState::Closed, session.ended().await);
+        });
+        assert_eq!(1, mock.connections());
+    }
+
+    #[test]
+    fn keep_alive_detects_drop() {
+        initialize();
+        let mock = MockNode::start(MockConfig { keep_alive_replies: Some(3), ..Default::default() });
+        let mut node = mock.node(NetworkType::Mainnet);
+        let options = KeepAliveOptions { interval: Duration::from_millis(10), ..Default::default() };
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let session = KeepAliveSession::start(&node, options);
+            let state = session.ended().await;
+            assert!(matches!(state, SessionState::Dropped(PingError::Io(_))), "{:?}", state);
+            assert_eq!(3, session.window().len());
+
+            session.update(&mut node);
+            assert!(!node.online());
+            assert!(matches!(node.online_error(), Some(PingError::Io(_))));
+        });
+
+        // close does not wait for a pending keep-alive response
+        let mock = MockNode::start(MockConfig { keep_alive_silent: true, ..Default::default() });
+        rt.block_on(async {
+            let session = KeepAliveSession::start(&mock.node(NetworkType::Mainnet), options);
+            while session.state() != SessionState::Connected {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let start = std::time::Instant::now();
+            session.close();
+            assert_eq!(SessionState::Closed, session.ended().await);
+            assert!(start.elapsed() < options.response_timeout / 2, "{:?}", start.elapsed());
+        });
+
+        // a refused handshake ends the session right away
+        let mock = MockNode::replying(Reply::RefuseVersion(vec![1]));
+        rt.block_on(async {
+            let session = KeepAliveSession::start(&mock.node(NetworkType::Mainnet), options);
+            assert_eq!(SessionState::Dropped(PingError::VersionMismatch(vec![1])), session.ended().await);
+        });
+    }
+
+    #[test]
+    fn ping_records_negotiated_version() {
+        initialize();
+        let mock = MockNode::accepting(NetworkType::Preprod);
+        let outcome = ping("127.0.0.1".to_string(), mock.port(), NetworkType::Preprod, PingOptions::default());
+        assert_eq!(
+            Some(NegotiatedVersion {
+                version: 14,
+                network_magic: NetworkType::Preprod.magic(),
+                initiator_only: false,
+                peer_sharing: Some(true),
+                query: Some(false),
+                node_to_client: false,
+            }),
+            outcome.version
+        );
+
+        let mock = MockNode::start(MockConfig { version: Some(8), ..Default::default() });
+        let outcome = ping("127.0.0.1".to_string(), mock.port(), NetworkType::Mainnet, PingOptions::default());
+        let version = outcome.version.unwrap();
+        assert_eq!(8, version.version);
+        assert_eq!(None, version.peer_sharing);
+        assert_eq!(None, version.query);
+
+        let options = PingOptions { mode: PingMode::TcpConnect, ..Default::default() };
+        let outcome = ping("127.0.0.1".to_string(), mock.port(), NetworkType::Mainnet, options);
+        assert_eq!(None, outcome.version);
+    }
+
+    #[test]
+    fn ping_vec_version_histogram() {
+        initialize();
+        let v14 = MockNode::accepting(NetworkType::Mainnet);
+        let v10 = MockNode::start(MockConfig { version: Some(10), ..Default::default() });
+        let old = MockNode::replying(Reply::RefuseVersion(vec![4, 5, 6]));
+
+        let node_vec = vec![
+            v14.node(NetworkType::Mainnet),
+            v14.node(NetworkType::Mainnet),
+            v10.node(NetworkType::Mainnet),
+            old.node(NetworkType::Mainnet),
+            local_node(closed_port()),
+        ];
+        let new_vec = ping_vec(node_vec, PingOptions { retries: 0, ..Default::default() });
+
+        assert_eq!(Some(vec![4, 5, 6]), new_vec[3].offered_versions());
+        assert_eq!(None, new_vec[0].offered_versions());
+        assert_eq!(10, new_vec[2].version().unwrap().version);
+
+        let histogram = version_histogram(&new_vec);
+        assert_eq!(vec![(10, 1), (14, 2)], histogram.accepted.into_iter().collect::<Vec<(u64, usize)>>());
+        assert_eq!(vec![(4, 1), (5, 1), (6, 1)], histogram.offered.into_iter().collect::<Vec<(u64, usize)>>());
+        assert_eq!(1, histogram.mismatched);
+        assert_eq!(1, histogram.unknown);
+    }
+
+    #[test]
+    fn handshake_node_to_client() {
+        let mut handshake = Handshake::with_suite(MAINNET_MAGIC, Suite::NodeToClient);
+        let propose: Value = serde_cbor::from_slice(&handshake.send_data().unwrap()).unwrap();
+        match propose {
+            Value::Array(items) => match &items[1] {
+                Value::Map(versions) => {
+                    assert_eq!(Some(&Value::Integer(MAINNET_MAGIC as i128)), versions.get(&Value::Integer(0x8000 | 14)));
+                    assert!(versions.contains_key(&Value::Integer(0x8000 | 16)));
+                    assert!(!versions.contains_key(&Value::Integer(14)));
+                }
+                other => panic!("unexpected versions: {:?}", other),
+            },
+            other => panic!("unexpected proposal: {:?}", other),
+        }
+
+        let accept = Value::Array(vec![
+            Value::Integer(1),
+            Value::Integer(0x8000 | 16),
+            Value::Array(vec![Value::Integer(MAINNET_MAGIC as i128), Value::Bool(false)]),
+        ]);
+        handshake.receive_data(serde_cbor::to_vec(&accept).unwrap());
+        let negotiated = handshake.outcome().unwrap().negotiated();
+        assert_eq!(16, negotiated.version);
+        assert!(negotiated.node_to_client);
+        assert_eq!(Some(false), negotiated.query);
+    }
+
+    #[test]
+    fn ping_local_node() {
+        initialize();
+        let mock = MockNode::start_local(MockConfig {
+            magic: Some(NetworkType::Preview.magic()),
+            tip: Some(tip(4_200)),
+            ..Default::default()
+        });
+
+        let options = PingOptions { query_tip: true, ..Default::default() };
+        let outcome = ping_local(mock.socket_path(), NetworkType::Preview, options);
+        assert!(outcome.online(), "{:?}", outcome.error);
+        assert!(outcome.con_latency.unwrap() <= outcome.total_latency.unwrap());
+        assert_eq!(Some(tip(4_200)), outcome.tip);
+
+        let version = outcome.version.unwrap();
+        assert!(version.node_to_client);
+        assert_eq!(16, version.version);
+        assert_eq!(NetworkType::Preview.magic(), version.network_magic);
+
+        let mock = MockNode::start_local(MockConfig { version: Some(0x8000 | 12), ..Default::default() });
+        let outcome = ping_local(mock.socket_path(), NetworkType::Mainnet, PingOptions::default());
+        assert_eq!(12, outcome.version.unwrap().version);
+        assert_eq!(None, outcome.tip);
+
+        // the samples apply to local nodes too
+        let options = PingOptions { samples: 3, sample_spacing: Duration::from_millis(5), ..Default::default() };
+        let outcome = ping_local(mock.socket_path(), NetworkType::Mainnet, options);
+        assert!(outcome.online(), "{:?}", outcome.error);
+        assert_eq!(3, outcome.stats.unwrap().samples);
+        assert_eq!(4, mock.connections());
+    }
+
+    #[test]
+    fn ping_local_failures() {
+        initialize();
+        let mock = MockNode::start_local(MockConfig {
+            magic: Some(NetworkType::Preprod.magic()),
+            ..Default::default()
+        });
+        let outcome = ping_local(mock.socket_path(), NetworkType::Mainnet, PingOptions { retries: 0, ..Default::default() });
+        assert!(!outcome.online());
+        assert!(matches!(outcome.error, Some(PingError::HandshakeRefused(_))), "{:?}", outcome.error);
+        assert!(outcome.con_latency.is_some());
+
+        let missing = env::temp_dir().join("adakai-no-such-node.socket");
+        let outcome = ping_local(&missing, NetworkType::Mainnet, PingOptions { retries: 0, ..Default::default() });
+        assert!(matches!(&outcome.error, Some(PingError::Connect(e)) if e.contains("adakai-no-such-node")), "{:?}", outcome.error);
+        assert_eq!(None, outcome.con_latency);
+    }
+
+    fn socket_addr(ip: &str, port: u16) -> SocketAddr {
+        SocketAddr::new(ip.parse().unwrap(), port)
+    }
+
+    #[test]
+    fn ping_restricted_to_a_family() {
+        initialize();
+        let mock = MockNode::start_on("[::1]:0", MockConfig::default());
+
+        let options = PingOptions { family: AddressFamily::V6, ..Default::default() };
+        let outcome = ping("::1".to_string(), mock.port(), NetworkType::Mainnet, options);
+        assert!(outcome.online(), "{:?}", outcome.error);
+        assert_eq!(Some(socket_addr("::1", mock.port())), outcome.addr);
+        assert!(outcome.ipv6.unwrap().online());
+        assert_eq!(None, outcome.ipv4);
+
+        // no IPv4 address: the family is not measured
+        let options = PingOptions { family: AddressFamily::V4, retries: 0, ..Default::default() };
+        let outcome = ping("::1".to_string(), mock.port(), NetworkType::Mainnet, options);
+        assert!(matches!(outcome.error, Some(PingError::Resolve(_))), "{:?}", outcome.error);
+        assert_eq!(None, outcome.ipv4);
+
+        // the default family records the result of the family connected to
+        let mock = MockNode::accepting(NetworkType::Mainnet);
+        let outcome = ping("127.0.0.1".to_string(), mock.port(), NetworkType::Mainnet, PingOptions::default());
+        assert!(outcome.ipv4.unwrap().online());
+        assert_eq!(None, outcome.ipv6);
+    }
+
+    #[test]
+    fn ping_both_families_finds_broken_ipv6() {
+        initialize();
+        let mock = MockNode::accepting(NetworkType::Mainnet);
+        let closed_v6 = TcpListener::bind("[::1]:0").unwrap().local_addr().unwrap().port();
+        let addrs = vec![socket_addr("127.0.0.1", mock.port()), socket_addr("::1", closed_v6)];
+
+        let options = PingOptions { family: AddressFamily::Both, retries: 0, ..Default::default() };
+        let outcome = runtime().block_on(ping_families("relay", &addrs, MAINNET_MAGIC, options));
+        assert!(outcome.online(), "{:?}", outcome.error);
+        assert_eq!(Some(socket_addr("127.0.0.1", mock.port())), outcome.addr);
+
+        let ipv4 = outcome.ipv4.clone().unwrap();
+        let ipv6 = outcome.ipv6.clone().unwrap();
+        assert!(ipv4.online());
+        assert_eq!(outcome.total_latency, ipv4.total_latency);
+        assert!(!ipv6.online());
+        assert!(matches!(ipv6.error, Some(PingError::Connect(_))), "{:?}", ipv6.error);
+        assert_eq!(Some(socket_addr("::1", closed_v6)), ipv6.addr);
+
+        let mut broken = local_node(mock.port());
+        broken.set_ipv4(outcome.ipv4);
+        broken.set_ipv6(outcome.ipv6);
+        let mut dual = local_node(mock.port());
+        dual.set_ipv4(Some(ipv4.clone()));
+        dual.set_ipv6(Some(ipv4));
+        assert_eq!(vec![1], broken_ipv6(&[local_node(mock.port()), broken, dual]));
+
+        // IPv4 only nodes have no IPv6 result
+        let outcome = runtime().block_on(ping_families("relay", &addrs[..1], MAINNET_MAGIC, options));
+        assert!(outcome.online());
+        assert_eq!(None, outcome.ipv6);
+    }
+
+    #[test]
+    fn happy_eyeballs_connect() {
+        initialize();
+        let v4 = MockNode::replying(Reply::Silent);
+        let v6 = MockNode::start_on("[::1]:0", MockConfig { reply: Reply::Silent, ..Default::default() });
+        let closed_v6 = TcpListener::bind("[::1]:0").unwrap().local_addr().unwrap().port();
+
+        runtime().block_on(async {
+            // IPv6 is preferred
+            let addrs = vec![socket_addr("127.0.0.1", v4.port()), socket_addr("::1", v6.port())];
+            let stream = connect_addrs(&addrs, AddressFamily::HappyEyeballs).await.unwrap();
+            assert!(stream.peer_addr().unwrap().is_ipv6());
+
+            // a refused IPv6 address falls back to IPv4 without waiting for the attempt delay
+            let start = Instant::now();
+            let addrs = vec![socket_addr("127.0.0.1", v4.port()), socket_addr("::1", closed_v6)];
+            let stream = connect_addrs(&addrs, AddressFamily::HappyEyeballs).await.unwrap();
+            assert!(stream.peer_addr().unwrap().is_ipv4());
+            assert!(start.elapsed() < HAPPY_EYEBALLS_DELAY);
+
+            let addrs = vec![socket_addr("::1", closed_v6)];
+            assert!(matches!(connect_addrs(&addrs, AddressFamily::HappyEyeballs).await, Err(PingError::Connect(_))));
+        });
+    }
 }
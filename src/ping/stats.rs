@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// LatencyStats holds the statistics of several pings of the same node. The latencies are total
+/// latencies (connection plus handshake, or connection only in TcpConnect mode) of the samples
+/// that succeeded.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatencyStats {
+    /// samples is the number of pings made
+    pub samples: u32,
+
+    /// failures is the number of pings that failed
+    pub failures: u32,
+
+    /// min is the lowest latency
+    pub min: Duration,
+
+    /// median is the median latency
+    pub median: Duration,
+
+    /// mean is the average latency
+    pub mean: Duration,
+
+    /// p95 is the 95th percentile latency (nearest rank)
+    pub p95: Duration,
+
+    /// p99 is the 99th percentile latency (nearest rank)
+    #[serde(default)]
+    pub p99: Duration,
+
+    /// jitter is the standard deviation of the latency
+    pub jitter: Duration,
+}
+
+impl LatencyStats {
+    /// from_samples: computes the statistics of the latencies of the successful pings
+    /// # Arguments:
+    /// - **latencies**: the latencies of the pings that succeeded, in any order
+    /// - **failures**: the number of pings that failed
+    pub fn from_samples(latencies: &[Duration], failures: u32) -> LatencyStats {
+        let mut stats = LatencyStats {
+            samples: latencies.len() as u32 + failures,
+            failures,
+            ..Default::default()
+        };
+        if latencies.is_empty() {
+            return stats;
+        }
+
+        let mut sorted = latencies.to_vec();
+        sorted.sort();
+        let n = sorted.len();
+
+        stats.min = sorted[0];
+        stats.median = if n.is_multiple_of(2) {
+            (sorted[n / 2 - 1] + sorted[n / 2]) / 2
+        } else {
+            sorted[n / 2]
+        };
+        let rank = |q: f64| sorted[((n as f64 * q).ceil() as usize).clamp(1, n) - 1];
+        stats.p95 = rank(0.95);
+        stats.p99 = rank(0.99);
+
+        let mean = sorted.iter().map(|d| d.as_secs_f64()).sum::<f64>() / n as f64;
+        let variance = sorted.iter().map(|d| (d.as_secs_f64() - mean).powi(2)).sum::<f64>() / n as f64;
+        stats.mean = Duration::from_secs_f64(mean);
+        stats.jitter = Duration::from_secs_f64(variance.sqrt());
+
+        stats
+    }
+
+    /// loss: returns the ratio of failed pings, between 0 and 1 (0 when no ping was made)
+    pub fn loss(&self) -> f64 {
+        if self.samples == 0 {
+            return 0.0;
+        }
+        self.failures as f64 / self.samples as f64
+    }
+}
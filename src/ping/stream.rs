@@ -0,0 +1,222 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::stream::{BoxStream, Stream, StreamExt};
+use tokio::sync::watch;
+
+use crate::node::Node;
+use crate::ping::{ping_node, runtime, MessageOut, PingOptions};
+
+/// Progress reports how far a streaming ping is
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// done is the number of nodes measured so far
+    pub done: usize,
+
+    /// total is the number of nodes to ping
+    pub total: usize,
+
+    /// failed is the number of measured nodes found offline
+    pub failed: usize,
+}
+
+/// ProgressCallback is called every time a node has been measured
+pub type ProgressCallback = Box<dyn FnMut(Progress) + Send>;
+
+/// CancelHandle stops a streaming ping: the pings in flight are aborted and every node not
+/// measured yet is returned right away, marked as not measured. It can be cloned and used from
+/// any thread.
+#[derive(Clone, Debug)]
+pub struct CancelHandle {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for CancelHandle {
+    fn default() -> Self {
+        CancelHandle::new()
+    }
+}
+
+impl CancelHandle {
+    /// new: returns a handle that is not cancelled
+    pub fn new() -> CancelHandle {
+        let (sender, _) = watch::channel(false);
+        CancelHandle { sender: Arc::new(sender) }
+    }
+
+    /// cancel: cancels the pings using this handle
+    pub fn cancel(&self) {
+        self.sender.send_replace(true);
+    }
+
+    /// is_cancelled: returns true once cancel was called
+    pub fn is_cancelled(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// cancelled: completes once cancel is called
+    pub async fn cancelled(&self) {
+        let mut receiver = self.sender.subscribe();
+        // the sender lives as long as self, so waiting cannot fail
+        let _ = receiver.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+/// PingControl holds the optional controls of a streaming ping
+#[derive(Default)]
+pub struct PingControl {
+    /// cancel stops the pings when cancelled
+    pub cancel: Option<CancelHandle>,
+
+    /// deadline stops the pings once elapsed, counted from the first poll of the stream
+    pub deadline: Option<Duration>,
+
+    /// progress is called every time a node has been measured
+    pub progress: Option<ProgressCallback>,
+}
+
+struct StreamState {
+    pings: BoxStream<'static, MessageOut>,
+    stop: Pin<Box<dyn Future<Output = ()> + Send>>,
+    nodes: Vec<Option<Node>>,
+    progress: Progress,
+    on_progress: Option<ProgressCallback>,
+    stopped: bool,
+    next_unfinished: usize,
+}
+
+impl StreamState {
+    fn measured(&mut self, msg: MessageOut) -> Option<(usize, Node)> {
+        let MessageOut::Latency { id, .. } = &msg;
+        let id = *id;
+        let mut node = self.nodes.get_mut(id)?.take()?;
+        super::apply(&mut node, msg);
+
+        self.progress.done += 1;
+        if !node.online() {
+            self.progress.failed += 1;
+        }
+        if let Some(on_progress) = self.on_progress.as_mut() {
+            on_progress(self.progress);
+        }
+        Some((id, node))
+    }
+
+    fn unfinished(&mut self) -> Option<(usize, Node)> {
+        while self.next_unfinished < self.nodes.len() {
+            let id = self.next_unfinished;
+            self.next_unfinished += 1;
+            if let Some(mut node) = self.nodes[id].take() {
+                node.set_measured(false);
+                return Some((id, node));
+            }
+        }
+        None
+    }
+}
+
+/// ping_stream: pings every node of the vector like ping_many, but yields every node with its
+/// position in the vector as soon as its ping is done. Once the control is cancelled or its
+/// deadline elapsed, the pings in flight are aborted and the nodes left are yielded right away,
+/// marked as not measured (see Node::measured).
+/// It must be polled from within a tokio runtime.
+/// # Arguments:
+/// * `in_node_vec:` the nodes to ping
+/// * `options:` the timeouts, retry policy, mode and concurrency of the pings
+/// * `control:` the cancel handle, global deadline and progress callback
+pub fn ping_stream(in_node_vec: Vec<Node>, options: PingOptions, control: PingControl) -> impl Stream<Item = (usize, Node)> + Send {
+    let requests: Vec<(usize, String, u16, u32)> = in_node_vec
+        .iter()
+        .enumerate()
+        .map(|(id, node)| (id, node.addr().to_string(), node.port(), node.network_type().magic()))
+        .collect();
+
+    let pings = futures::stream::iter(requests)
+        .map(move |(id, host, port, network_magic)| {
+            debug!("node to ping: {} --> {}:{} (magic {})", id, host, port, network_magic);
+            ping_node(id, host, port, network_magic, options)
+        })
+        .buffer_unordered(options.concurrency.max(1))
+        .boxed();
+
+    let PingControl { cancel, deadline, progress } = control;
+    let stop = Box::pin(async move {
+        let deadline = async {
+            match deadline {
+                Some(deadline) => tokio::time::sleep(deadline).await,
+                None => futures::future::pending().await,
+            }
+        };
+        let cancelled = async {
+            match &cancel {
+                Some(cancel) => cancel.cancelled().await,
+                None => futures::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = deadline => debug!("ping deadline elapsed"),
+            _ = cancelled => debug!("ping cancelled"),
+        }
+    });
+
+    let state = StreamState {
+        pings,
+        stop,
+        progress: Progress { done: 0, total: in_node_vec.len(), failed: 0 },
+        nodes: in_node_vec.into_iter().map(Some).collect(),
+        on_progress: progress,
+        stopped: false,
+        next_unfinished: 0,
+    };
+
+    futures::stream::unfold(state, |mut state| async move {
+        while !state.stopped {
+            tokio::select! {
+                msg = state.pings.next() => match msg {
+                    Some(msg) => {
+                        if let Some(item) = state.measured(msg) {
+                            return Some((item, state));
+                        }
+                    }
+                    None => return None,
+                },
+                _ = &mut state.stop => {
+                    state.stopped = true;
+                    // drops the pings in flight, which aborts their tasks
+                    state.pings = futures::stream::empty().boxed();
+                }
+            }
+        }
+        state.unfinished().map(|item| (item, state))
+    })
+}
+
+/// PingIter is the blocking counterpart of ping_stream: an iterator of `(index, Node)` driven by a
+/// runtime of its own. It must not be used from within an asynchronous runtime.
+pub struct PingIter {
+    runtime: tokio::runtime::Runtime,
+    stream: BoxStream<'static, (usize, Node)>,
+}
+
+impl Iterator for PingIter {
+    type Item = (usize, Node);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.runtime.block_on(self.stream.next())
+    }
+}
+
+/// ping_iter: returns an iterator yielding every node as soon as its ping is done (see
+/// ping_stream)
+/// # Arguments:
+/// * `in_node_vec:` the nodes to ping
+/// * `options:` the timeouts, retry policy, mode and concurrency of the pings
+/// * `control:` the cancel handle, global deadline and progress callback
+pub fn ping_iter(in_node_vec: Vec<Node>, options: PingOptions, control: PingControl) -> PingIter {
+    PingIter {
+        runtime: runtime(),
+        stream: ping_stream(in_node_vec, options, control).boxed(),
+    }
+}
@@ -0,0 +1,306 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::node::{Node, NodeId};
+
+mod report_tests;
+
+/// ReportFormat selects how the nodes are rendered
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Table is an aligned text table for terminals
+    #[default]
+    Table,
+
+    /// Csv is comma separated values with a header line (RFC 4180)
+    Csv,
+
+    /// Json is a pretty printed JSON array
+    Json,
+
+    /// Ndjson is one compact JSON object per line, for log shippers
+    Ndjson,
+}
+
+/// SortKey selects the order of the nodes in a report
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+    /// None keeps the order of the nodes
+    #[default]
+    None,
+
+    /// Latency sorts by total latency, the offline nodes last
+    Latency,
+
+    /// Continent sorts by continent, then by latency
+    Continent,
+
+    /// Address sorts by address and port
+    Address,
+}
+
+/// ParseReportError is returned when a string does not name a report format or a sort key
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseReportError(String);
+
+impl fmt::Display for ParseReportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for ParseReportError {}
+
+impl FromStr for ReportFormat {
+    type Err = ParseReportError;
+
+    /// from_str: parses `table`, `csv`, `json` or `ndjson` (case insensitive)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "table" => Ok(ReportFormat::Table),
+            "csv" => Ok(ReportFormat::Csv),
+            "json" => Ok(ReportFormat::Json),
+            "ndjson" => Ok(ReportFormat::Ndjson),
+            _ => Err(ParseReportError(format!(
+                "unknown report format '{}', expected table, csv, json or ndjson",
+                s
+            ))),
+        }
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = ParseReportError;
+
+    /// from_str: parses `none`, `latency`, `continent` or `address` (case insensitive)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(SortKey::None),
+            "latency" => Ok(SortKey::Latency),
+            "continent" => Ok(SortKey::Continent),
+            "address" | "addr" => Ok(SortKey::Address),
+            _ => Err(ParseReportError(format!(
+                "unknown sort key '{}', expected none, latency, continent or address",
+                s
+            ))),
+        }
+    }
+}
+
+/// ReportOptions holds the format of a report and the filters and order of its nodes, shared by
+/// every format. The default options render every node as a table, in their order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReportOptions {
+    /// format is the output format
+    pub format: ReportFormat,
+
+    /// sort is the order of the nodes
+    pub sort: SortKey,
+
+    /// online_only leaves out the offline nodes
+    pub online_only: bool,
+
+    /// continent keeps only the nodes of the continent (case insensitive)
+    pub continent: Option<String>,
+
+    /// max_latency keeps only the online nodes whose total latency is at most the duration
+    pub max_latency: Option<Duration>,
+
+    /// measured_only leaves out the nodes that were not pinged
+    pub measured_only: bool,
+}
+
+/// ReportRow is the line of a node in a report, latencies in milliseconds
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReportRow {
+    /// addr is the address of the node
+    pub addr: String,
+
+    /// port is the port of the node
+    pub port: u16,
+
+    /// continent is the continent of the node, if known
+    pub continent: String,
+
+    /// state is the state or country of the node, if known
+    pub state: String,
+
+    /// online is true if the node answered its ping
+    pub online: bool,
+
+    /// con_latency_ms is the connection latency, None when offline
+    pub con_latency_ms: Option<f64>,
+
+    /// total_latency_ms is the connection plus handshake latency, None when offline
+    pub total_latency_ms: Option<f64>,
+
+    /// version is the version negotiated in the handshake
+    pub version: Option<u64>,
+
+    /// tip_slot is the slot of the tip of the node (see PingOptions::query_tip)
+    pub tip_slot: Option<u64>,
+
+    /// error is the reason the node is offline
+    pub error: Option<String>,
+}
+
+impl ReportRow {
+    /// new: returns the line of the node
+    pub fn new(node: &Node) -> ReportRow {
+        let ms = |d: Duration| (d.as_secs_f64() * 1_000_000.0).round() / 1000.0;
+        ReportRow {
+            addr: node.addr().to_string(),
+            port: node.port(),
+            continent: node.continent().to_string(),
+            state: node.state().to_string(),
+            online: node.online(),
+            con_latency_ms: node.online().then(|| ms(node.con_latency())),
+            total_latency_ms: node.online().then(|| ms(node.total_latency())),
+            version: node.version().map(|v| v.version),
+            tip_slot: node.tip().map(|t| t.slot),
+            error: node.online_error().map(|e| e.to_string()),
+        }
+    }
+}
+
+/// CSV_HEADER is the first line of the CSV reports
+const CSV_HEADER: &str = "addr,port,continent,state,online,con_latency_ms,total_latency_ms,version,tip_slot,error";
+
+/// rows: returns the lines of the nodes kept by the options, in the order of the options
+/// # Arguments:
+/// * `nodes:` the nodes, e.g. as returned by ping::ping_vec
+/// * `options:` the filters and order (the format is ignored)
+pub fn rows(nodes: &[Node], options: &ReportOptions) -> Vec<ReportRow> {
+    let continent = options.continent.as_ref().map(|c| c.trim().to_lowercase());
+    let mut kept: Vec<&Node> = nodes
+        .iter()
+        .filter(|n| !options.online_only || n.online())
+        .filter(|n| !options.measured_only || n.measured())
+        .filter(|n| continent.as_ref().is_none_or(|c| &n.continent().trim().to_lowercase() == c))
+        .filter(|n| options.max_latency.is_none_or(|max| n.online() && n.total_latency() <= max))
+        .collect();
+
+    let by_latency = |a: &&Node, b: &&Node| match (a.online(), b.online()) {
+        (true, true) => a.total_latency().cmp(&b.total_latency()),
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => Ordering::Equal,
+    };
+    match options.sort {
+        SortKey::None => {}
+        SortKey::Latency => kept.sort_by(by_latency),
+        SortKey::Continent => {
+            kept.sort_by(|a, b| a.continent().to_lowercase().cmp(&b.continent().to_lowercase()).then_with(|| by_latency(a, b)))
+        }
+        SortKey::Address => kept.sort_by_key(|n| n.id()),
+    }
+    kept.into_iter().map(ReportRow::new).collect()
+}
+
+/// render: returns the report of the nodes
+/// # Arguments:
+/// * `nodes:` the nodes, e.g. as returned by ping::ping_vec
+/// * `options:` the format, filters and order of the report
+pub fn render(nodes: &[Node], options: &ReportOptions) -> String {
+    let mut out = Vec::new();
+    write(nodes, options, &mut out).expect("writing to memory");
+    String::from_utf8(out).expect("reports are UTF-8")
+}
+
+/// write: writes the report of the nodes (see render)
+pub fn write<W: Write + ?Sized>(nodes: &[Node], options: &ReportOptions, out: &mut W) -> io::Result<()> {
+    let rows = rows(nodes, options);
+    match options.format {
+        ReportFormat::Table => write_table(&rows, out),
+        ReportFormat::Csv => {
+            writeln!(out, "{}", CSV_HEADER)?;
+            for row in rows.iter() {
+                writeln!(out, "{}", csv_line(row))?;
+            }
+            Ok(())
+        }
+        ReportFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&rows)?),
+        ReportFormat::Ndjson => {
+            for row in rows.iter() {
+                writeln!(out, "{}", serde_json::to_string(row)?)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn optional<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// csv_field: quotes the field if it holds a comma, a double quote or a line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_line(row: &ReportRow) -> String {
+    [
+        csv_field(&row.addr),
+        row.port.to_string(),
+        csv_field(&row.continent),
+        csv_field(&row.state),
+        row.online.to_string(),
+        optional(&row.con_latency_ms),
+        optional(&row.total_latency_ms),
+        optional(&row.version),
+        optional(&row.tip_slot),
+        csv_field(&optional(&row.error)),
+    ]
+    .join(",")
+}
+
+fn write_table<W: Write + ?Sized>(rows: &[ReportRow], out: &mut W) -> io::Result<()> {
+    let latency = |l: Option<f64>| l.map_or_else(|| "-".to_string(), |l| format!("{:.1} ms", l));
+    let header = ["NODE", "CONTINENT", "STATE", "ONLINE", "CONNECT", "TOTAL", "ERROR"].map(String::from);
+    let cells: Vec<[String; 7]> = rows
+        .iter()
+        .map(|row| {
+            [
+                NodeId::new(&row.addr, row.port).to_string(),
+                row.continent.clone(),
+                row.state.clone(),
+                if row.online { "yes" } else { "no" }.to_string(),
+                latency(row.con_latency_ms),
+                latency(row.total_latency_ms),
+                optional(&row.error).replace('\n', " "),
+            ]
+        })
+        .collect();
+
+    let mut widths = header.clone().map(|h| h.chars().count());
+    for line in cells.iter() {
+        for (width, cell) in widths.iter_mut().zip(line.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    for line in std::iter::once(&header).chain(cells.iter()) {
+        let mut text = String::new();
+        for (i, cell) in line.iter().enumerate() {
+            let pad = widths[i] - cell.chars().count();
+            match i {
+                // latencies are right aligned
+                4 | 5 => text.push_str(&format!("{}{}", " ".repeat(pad), cell)),
+                _ => text.push_str(&format!("{}{}", cell, " ".repeat(pad))),
+            }
+            text.push_str("  ");
+        }
+        writeln!(out, "{}", text.trim_end())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::node::Node;
+    use crate::ping::mock::PingedNode;
+    use crate::ping::PingError;
+    use crate::report::{render, rows, ReportFormat, ReportOptions, ReportRow, SortKey};
+
+    fn nodes() -> Vec<Node> {
+        let online = |addr: &str, continent: &str, latency_ms: u64| {
+            PingedNode::new(addr)
+                .location(continent, "")
+                .latency(latency_ms)
+                .con_latency(Duration::from_micros(latency_ms * 400))
+                .build()
+        };
+        vec![
+            online("10.0.0.3", "Europe", 80),
+            PingedNode::new("10.0.0.1")
+                .location("North America", "")
+                .offline(PingError::Connect("connection refused, \"try later\"".to_string()))
+                .build(),
+            online("2a05:d018::40", "europe", 25),
+            online("10.0.0.2", "Asia", 120),
+        ]
+    }
+
+    fn addrs(rows: &[ReportRow]) -> Vec<&str> {
+        rows.iter().map(|r| r.addr.as_str()).collect()
+    }
+
+    #[test]
+    fn filter_and_sort() {
+        let nodes = nodes();
+        assert_eq!(vec!["10.0.0.3", "10.0.0.1", "2a05:d018::40", "10.0.0.2"], addrs(&rows(&nodes, &ReportOptions::default())));
+
+        let options = ReportOptions { sort: SortKey::Latency, ..Default::default() };
+        assert_eq!(vec!["2a05:d018::40", "10.0.0.3", "10.0.0.2", "10.0.0.1"], addrs(&rows(&nodes, &options)));
+
+        let options = ReportOptions { sort: SortKey::Continent, online_only: true, ..Default::default() };
+        assert_eq!(vec!["10.0.0.2", "2a05:d018::40", "10.0.0.3"], addrs(&rows(&nodes, &options)));
+
+        let options = ReportOptions { continent: Some("EUROPE ".to_string()), sort: SortKey::Address, ..Default::default() };
+        assert_eq!(vec!["10.0.0.3", "2a05:d018::40"], addrs(&rows(&nodes, &options)));
+
+        let options = ReportOptions { max_latency: Some(Duration::from_millis(80)), ..Default::default() };
+        assert_eq!(vec!["10.0.0.3", "2a05:d018::40"], addrs(&rows(&nodes, &options)));
+
+        let mut not_measured = nodes.clone();
+        not_measured[0].set_measured(false);
+        let options = ReportOptions { measured_only: true, ..Default::default() };
+        assert_eq!(3, rows(&not_measured, &options).len());
+
+        assert_eq!(Ok(SortKey::Address), "addr".parse());
+        assert_eq!(Ok(ReportFormat::Ndjson), "NDJSON".parse());
+        assert!("xml".parse::<ReportFormat>().is_err());
+    }
+
+    #[test]
+    fn render_formats() {
+        let nodes = nodes();
+        let options = |format| ReportOptions { format, sort: SortKey::Latency, ..Default::default() };
+
+        let table = render(&nodes, &options(ReportFormat::Table));
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(5, lines.len());
+        assert!(lines[0].starts_with("NODE                  CONTINENT      STATE  ONLINE  CONNECT     TOTAL  ERROR"), "{}", table);
+        assert_eq!("[2a05:d018::40]:3001  europe                yes     10.0 ms   25.0 ms", lines[1]);
+        assert!(lines[4].starts_with("10.0.0.1:3001         North America         no            -         -  connect"), "{}", table);
+
+        let csv = render(&nodes, &options(ReportFormat::Csv));
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!("addr,port,continent,state,online,con_latency_ms,total_latency_ms,version,tip_slot,error", lines[0]);
+        assert_eq!("2a05:d018::40,3001,europe,,true,10,25,,,", lines[1]);
+        assert!(lines[4].starts_with("10.0.0.1,3001,North America,,false,,,,,\"connect"), "{}", lines[4]);
+        assert!(lines[4].ends_with("\"\"try later\"\"\""), "{}", lines[4]);
+
+        let json = render(&nodes, &options(ReportFormat::Json));
+        let parsed: Vec<ReportRow> = serde_json::from_str(&json).unwrap();
+        assert_eq!(rows(&nodes, &options(ReportFormat::Json)), parsed);
+        assert!(json.contains("\n  {\n    \"addr\": \"2a05:d018::40\""), "{}", json);
+
+        let ndjson = render(&nodes, &options(ReportFormat::Ndjson));
+        let parsed: Vec<ReportRow> = ndjson.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(4, parsed.len());
+        assert_eq!(Some(80.0), parsed[1].total_latency_ms);
+        assert_eq!(None, parsed[3].total_latency_ms);
+
+        let empty = ReportOptions { continent: Some("Antarctica".to_string()), ..options(ReportFormat::Ndjson) };
+        assert_eq!("", render(&nodes, &empty));
+    }
+}
@@ -0,0 +1,465 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::history::HistorySummary;
+use crate::node::{Node, NodeId};
+use crate::ping::{LatencyStats, PingError};
+use crate::topology::Topology;
+
+mod select_tests;
+
+/// LatencyMetric selects which of the latencies measured by the ping module is used for ranking
+/// the candidates
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LatencyMetric {
+    /// Connect ranks by connection latency (TCP connect)
+    Connect,
+
+    /// Total ranks by total latency (TCP connect plus handshake)
+    #[default]
+    Total,
+
+    /// Median ranks by the median total latency of several samples, falling back to the total
+    /// latency for nodes pinged only once
+    Median,
+
+    /// P95 ranks by the 95th percentile total latency of several samples, falling back to the
+    /// total latency for nodes pinged only once
+    P95,
+
+    /// HistoryMedian ranks by the median total latency recorded in the ping history, falling
+    /// back to the total latency for nodes without history (see Selector::set_history)
+    HistoryMedian,
+
+    /// HistoryP95 ranks by the 95th percentile total latency recorded in the ping history,
+    /// falling back to the total latency for nodes without history
+    HistoryP95,
+}
+
+/// AsnLookup maps an IP address to the autonomous system number it belongs to.
+pub trait AsnLookup {
+    /// asn: returns the ASN of the address, None if unknown
+    fn asn(&self, ip: IpAddr) -> Option<u32>;
+}
+
+impl AsnLookup for HashMap<IpAddr, u32> {
+    fn asn(&self, ip: IpAddr) -> Option<u32> {
+        self.get(&ip).copied()
+    }
+}
+
+/// HistoryLookup returns the ping history aggregates of a node (see history::HistoryStore)
+pub trait HistoryLookup {
+    /// summary: returns the aggregates of the node, None if it has no history
+    fn summary(&self, id: &NodeId) -> Option<HistorySummary>;
+}
+
+impl HistoryLookup for HashMap<NodeId, HistorySummary> {
+    fn summary(&self, id: &NodeId) -> Option<HistorySummary> {
+        self.get(id).cloned()
+    }
+}
+
+/// SelectOptions holds the policies applied when selecting peers. Every cap is disabled by default
+/// so the default options select the `count` online peers with the lowest latency.
+#[derive(Clone, Debug)]
+pub struct SelectOptions {
+    /// count is the number of peers to select, pinned peers included
+    pub count: usize,
+
+    /// metric is the latency used for ranking the candidates
+    pub metric: LatencyMetric,
+
+    /// pinned are the `(addr, port)` of the peers that are always kept, whatever their state
+    pub pinned: Vec<(String, u16)>,
+
+    /// max_per_continent caps the number of peers selected from the same continent
+    pub max_per_continent: Option<usize>,
+
+    /// continent_quotas overrides max_per_continent for specific continents
+    pub continent_quotas: HashMap<String, usize>,
+
+    /// max_per_state caps the number of peers selected from the same state
+    pub max_per_state: Option<usize>,
+
+    /// max_per_ip caps the number of peers selected on the same IP address (different ports). A
+    /// host name counts on every address it resolves to, and so do the subnet and ASN caps.
+    pub max_per_ip: Option<usize>,
+
+    /// max_per_subnet caps the number of peers selected on the same subnet
+    pub max_per_subnet: Option<usize>,
+
+    /// subnet_prefix_v4 is the prefix length defining an IPv4 subnet (default /24)
+    pub subnet_prefix_v4: u8,
+
+    /// subnet_prefix_v6 is the prefix length defining an IPv6 subnet (default /48)
+    pub subnet_prefix_v6: u8,
+
+    /// max_per_asn caps the number of peers selected on the same autonomous system, it needs an
+    /// AsnLookup (see Selector::set_asn_lookup)
+    pub max_per_asn: Option<usize>,
+
+    /// min_uptime rejects the peers whose uptime in the ping history is below the percentage
+    /// (0 to 100). It needs a HistoryLookup (see Selector::set_history); peers without history
+    /// are not rejected.
+    pub min_uptime: Option<f64>,
+}
+
+impl Default for SelectOptions {
+    fn default() -> Self {
+        SelectOptions {
+            count: 20,
+            metric: LatencyMetric::default(),
+            pinned: Vec::new(),
+            max_per_continent: None,
+            continent_quotas: HashMap::new(),
+            max_per_state: None,
+            max_per_ip: None,
+            max_per_subnet: None,
+            subnet_prefix_v4: 24,
+            subnet_prefix_v6: 48,
+            max_per_asn: None,
+            min_uptime: None,
+        }
+    }
+}
+
+/// RejectReason explains why a candidate was not selected
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RejectReason {
+    /// Offline: the peer did not answer the ping, holds the ping error
+    Offline(Option<PingError>),
+
+    /// Duplicate: the same addr:port, or one of its resolved endpoints, was already considered
+    Duplicate,
+
+    /// ContinentQuota: the quota of the continent was reached
+    ContinentQuota(String),
+
+    /// StateQuota: the quota of the state was reached
+    StateQuota(String),
+
+    /// IpCap: the cap of peers on the IP address was reached
+    IpCap(IpAddr),
+
+    /// SubnetCap: the cap of peers on the subnet was reached, holds the subnet (e.g. 10.0.0.0/24)
+    SubnetCap(String),
+
+    /// AsnCap: the cap of peers on the autonomous system was reached
+    AsnCap(u32),
+
+    /// Unresolved: the peer is a host name without resolved addresses (see node::resolve_all),
+    /// so the IP, subnet and ASN caps cannot be checked
+    Unresolved,
+
+    /// LowUptime: the uptime of the peer in the ping history is below min_uptime, holds the
+    /// uptime percentage rounded down
+    LowUptime(u32),
+
+    /// CountReached: enough peers with a lower latency were already selected
+    CountReached,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::Offline(Some(e)) => write!(f, "offline: {}", e),
+            RejectReason::Offline(None) => write!(f, "offline"),
+            RejectReason::Duplicate => write!(f, "duplicate peer"),
+            RejectReason::ContinentQuota(c) => write!(f, "quota reached for continent '{}'", c),
+            RejectReason::StateQuota(s) => write!(f, "quota reached for state '{}'", s),
+            RejectReason::IpCap(ip) => write!(f, "cap reached for IP {}", ip),
+            RejectReason::SubnetCap(s) => write!(f, "cap reached for subnet {}", s),
+            RejectReason::AsnCap(asn) => write!(f, "cap reached for AS{}", asn),
+            RejectReason::Unresolved => write!(f, "host name not resolved"),
+            RejectReason::LowUptime(uptime) => write!(f, "uptime too low: {}%", uptime),
+            RejectReason::CountReached => write!(f, "enough peers selected"),
+        }
+    }
+}
+
+/// Rejection holds a candidate that was not selected and the reason why
+#[derive(Clone, Debug)]
+pub struct Rejection {
+    /// node is the rejected candidate
+    pub node: Node,
+
+    /// reason explains the rejection
+    pub reason: RejectReason,
+}
+
+/// Selection is the result of a peer selection
+#[derive(Clone, Debug)]
+pub struct Selection {
+    /// selected holds the selected peers, pinned peers first and then by latency
+    pub selected: Vec<Node>,
+
+    /// rejected holds every other candidate with the reason it was not selected
+    pub rejected: Vec<Rejection>,
+}
+
+impl Selection {
+    /// topology: returns a legacy topology with the selected peers, ready to be written. Peers
+    /// without a valency get a valency of 1.
+    pub fn topology(&self) -> Topology {
+        let nodes = self
+            .selected
+            .iter()
+            .map(|n| {
+                let mut node = n.clone();
+                if node.valency() == 0 {
+                    node.set_valency(1);
+                }
+                node
+            })
+            .collect();
+        Topology::new(nodes)
+    }
+}
+
+/// Selector picks the best peers out of a vector of nodes previously pinged with
+/// `ping::ping_vec`.
+pub struct Selector {
+    options: SelectOptions,
+    asn_lookup: Option<Box<dyn AsnLookup>>,
+    history: Option<Box<dyn HistoryLookup>>,
+}
+
+/// Counters tracks how many peers were selected for every capped key
+#[derive(Default)]
+struct Counters {
+    continent: HashMap<String, usize>,
+    state: HashMap<String, usize>,
+    ip: HashMap<IpAddr, usize>,
+    subnet: HashMap<String, usize>,
+    asn: HashMap<u32, usize>,
+}
+
+impl Selector {
+    /// new: returns a selector applying the given options
+    pub fn new(options: SelectOptions) -> Selector {
+        Selector {
+            options,
+            asn_lookup: None,
+            history: None,
+        }
+    }
+
+    /// set_asn_lookup: sets the lookup used for the max_per_asn policy
+    pub fn set_asn_lookup(&mut self, lookup: Box<dyn AsnLookup>) {
+        self.asn_lookup = Some(lookup);
+    }
+
+    /// set_history: sets the ping history used for the min_uptime policy and the History latency
+    /// metrics (see history::HistoryStore::summaries)
+    pub fn set_history(&mut self, history: Box<dyn HistoryLookup>) {
+        self.history = Some(history);
+    }
+
+    /// options: returns the selection options
+    pub fn options(&self) -> &SelectOptions {
+        &self.options
+    }
+
+    /// select: selects up to `count` peers out of the pinged nodes. Pinned peers are always
+    /// selected, then online peers are considered from the lowest to the highest latency and kept
+    /// as long as no cap is reached.
+    pub fn select(&self, nodes: &[Node]) -> Selection {
+        let mut selected: Vec<Node> = Vec::new();
+        let mut rejected: Vec<Rejection> = Vec::new();
+        let mut counters = Counters::default();
+        let mut seen: HashSet<String> = HashSet::new();
+
+        let (pinned, mut candidates): (Vec<&Node>, Vec<&Node>) =
+            nodes.iter().partition(|n| self.is_pinned(n));
+
+        for node in pinned {
+            if Selector::check_duplicate(&mut seen, node) {
+                rejected.push(Rejection { node: node.clone(), reason: RejectReason::Duplicate });
+                continue;
+            }
+            self.count(&mut counters, node);
+            selected.push(node.clone());
+        }
+
+        candidates.sort_by_key(|n| self.latency(n));
+
+        for node in candidates {
+            if Selector::check_duplicate(&mut seen, node) {
+                rejected.push(Rejection { node: node.clone(), reason: RejectReason::Duplicate });
+                continue;
+            }
+
+            let reason = if !node.online() {
+                Some(RejectReason::Offline(node.online_error()))
+            } else if let Some(uptime) = self.low_uptime(node) {
+                Some(RejectReason::LowUptime(uptime))
+            } else if selected.len() >= self.options.count {
+                Some(RejectReason::CountReached)
+            } else {
+                self.check_caps(&counters, node)
+            };
+
+            match reason {
+                Some(reason) => rejected.push(Rejection { node: node.clone(), reason }),
+                None => {
+                    self.count(&mut counters, node);
+                    selected.push(node.clone());
+                }
+            }
+        }
+
+        Selection { selected, rejected }
+    }
+
+    fn latency(&self, node: &Node) -> Duration {
+        match self.options.metric {
+            LatencyMetric::Connect => node.con_latency(),
+            LatencyMetric::Total => node.total_latency(),
+            LatencyMetric::Median => node.latency_stats().map_or(node.total_latency(), |s| s.median),
+            LatencyMetric::P95 => node.latency_stats().map_or(node.total_latency(), |s| s.p95),
+            LatencyMetric::HistoryMedian => self.history_latency(node).map_or(node.total_latency(), |s| s.median),
+            LatencyMetric::HistoryP95 => self.history_latency(node).map_or(node.total_latency(), |s| s.p95),
+        }
+    }
+
+    fn history(&self, node: &Node) -> Option<HistorySummary> {
+        self.history.as_ref()?.summary(&node.id())
+    }
+
+    fn history_latency(&self, node: &Node) -> Option<LatencyStats> {
+        self.history(node).map(|s| s.latency).filter(|l| l.samples > l.failures)
+    }
+
+    fn low_uptime(&self, node: &Node) -> Option<u32> {
+        let min_uptime = self.options.min_uptime?;
+        let summary = self.history(node).filter(|s| s.checks > 0)?;
+        (summary.uptime < min_uptime).then_some(summary.uptime.floor() as u32)
+    }
+
+    fn is_pinned(&self, node: &Node) -> bool {
+        self.options
+            .pinned
+            .iter()
+            .any(|(addr, port)| addr.eq_ignore_ascii_case(node.addr()) && *port == node.port())
+    }
+
+    fn check_duplicate(seen: &mut HashSet<String>, node: &Node) -> bool {
+        let mut keys = vec![node.id().to_string()];
+        keys.extend(node.endpoints().iter().map(|e| e.to_string()));
+        if keys.iter().any(|k| seen.contains(k)) {
+            return true;
+        }
+        seen.extend(keys);
+        false
+    }
+
+    fn ips(node: &Node) -> Vec<IpAddr> {
+        match node.addr().parse() {
+            Ok(ip) => vec![ip],
+            Err(_) => {
+                let mut ips = node.resolved().to_vec();
+                ips.sort();
+                ips.dedup();
+                ips
+            }
+        }
+    }
+
+    fn subnets(&self, ips: &[IpAddr]) -> Vec<String> {
+        let mut subnets: Vec<String> = ips.iter().map(|ip| self.subnet(*ip)).collect();
+        subnets.sort();
+        subnets.dedup();
+        subnets
+    }
+
+    fn subnet(&self, ip: IpAddr) -> String {
+        match ip {
+            IpAddr::V4(v4) => {
+                let prefix = self.options.subnet_prefix_v4.min(32);
+                let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                let network = std::net::Ipv4Addr::from(u32::from(v4) & mask);
+                format!("{}/{}", network, prefix)
+            }
+            IpAddr::V6(v6) => {
+                let prefix = self.options.subnet_prefix_v6.min(128);
+                let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                let network = std::net::Ipv6Addr::from(u128::from(v6) & mask);
+                format!("{}/{}", network, prefix)
+            }
+        }
+    }
+
+    fn asns(&self, ips: &[IpAddr]) -> Vec<u32> {
+        let mut asns: Vec<u32> = match self.asn_lookup.as_ref() {
+            Some(lookup) => ips.iter().filter_map(|ip| lookup.asn(*ip)).collect(),
+            None => Vec::new(),
+        };
+        asns.sort_unstable();
+        asns.dedup();
+        asns
+    }
+
+    fn continent_quota(&self, continent: &str) -> Option<usize> {
+        self.options
+            .continent_quotas
+            .get(continent)
+            .copied()
+            .or(self.options.max_per_continent)
+    }
+
+    fn check_caps(&self, counters: &Counters, node: &Node) -> Option<RejectReason> {
+        let reached = |count: Option<&usize>, cap: Option<usize>| match cap {
+            Some(cap) => *count.unwrap_or(&0) >= cap,
+            None => false,
+        };
+
+        if reached(counters.continent.get(node.continent()), self.continent_quota(node.continent())) {
+            return Some(RejectReason::ContinentQuota(node.continent().to_string()));
+        }
+        if reached(counters.state.get(node.state()), self.options.max_per_state) {
+            return Some(RejectReason::StateQuota(node.state().to_string()));
+        }
+        let address_caps = self.options.max_per_ip.is_some()
+            || self.options.max_per_subnet.is_some()
+            || self.options.max_per_asn.is_some();
+        let ips = Selector::ips(node);
+        if address_caps && ips.is_empty() {
+            return Some(RejectReason::Unresolved);
+        }
+        if let Some(ip) = ips.iter().find(|ip| reached(counters.ip.get(ip), self.options.max_per_ip)) {
+            return Some(RejectReason::IpCap(*ip));
+        }
+        let subnets = self.subnets(&ips);
+        if let Some(subnet) = subnets.into_iter().find(|s| reached(counters.subnet.get(s), self.options.max_per_subnet)) {
+            return Some(RejectReason::SubnetCap(subnet));
+        }
+        let asns = self.asns(&ips);
+        if let Some(asn) = asns.into_iter().find(|asn| reached(counters.asn.get(asn), self.options.max_per_asn)) {
+            return Some(RejectReason::AsnCap(asn));
+        }
+        None
+    }
+
+    fn count(&self, counters: &mut Counters, node: &Node) {
+        *counters.continent.entry(node.continent().to_string()).or_insert(0) += 1;
+        *counters.state.entry(node.state().to_string()).or_insert(0) += 1;
+        let ips = Selector::ips(node);
+        for subnet in self.subnets(&ips) {
+            *counters.subnet.entry(subnet).or_insert(0) += 1;
+        }
+        for asn in self.asns(&ips) {
+            *counters.asn.entry(asn).or_insert(0) += 1;
+        }
+        for ip in ips {
+            *counters.ip.entry(ip).or_insert(0) += 1;
+        }
+    }
+}
+
+/// select: selects the best peers out of the pinged nodes (see Selector::select)
+pub fn select(nodes: &[Node], options: SelectOptions) -> Selection {
+    Selector::new(options).select(nodes)
+}
@@ -0,0 +1,191 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::IpAddr;
+    use std::time::Duration;
+
+    use serde_json::Value;
+
+    use crate::node::Node;
+    use crate::ping::mock::PingedNode;
+    use crate::ping::{LatencyStats, PingError};
+    use crate::select::{select, LatencyMetric, RejectReason, SelectOptions, Selector};
+
+    fn reason_of(selection: &crate::select::Selection, addr: &str) -> RejectReason {
+        selection
+            .rejected
+            .iter()
+            .find(|r| r.node.addr() == addr)
+            .map(|r| r.reason.clone())
+            .unwrap()
+    }
+
+    fn pinged_vec() -> Vec<Node> {
+        vec![
+            PingedNode::new("10.0.0.1").location("Europe", "DE").latency(80).build(),
+            PingedNode::new("10.0.0.2").location("Europe", "DE").latency(20).build(),
+            PingedNode::new("10.0.1.1").location("North America", "US").latency(50).build(),
+            PingedNode::new("10.0.2.1").location("Asia", "JP").latency(10).refused().build(),
+            PingedNode::new("10.0.3.1").location("North America", "CR").latency(30).build(),
+        ]
+    }
+
+    #[test]
+    fn select_by_latency() {
+        let options = SelectOptions { count: 3, ..Default::default() };
+        let selection = select(&pinged_vec(), options);
+
+        let addrs: Vec<&str> = selection.selected.iter().map(|n| n.addr()).collect();
+        assert_eq!(vec!["10.0.0.2", "10.0.3.1", "10.0.1.1"], addrs);
+
+        assert_eq!(2, selection.rejected.len());
+        assert_eq!(RejectReason::Offline(Some(PingError::Connect("connection refused".to_string()))), reason_of(&selection, "10.0.2.1"));
+        assert_eq!(RejectReason::CountReached, reason_of(&selection, "10.0.0.1"));
+    }
+
+    #[test]
+    fn select_by_connect_latency() {
+        let mut nodes = pinged_vec();
+        nodes[0].set_con_latency(Duration::from_millis(1));
+
+        let options = SelectOptions { count: 1, metric: LatencyMetric::Connect, ..Default::default() };
+        let selection = select(&nodes, options);
+        assert_eq!("10.0.0.1", selection.selected[0].addr());
+    }
+
+    #[test]
+    fn select_by_median_and_p95() {
+        let ms = Duration::from_millis;
+        let mut nodes = pinged_vec();
+        // steady around 60 ms
+        nodes[0].set_latency_stats(Some(LatencyStats::from_samples(&[ms(60), ms(60), ms(61)], 0)));
+        // fast median, with spikes
+        nodes[1].set_latency_stats(Some(LatencyStats::from_samples(&[ms(5), ms(5), ms(300)], 0)));
+
+        let options = SelectOptions { count: 1, metric: LatencyMetric::Median, ..Default::default() };
+        assert_eq!("10.0.0.2", select(&nodes, options).selected[0].addr());
+
+        // nodes without statistics fall back to the total latency (10.0.3.1: 30 ms)
+        let options = SelectOptions { count: 1, metric: LatencyMetric::P95, ..Default::default() };
+        assert_eq!("10.0.3.1", select(&nodes, options).selected[0].addr());
+
+        let options = SelectOptions { count: 5, metric: LatencyMetric::P95, ..Default::default() };
+        let selection = select(&nodes, options);
+        let addrs: Vec<&str> = selection.selected.iter().map(|n| n.addr()).collect();
+        assert_eq!(vec!["10.0.3.1", "10.0.1.1", "10.0.0.1", "10.0.0.2"], addrs);
+    }
+
+    #[test]
+    fn select_with_continent_and_state_quotas() {
+        let mut options = SelectOptions { count: 5, max_per_continent: Some(1), ..Default::default() };
+        options.continent_quotas.insert("North America".to_string(), 2);
+        let selection = select(&pinged_vec(), options);
+
+        assert_eq!(3, selection.selected.len());
+        assert_eq!(RejectReason::ContinentQuota("Europe".to_string()), reason_of(&selection, "10.0.0.1"));
+
+        let options = SelectOptions { count: 5, max_per_state: Some(1), ..Default::default() };
+        let selection = select(&pinged_vec(), options);
+        assert_eq!(RejectReason::StateQuota("DE".to_string()), reason_of(&selection, "10.0.0.1"));
+    }
+
+    #[test]
+    fn select_with_ip_and_subnet_caps() {
+        let mut nodes = pinged_vec();
+        nodes.push(PingedNode::new("10.0.0.2").port(3002).location("Europe", "DE").latency(25).build());
+
+        let options = SelectOptions { count: 10, max_per_ip: Some(1), ..Default::default() };
+        let selection = select(&nodes, options);
+        let ip: IpAddr = "10.0.0.2".parse().unwrap();
+        assert_eq!(RejectReason::IpCap(ip), selection.rejected.iter().find(|r| r.node.port() == 3002).unwrap().reason);
+
+        let options = SelectOptions { count: 10, max_per_subnet: Some(1), ..Default::default() };
+        let selection = select(&nodes, options);
+        assert_eq!(RejectReason::SubnetCap("10.0.0.0/24".to_string()), reason_of(&selection, "10.0.0.1"));
+        assert_eq!(3, selection.selected.len());
+
+        // a host name cannot bypass the caps: it is rejected until resolved, then counted
+        nodes.push(PingedNode::new("relay.example.com").port(3005).location("Europe", "DE").latency(1).build());
+        let options = SelectOptions { count: 10, max_per_ip: Some(1), ..Default::default() };
+        assert_eq!(RejectReason::Unresolved, reason_of(&select(&nodes, options.clone()), "relay.example.com"));
+        assert!(select(&nodes, SelectOptions { count: 10, ..Default::default() }).rejected.iter().all(|r| r.reason != RejectReason::Unresolved));
+        nodes.last_mut().unwrap().set_resolved(vec!["10.0.0.1".parse().unwrap(), "10.0.0.9".parse().unwrap()]);
+        let selection = select(&nodes, options.clone());
+        assert_eq!("relay.example.com", selection.selected[0].addr());
+        assert_eq!(RejectReason::IpCap("10.0.0.1".parse().unwrap()), reason_of(&selection, "10.0.0.1"));
+
+        // nor can a dual-stack host through its other addresses
+        let mut dual = PingedNode::new("dual.example.com").port(3005).location("Europe", "DE").latency(90).build();
+        dual.set_resolved(vec!["2001:db8::1".parse().unwrap(), "10.0.3.1".parse().unwrap()]);
+        nodes.push(dual);
+        let selection = select(&nodes, options);
+        assert_eq!(RejectReason::IpCap("10.0.3.1".parse().unwrap()), reason_of(&selection, "dual.example.com"));
+        let options = SelectOptions { count: 10, max_per_subnet: Some(1), ..Default::default() };
+        assert_eq!(RejectReason::SubnetCap("10.0.3.0/24".to_string()), reason_of(&select(&nodes, options), "dual.example.com"));
+    }
+
+    #[test]
+    fn select_with_asn_cap() {
+        let mut asn: HashMap<IpAddr, u32> = HashMap::new();
+        asn.insert("10.0.0.2".parse().unwrap(), 16509);
+        asn.insert("10.0.3.1".parse().unwrap(), 16509);
+
+        let mut selector = Selector::new(SelectOptions { count: 10, max_per_asn: Some(1), ..Default::default() });
+        selector.set_asn_lookup(Box::new(asn));
+        let selection = selector.select(&pinged_vec());
+
+        assert_eq!(RejectReason::AsnCap(16509), reason_of(&selection, "10.0.3.1"));
+        assert_eq!(3, selection.selected.len());
+
+        // a host name is capped on the AS of any of its addresses
+        let mut nodes = pinged_vec();
+        let mut relay = PingedNode::new("relay.example.com").port(3005).location("Europe", "DE").latency(90).build();
+        relay.set_resolved(vec!["10.0.1.1".parse().unwrap(), "10.0.3.1".parse().unwrap()]);
+        nodes.push(relay);
+        let selection = selector.select(&nodes);
+        assert_eq!(RejectReason::AsnCap(16509), reason_of(&selection, "relay.example.com"));
+    }
+
+    #[test]
+    fn select_keeps_pinned_peers() {
+        let options = SelectOptions {
+            count: 2,
+            pinned: vec![("10.0.2.1".to_string(), 3001), ("10.0.0.1".to_string(), 3001)],
+            ..Default::default()
+        };
+        let selection = select(&pinged_vec(), options);
+
+        let addrs: Vec<&str> = selection.selected.iter().map(|n| n.addr()).collect();
+        assert_eq!(vec!["10.0.0.1", "10.0.2.1"], addrs);
+        assert!(selection.rejected.iter().all(|r| r.reason == RejectReason::CountReached));
+    }
+
+    #[test]
+    fn select_rejects_duplicates() {
+        let mut nodes = pinged_vec();
+        nodes.push(PingedNode::new("10.0.0.2").location("Europe", "DE").latency(20).build());
+
+        let selection = select(&nodes, SelectOptions::default());
+        assert_eq!(4, selection.selected.len());
+        assert_eq!(1, selection.rejected.iter().filter(|r| r.reason == RejectReason::Duplicate).count());
+
+        // the same relay under its host name, once resolved
+        let mut relay = PingedNode::new("relay.example.com").location("Europe", "DE").latency(5).build();
+        relay.set_resolved(vec!["10.0.0.2".parse().unwrap()]);
+        nodes.push(relay);
+        let selection = select(&nodes, SelectOptions::default());
+        assert_eq!("relay.example.com", selection.selected[0].addr());
+        assert_eq!(2, selection.rejected.iter().filter(|r| r.reason == RejectReason::Duplicate).count());
+    }
+
+    #[test]
+    fn selection_topology() {
+        let options = SelectOptions { count: 2, ..Default::default() };
+        let topology = select(&pinged_vec(), options).topology();
+
+        let value: Value = serde_json::from_str(&topology.to_json().unwrap()).unwrap();
+        assert_eq!(Value::from("10.0.0.2"), value["Producers"][0]["addr"]);
+        assert_eq!(Value::from(1), value["Producers"][0]["valency"]);
+        assert_eq!(2, value["Producers"].as_array().unwrap().len());
+    }
+}
@@ -0,0 +1,234 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::node::{validate_nodes, Node, NodeId};
+use crate::types::{AdakaiError, AdakaiResult, NetworkType};
+
+mod topology_tests;
+
+mod p2p;
+pub use p2p::{AnyTopology, LocalRootGroup, P2PTopology, PublicRootGroup};
+
+/// Producer is the on-disk representation of one entry of the `Producers` array of a legacy
+/// cardano-node topology file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Producer {
+    addr: String,
+    port: u16,
+
+    #[serde(default = "default_valency")]
+    valency: u16,
+
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    continent: String,
+
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    state: String,
+
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+/// TopologyFile is the on-disk representation of a legacy cardano-node topology file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct TopologyFile {
+    #[serde(rename = "Producers")]
+    producers: Vec<Producer>,
+
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+fn default_valency() -> u16 {
+    1
+}
+
+impl Producer {
+    fn into_node(self, network_type: NetworkType) -> Node {
+        let mut node = Node::default();
+        node.set_addr(self.addr);
+        node.set_port(self.port);
+        node.set_valency(self.valency);
+        node.set_continent(self.continent);
+        node.set_state(self.state);
+        node.set_network_type(network_type);
+        node.set_extra(self.extra);
+        node
+    }
+
+    fn from_node(node: &Node) -> Producer {
+        Producer {
+            addr: node.addr().to_string(),
+            port: node.port(),
+            valency: node.valency(),
+            continent: node.continent().to_string(),
+            state: node.state().to_string(),
+            extra: node.extra().clone(),
+        }
+    }
+}
+
+/// Topology holds the list of nodes (producers) described by a legacy cardano-node topology file:
+/// ``` [json]
+/// {
+///   "Producers": [
+///     {
+///       "addr": "costa-rica.adakailabs.com",
+///       "port": 5000,
+///       "valency": 1
+///     }
+///   ]
+/// }
+/// ```
+/// Fields not modeled by Topology or Node are preserved and written back when the topology is
+/// serialized.
+#[derive(Debug, Clone, Default)]
+pub struct Topology {
+    nodes: Vec<Node>,
+    extra: Map<String, Value>,
+}
+
+impl Topology {
+    /// new: returns a topology containing the given nodes
+    pub fn new(nodes: Vec<Node>) -> Topology {
+        Topology {
+            nodes,
+            extra: Map::new(),
+        }
+    }
+
+    /// new_from_json: takes a json encoded legacy topology and deserializes it into a Topology.
+    /// # Arguments:
+    /// - **network_type**: network type assigned to every node of the topology.
+    /// - **json**: a json encoded string with a `Producers` array. A producer without `valency`
+    ///   gets a valency of 1, as cardano-node does.
+    pub fn new_from_json(network_type: NetworkType, json: String) -> AdakaiResult<Topology> {
+        let file: TopologyFile = serde_json::from_str(&json)?;
+        Ok(Topology {
+            nodes: file
+                .producers
+                .into_iter()
+                .map(|p| p.into_node(network_type))
+                .collect(),
+            extra: file.extra,
+        })
+    }
+
+    /// new_from_file: reads a legacy topology file (see new_from_json).
+    pub fn new_from_file<P: AsRef<Path>>(network_type: NetworkType, path: P) -> AdakaiResult<Topology> {
+        let json = fs::read_to_string(path)?;
+        Topology::new_from_json(network_type, json)
+    }
+
+    /// to_json: serializes the topology into the json format read by cardano-node.
+    /// It fails if any node has an empty address or a zero port, as cardano-node would refuse such
+    /// a file.
+    pub fn to_json(&self) -> AdakaiResult<String> {
+        for node in self.nodes.iter() {
+            if node.addr().is_empty() || node.port() == 0 {
+                return Err(AdakaiError::Topology(format!("invalid producer: '{}:{}'", node.addr(), node.port())));
+            }
+        }
+
+        let file = TopologyFile {
+            producers: self.nodes.iter().map(Producer::from_node).collect(),
+            extra: self.extra.clone(),
+        };
+        Ok(serde_json::to_string_pretty(&file)?)
+    }
+
+    /// validate: checks every node of the topology (see node::NodeBuilder) and returns the
+    /// topology with its continents and countries normalized, or AdakaiError::Validation listing
+    /// every invalid node and all its errors.
+    pub fn validate(&self) -> AdakaiResult<Topology> {
+        Ok(Topology {
+            nodes: validate_nodes(&self.nodes)?,
+            extra: self.extra.clone(),
+        })
+    }
+
+    /// write_file: serializes the topology (see to_json) and writes it to the given path.
+    pub fn write_file<P: AsRef<Path>>(&self, path: P) -> AdakaiResult<()> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    /// nodes: returns the nodes of the topology, in file order
+    pub fn nodes(&self) -> &Vec<Node> {
+        &self.nodes
+    }
+
+    /// nodes_mut: returns a mutable reference to the nodes of the topology
+    pub fn nodes_mut(&mut self) -> &mut Vec<Node> {
+        &mut self.nodes
+    }
+
+    /// into_nodes: consumes the topology and returns its nodes, e.g. for passing them to
+    /// `ping::ping_vec`
+    pub fn into_nodes(self) -> Vec<Node> {
+        self.nodes
+    }
+
+    /// set_nodes: replaces the nodes of the topology, keeping the top level fields
+    pub fn set_nodes(&mut self, nodes: Vec<Node>) {
+        self.nodes = nodes;
+    }
+
+    /// to_p2p: converts the topology into the P2P format (see P2PTopology::from_legacy)
+    pub fn to_p2p(&self) -> P2PTopology {
+        P2PTopology::from_legacy(self)
+    }
+
+    /// extra: returns the top level fields of the topology file other than `Producers`
+    pub fn extra(&self) -> &Map<String, Value> {
+        &self.extra
+    }
+}
+
+/// TopologyDiff holds the differences between two lists of nodes, nodes being matched on their
+/// identity (see Node::id)
+#[derive(Debug, Clone, Default)]
+pub struct TopologyDiff {
+    /// added are the nodes only in the new list
+    pub added: Vec<Node>,
+
+    /// removed are the nodes only in the old list
+    pub removed: Vec<Node>,
+
+    /// changed are the `(old, new)` nodes in both lists whose valency, continent or state differ
+    pub changed: Vec<(Node, Node)>,
+}
+
+impl TopologyDiff {
+    /// is_empty: returns true if both lists hold the same nodes
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// diff: compares the nodes of two topologies, in any format (see AnyTopology::nodes)
+/// # Arguments:
+/// * `old:` the nodes of the current topology
+/// * `new:` the nodes of the topology replacing it
+pub fn diff(old: &[Node], new: &[Node]) -> TopologyDiff {
+    let find = |nodes: &[Node], id: &NodeId| nodes.iter().find(|n| &n.id() == id).cloned();
+    let mut result = TopologyDiff::default();
+    for node in new {
+        match find(old, &node.id()) {
+            None => result.added.push(node.clone()),
+            Some(previous) => {
+                if previous.valency() != node.valency()
+                    || previous.continent() != node.continent()
+                    || previous.state() != node.state()
+                {
+                    result.changed.push((previous, node.clone()));
+                }
+            }
+        }
+    }
+    result.removed = old.iter().filter(|n| find(new, &n.id()).is_none()).cloned().collect();
+    result
+}
@@ -0,0 +1,587 @@
+use std::fs;
+use std::path::Path;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::node::{validate_nodes, Node};
+use crate::topology::Topology;
+use crate::types::{AdakaiError, AdakaiResult, NetworkType};
+
+/// AccessPoint is the on-disk representation of an entry of an `accessPoints` or `bootstrapPeers`
+/// array of a P2P topology file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct AccessPoint {
+    address: String,
+    port: u16,
+
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+/// LocalRootFile is the on-disk representation of a `localRoots` group.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct LocalRootFile {
+    access_points: Vec<AccessPoint>,
+
+    #[serde(default)]
+    advertise: bool,
+
+    #[serde(default)]
+    trustable: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    valency: Option<u16>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hot_valency: Option<u16>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    warm_valency: Option<u16>,
+
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+/// PublicRootFile is the on-disk representation of a `publicRoots` group.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct PublicRootFile {
+    access_points: Vec<AccessPoint>,
+
+    #[serde(default)]
+    advertise: bool,
+
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+/// P2PFile is the on-disk representation of a P2P cardano-node topology file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct P2PFile {
+    #[serde(default)]
+    local_roots: Vec<LocalRootFile>,
+
+    #[serde(default)]
+    public_roots: Vec<PublicRootFile>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bootstrap_peers: Option<Vec<AccessPoint>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    use_ledger_after_slot: Option<i64>,
+
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+impl AccessPoint {
+    fn into_node(self, network_type: NetworkType) -> Node {
+        let mut node = Node::default();
+        node.set_addr(self.address);
+        node.set_port(self.port);
+        node.set_valency(1);
+        node.set_network_type(network_type);
+        node.set_extra(self.extra);
+        node
+    }
+
+    fn from_node(node: &Node) -> AdakaiResult<AccessPoint> {
+        if node.addr().is_empty() || node.port() == 0 {
+            return Err(AdakaiError::Topology(format!("invalid access point: '{}:{}'", node.addr(), node.port())));
+        }
+
+        Ok(AccessPoint {
+            address: node.addr().to_string(),
+            port: node.port(),
+            extra: node.extra().clone(),
+        })
+    }
+}
+
+fn into_nodes(access_points: Vec<AccessPoint>, network_type: NetworkType) -> Vec<Node> {
+    access_points
+        .into_iter()
+        .map(|a| a.into_node(network_type))
+        .collect()
+}
+
+fn from_nodes(nodes: &[Node]) -> AdakaiResult<Vec<AccessPoint>> {
+    nodes.iter().map(AccessPoint::from_node).collect()
+}
+
+/// LocalRootGroup is a group of `localRoots` access points that share the same valency and flags.
+#[derive(Debug, Clone, Default)]
+pub struct LocalRootGroup {
+    access_points: Vec<Node>,
+    advertise: bool,
+    trustable: bool,
+    hot_valency: u16,
+    warm_valency: Option<u16>,
+    uses_valency_key: bool,
+    extra: Map<String, Value>,
+}
+
+/// all_hot: returns the hot valency keeping every access point hot, saturated at u16::MAX
+fn all_hot<T>(access_points: &[T]) -> u16 {
+    u16::try_from(access_points.len()).unwrap_or(u16::MAX)
+}
+
+impl LocalRootGroup {
+    /// new: returns a group with the given access points, not advertised nor trustable, that keeps
+    /// all of its access points hot.
+    pub fn new(access_points: Vec<Node>) -> LocalRootGroup {
+        LocalRootGroup {
+            hot_valency: all_hot(&access_points),
+            access_points,
+            ..Default::default()
+        }
+    }
+
+    /// access_points: returns the nodes of the group
+    pub fn access_points(&self) -> &Vec<Node> {
+        &self.access_points
+    }
+
+    /// access_points_mut: returns a mutable reference to the nodes of the group
+    pub fn access_points_mut(&mut self) -> &mut Vec<Node> {
+        &mut self.access_points
+    }
+
+    /// advertise: returns true if the peers of the group can be shared with other nodes
+    pub fn advertise(&self) -> bool {
+        self.advertise
+    }
+
+    /// set_advertise: sets whether the peers of the group can be shared with other nodes
+    pub fn set_advertise(&mut self, advertise: bool) {
+        self.advertise = advertise;
+    }
+
+    /// trustable: returns true if the group can be used while the node is syncing in Genesis mode
+    pub fn trustable(&self) -> bool {
+        self.trustable
+    }
+
+    /// set_trustable: sets whether the group can be used while the node is syncing
+    pub fn set_trustable(&mut self, trustable: bool) {
+        self.trustable = trustable;
+    }
+
+    /// hot_valency: returns the number of peers of the group the node keeps active.
+    /// The value is read either from `hotValency` or from the older `valency` key.
+    pub fn hot_valency(&self) -> u16 {
+        self.hot_valency
+    }
+
+    /// set_hot_valency: sets the number of peers of the group the node keeps active
+    pub fn set_hot_valency(&mut self, hot_valency: u16) {
+        self.hot_valency = hot_valency;
+    }
+
+    /// warm_valency: returns the number of established connections of the group, if set.
+    /// cardano-node defaults it to the hot valency.
+    pub fn warm_valency(&self) -> Option<u16> {
+        self.warm_valency
+    }
+
+    /// set_warm_valency: sets the number of established connections of the group
+    pub fn set_warm_valency(&mut self, warm_valency: Option<u16>) {
+        self.warm_valency = warm_valency;
+    }
+
+    /// extra: returns the fields of the group not modeled by LocalRootGroup
+    pub fn extra(&self) -> &Map<String, Value> {
+        &self.extra
+    }
+
+    fn from_file(file: LocalRootFile, network_type: NetworkType) -> LocalRootGroup {
+        let hot_valency = file
+            .hot_valency
+            .or(file.valency)
+            .unwrap_or_else(|| all_hot(&file.access_points));
+
+        LocalRootGroup {
+            uses_valency_key: file.valency.is_some() && file.hot_valency.is_none(),
+            access_points: into_nodes(file.access_points, network_type),
+            advertise: file.advertise,
+            trustable: file.trustable,
+            hot_valency,
+            warm_valency: file.warm_valency,
+            extra: file.extra,
+        }
+    }
+
+    fn to_file(&self) -> AdakaiResult<LocalRootFile> {
+        let (valency, hot_valency) = if self.uses_valency_key {
+            (Some(self.hot_valency), None)
+        } else {
+            (None, Some(self.hot_valency))
+        };
+
+        Ok(LocalRootFile {
+            access_points: from_nodes(&self.access_points)?,
+            advertise: self.advertise,
+            trustable: self.trustable,
+            valency,
+            hot_valency,
+            warm_valency: self.warm_valency,
+            extra: self.extra.clone(),
+        })
+    }
+}
+
+/// PublicRootGroup is a group of `publicRoots` access points.
+#[derive(Debug, Clone, Default)]
+pub struct PublicRootGroup {
+    access_points: Vec<Node>,
+    advertise: bool,
+    extra: Map<String, Value>,
+}
+
+impl PublicRootGroup {
+    /// new: returns a non advertised group with the given access points
+    pub fn new(access_points: Vec<Node>) -> PublicRootGroup {
+        PublicRootGroup {
+            access_points,
+            ..Default::default()
+        }
+    }
+
+    /// access_points: returns the nodes of the group
+    pub fn access_points(&self) -> &Vec<Node> {
+        &self.access_points
+    }
+
+    /// access_points_mut: returns a mutable reference to the nodes of the group
+    pub fn access_points_mut(&mut self) -> &mut Vec<Node> {
+        &mut self.access_points
+    }
+
+    /// advertise: returns true if the peers of the group can be shared with other nodes
+    pub fn advertise(&self) -> bool {
+        self.advertise
+    }
+
+    /// set_advertise: sets whether the peers of the group can be shared with other nodes
+    pub fn set_advertise(&mut self, advertise: bool) {
+        self.advertise = advertise;
+    }
+
+    /// extra: returns the fields of the group not modeled by PublicRootGroup
+    pub fn extra(&self) -> &Map<String, Value> {
+        &self.extra
+    }
+}
+
+/// P2PTopology holds the peers described by a P2P cardano-node topology file:
+/// ``` [json]
+/// {
+///   "bootstrapPeers": [
+///     { "address": "backbone.cardano.iog.io", "port": 3001 }
+///   ],
+///   "localRoots": [
+///     {
+///       "accessPoints": [ { "address": "costa-rica.adakailabs.com", "port": 5000 } ],
+///       "advertise": false,
+///       "trustable": true,
+///       "hotValency": 1
+///     }
+///   ],
+///   "publicRoots": [ { "accessPoints": [], "advertise": false } ],
+///   "useLedgerAfterSlot": 128908821
+/// }
+/// ```
+/// Every access point is mapped to a Node. Fields not modeled are preserved and written back when
+/// the topology is serialized.
+#[derive(Debug, Clone, Default)]
+pub struct P2PTopology {
+    local_roots: Vec<LocalRootGroup>,
+    public_roots: Vec<PublicRootGroup>,
+    bootstrap_peers: Option<Vec<Node>>,
+    use_ledger_after_slot: Option<i64>,
+    extra: Map<String, Value>,
+}
+
+impl P2PTopology {
+    /// new_from_json: takes a json encoded P2P topology and deserializes it into a P2PTopology.
+    /// # Arguments:
+    /// - **network_type**: network type assigned to every node of the topology.
+    /// - **json**: a json encoded string with the P2P topology.
+    pub fn new_from_json(network_type: NetworkType, json: String) -> AdakaiResult<P2PTopology> {
+        let file: P2PFile = serde_json::from_str(&json)?;
+        Ok(P2PTopology {
+            local_roots: file
+                .local_roots
+                .into_iter()
+                .map(|g| LocalRootGroup::from_file(g, network_type))
+                .collect(),
+            public_roots: file
+                .public_roots
+                .into_iter()
+                .map(|g| PublicRootGroup {
+                    access_points: into_nodes(g.access_points, network_type),
+                    advertise: g.advertise,
+                    extra: g.extra,
+                })
+                .collect(),
+            bootstrap_peers: file.bootstrap_peers.map(|b| into_nodes(b, network_type)),
+            use_ledger_after_slot: file.use_ledger_after_slot,
+            extra: file.extra,
+        })
+    }
+
+    /// new_from_file: reads a P2P topology file (see new_from_json).
+    pub fn new_from_file<P: AsRef<Path>>(network_type: NetworkType, path: P) -> AdakaiResult<P2PTopology> {
+        let json = fs::read_to_string(path)?;
+        P2PTopology::new_from_json(network_type, json)
+    }
+
+    /// to_json: serializes the topology into the P2P json format read by cardano-node.
+    pub fn to_json(&self) -> AdakaiResult<String> {
+        let file = P2PFile {
+            local_roots: self
+                .local_roots
+                .iter()
+                .map(LocalRootGroup::to_file)
+                .collect::<AdakaiResult<Vec<_>>>()?,
+            public_roots: self
+                .public_roots
+                .iter()
+                .map(|g| {
+                    Ok(PublicRootFile {
+                        access_points: from_nodes(&g.access_points)?,
+                        advertise: g.advertise,
+                        extra: g.extra.clone(),
+                    })
+                })
+                .collect::<AdakaiResult<Vec<_>>>()?,
+            bootstrap_peers: match &self.bootstrap_peers {
+                Some(nodes) => Some(from_nodes(nodes)?),
+                None => None,
+            },
+            use_ledger_after_slot: self.use_ledger_after_slot,
+            extra: self.extra.clone(),
+        };
+        Ok(serde_json::to_string_pretty(&file)?)
+    }
+
+    /// write_file: serializes the topology (see to_json) and writes it to the given path.
+    pub fn write_file<P: AsRef<Path>>(&self, path: P) -> AdakaiResult<()> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    /// local_roots: returns the `localRoots` groups
+    pub fn local_roots(&self) -> &Vec<LocalRootGroup> {
+        &self.local_roots
+    }
+
+    /// local_roots_mut: returns a mutable reference to the `localRoots` groups
+    pub fn local_roots_mut(&mut self) -> &mut Vec<LocalRootGroup> {
+        &mut self.local_roots
+    }
+
+    /// public_roots: returns the `publicRoots` groups
+    pub fn public_roots(&self) -> &Vec<PublicRootGroup> {
+        &self.public_roots
+    }
+
+    /// public_roots_mut: returns a mutable reference to the `publicRoots` groups
+    pub fn public_roots_mut(&mut self) -> &mut Vec<PublicRootGroup> {
+        &mut self.public_roots
+    }
+
+    /// bootstrap_peers: returns the `bootstrapPeers`, None when they are not set or disabled
+    pub fn bootstrap_peers(&self) -> Option<&Vec<Node>> {
+        self.bootstrap_peers.as_ref()
+    }
+
+    /// set_bootstrap_peers: sets the `bootstrapPeers`
+    pub fn set_bootstrap_peers(&mut self, bootstrap_peers: Option<Vec<Node>>) {
+        self.bootstrap_peers = bootstrap_peers;
+    }
+
+    /// use_ledger_after_slot: returns the slot after which the node uses ledger peers, a
+    /// negative value disables them
+    pub fn use_ledger_after_slot(&self) -> Option<i64> {
+        self.use_ledger_after_slot
+    }
+
+    /// set_use_ledger_after_slot: sets the slot after which the node uses ledger peers
+    pub fn set_use_ledger_after_slot(&mut self, slot: Option<i64>) {
+        self.use_ledger_after_slot = slot;
+    }
+
+    /// extra: returns the top level fields of the topology file not modeled by P2PTopology
+    pub fn extra(&self) -> &Map<String, Value> {
+        &self.extra
+    }
+
+    /// nodes: returns every access point of the topology (local roots, public roots and then
+    /// bootstrap peers), e.g. for passing them to `ping::ping_vec`
+    pub fn nodes(&self) -> Vec<Node> {
+        let local = self.local_roots.iter().flat_map(|g| g.access_points.iter());
+        let public = self.public_roots.iter().flat_map(|g| g.access_points.iter());
+        let bootstrap = self.bootstrap_peers.iter().flatten();
+        local.chain(public).chain(bootstrap).cloned().collect()
+    }
+
+    /// validate: checks every access point of the topology (see node::NodeBuilder) and returns
+    /// the topology with its access points normalized, or AdakaiError::Validation listing every
+    /// invalid access point and all its errors, positioned as in nodes.
+    pub fn validate(&self) -> AdakaiResult<P2PTopology> {
+        let valid = validate_nodes(&self.nodes())?;
+        let mut topology = self.clone();
+        let local = topology.local_roots.iter_mut().flat_map(|g| g.access_points.iter_mut());
+        let public = topology.public_roots.iter_mut().flat_map(|g| g.access_points.iter_mut());
+        let bootstrap = topology.bootstrap_peers.iter_mut().flatten();
+        for (node, normalized) in local.chain(public).chain(bootstrap).zip(valid) {
+            *node = normalized;
+        }
+        Ok(topology)
+    }
+
+    /// from_legacy: converts a legacy topology into a P2P one. Each producer becomes a
+    /// `localRoots` group of its own whose hot valency is the producer valency, so the node keeps
+    /// the same connections it had in legacy mode.
+    pub fn from_legacy(topology: &Topology) -> P2PTopology {
+        let local_roots = topology
+            .nodes()
+            .iter()
+            .map(|node| {
+                let mut access_point = node.clone();
+                access_point.set_valency(1);
+
+                let mut group = LocalRootGroup::new(vec![access_point]);
+                group.set_hot_valency(node.valency());
+                group
+            })
+            .collect();
+
+        P2PTopology {
+            local_roots,
+            ..Default::default()
+        }
+    }
+
+    /// to_legacy: converts the topology into a legacy one. Access points of the local and public
+    /// roots become producers, in that order and without duplicates. A group with a single access
+    /// point passes its hot valency to the producer, other producers get a valency of 1.
+    /// Bootstrap peers, ledger peers and the fields not modeled have no legacy equivalent: they
+    /// are dropped with a warning (see legacy_dropped).
+    pub fn to_legacy(&self) -> Topology {
+        let dropped = self.legacy_dropped();
+        if !dropped.is_empty() {
+            warn!("converting to a legacy topology drops {}", dropped.join(", "));
+        }
+        let mut producers: Vec<Node> = Vec::new();
+
+        let local = self
+            .local_roots
+            .iter()
+            .flat_map(|g| g.access_points.iter().map(move |n| (g, n)))
+            .map(|(g, n)| {
+                let mut producer = n.clone();
+                if g.access_points.len() == 1 {
+                    producer.set_valency(g.hot_valency.max(1));
+                } else {
+                    producer.set_valency(1);
+                }
+                producer
+            });
+
+        let public = self
+            .public_roots
+            .iter()
+            .flat_map(|g| g.access_points.iter())
+            .map(|n| {
+                let mut producer = n.clone();
+                producer.set_valency(1);
+                producer
+            });
+
+        for producer in local.chain(public) {
+            let duplicate = producers
+                .iter()
+                .any(|p| p.addr() == producer.addr() && p.port() == producer.port());
+            if !duplicate {
+                producers.push(producer);
+            }
+        }
+
+        Topology::new(producers)
+    }
+
+    /// legacy_dropped: returns the keys of the topology file that to_legacy drops: `bootstrapPeers`,
+    /// `useLedgerAfterSlot` and the top-level fields not modeled, empty if the conversion is lossless
+    pub fn legacy_dropped(&self) -> Vec<String> {
+        let mut dropped = Vec::new();
+        if self.bootstrap_peers.as_ref().is_some_and(|peers| !peers.is_empty()) {
+            dropped.push("bootstrapPeers".to_string());
+        }
+        if self.use_ledger_after_slot.is_some() {
+            dropped.push("useLedgerAfterSlot".to_string());
+        }
+        dropped.extend(self.extra.keys().cloned());
+        dropped
+    }
+}
+
+/// AnyTopology holds a topology file in either of the formats read by cardano-node.
+#[derive(Debug, Clone)]
+pub enum AnyTopology {
+    /// Legacy is a topology file with a `Producers` array
+    Legacy(Topology),
+
+    /// P2P is a topology file with `localRoots` / `publicRoots`
+    P2P(P2PTopology),
+}
+
+impl AnyTopology {
+    /// new_from_json: deserializes a topology, detecting its format from the presence of the
+    /// `Producers` key.
+    pub fn new_from_json(network_type: NetworkType, json: String) -> AdakaiResult<AnyTopology> {
+        let value: Value = serde_json::from_str(&json)?;
+        if value.get("Producers").is_some() {
+            Ok(AnyTopology::Legacy(Topology::new_from_json(network_type, json)?))
+        } else {
+            Ok(AnyTopology::P2P(P2PTopology::new_from_json(network_type, json)?))
+        }
+    }
+
+    /// new_from_file: reads a topology file in either format (see new_from_json).
+    pub fn new_from_file<P: AsRef<Path>>(network_type: NetworkType, path: P) -> AdakaiResult<AnyTopology> {
+        let json = fs::read_to_string(path)?;
+        AnyTopology::new_from_json(network_type, json)
+    }
+
+    /// nodes: returns every node of the topology
+    pub fn nodes(&self) -> Vec<Node> {
+        match self {
+            AnyTopology::Legacy(topology) => topology.nodes().clone(),
+            AnyTopology::P2P(topology) => topology.nodes(),
+        }
+    }
+
+    /// validate: checks every node of the topology and returns it normalized, or
+    /// AdakaiError::Validation listing every invalid node (see Topology::validate and
+    /// P2PTopology::validate)
+    pub fn validate(&self) -> AdakaiResult<AnyTopology> {
+        match self {
+            AnyTopology::Legacy(topology) => topology.validate().map(AnyTopology::Legacy),
+            AnyTopology::P2P(topology) => topology.validate().map(AnyTopology::P2P),
+        }
+    }
+
+    /// to_json: serializes the topology in its own format
+    pub fn to_json(&self) -> AdakaiResult<String> {
+        match self {
+            AnyTopology::Legacy(topology) => topology.to_json(),
+            AnyTopology::P2P(topology) => topology.to_json(),
+        }
+    }
+}
@@ -0,0 +1,291 @@
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use serde_json::Value;
+
+    use crate::node::Node;
+    use crate::topology::{diff, AnyTopology, LocalRootGroup, P2PTopology, Topology};
+    use crate::types::{AdakaiError, NetworkType};
+
+    const RELAY_TOPOLOGY: &str = include_str!("testdata/mainnet-relay-topology.json");
+    const FETCHED_TOPOLOGY: &str = include_str!("testdata/fetched-topology.json");
+    const P2P_TOPOLOGY: &str = include_str!("testdata/p2p-relay-topology.json");
+
+    fn assert_round_trip(json: &str) {
+        let topology = Topology::new_from_json(NetworkType::Mainnet, json.to_string()).unwrap();
+        let written = topology.to_json().unwrap();
+
+        let original: Value = serde_json::from_str(json).unwrap();
+        let round_trip: Value = serde_json::from_str(&written).unwrap();
+        assert_eq!(original, round_trip);
+    }
+
+    #[test]
+    fn load_relay_topology() {
+        let topology = Topology::new_from_json(NetworkType::Mainnet, RELAY_TOPOLOGY.to_string()).unwrap();
+        let nodes = topology.nodes();
+
+        assert_eq!(3, nodes.len());
+        assert_eq!("10.0.0.10", nodes[0].addr());
+        assert_eq!(6000, nodes[0].port());
+        assert_eq!(2, nodes[1].valency());
+        assert_eq!("North America", nodes[2].continent());
+        assert_eq!("CR", nodes[2].state());
+        assert!(nodes[0].extra().is_empty());
+    }
+
+    #[test]
+    fn load_fetched_topology_keeps_unknown_fields() {
+        let topology = Topology::new_from_json(NetworkType::Mainnet, FETCHED_TOPOLOGY.to_string()).unwrap();
+        let nodes = topology.nodes();
+
+        assert_eq!(2, nodes.len());
+        assert_eq!(Some(&Value::from(217)), nodes[0].extra().get("distance"));
+        assert_eq!(Some(&Value::from("201")), topology.extra().get("resultcode"));
+
+        // valency is optional in the file, cardano-node defaults it to 1
+        assert_eq!(1, nodes[1].valency());
+        assert_eq!("", nodes[1].state());
+    }
+
+    #[test]
+    fn round_trip_relay_topology() {
+        assert_round_trip(RELAY_TOPOLOGY);
+    }
+
+    #[test]
+    fn round_trip_fetched_topology() {
+        let topology = Topology::new_from_json(NetworkType::Mainnet, FETCHED_TOPOLOGY.to_string()).unwrap();
+        let written: Value = serde_json::from_str(&topology.to_json().unwrap()).unwrap();
+        let mut original: Value = serde_json::from_str(FETCHED_TOPOLOGY).unwrap();
+
+        // the defaulted valency is written explicitly
+        original["Producers"][1]["valency"] = Value::from(1);
+        assert_eq!(original, written);
+    }
+
+    #[test]
+    fn round_trip_file() {
+        let path = env::temp_dir().join(format!("adakairust-topology-{}.json", std::process::id()));
+        let topology = Topology::new_from_json(NetworkType::Mainnet, RELAY_TOPOLOGY.to_string()).unwrap();
+        topology.write_file(&path).unwrap();
+
+        let read = Topology::new_from_file(NetworkType::Mainnet, &path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(topology.to_json().unwrap(), read.to_json().unwrap());
+    }
+
+    #[test]
+    fn write_new_topology() {
+        let mut node = Node::default();
+        node.set_addr("costa-rica.adakailabs.com".to_string());
+        node.set_port(5000);
+        node.set_valency(1);
+
+        let json = Topology::new(vec![node]).to_json().unwrap();
+        let value: Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            serde_json::json!({"Producers": [{"addr": "costa-rica.adakailabs.com", "port": 5000, "valency": 1}]}),
+            value
+        );
+    }
+
+    #[test]
+    fn reject_invalid_producer() {
+        let topology = Topology::new(vec![Node::default()]);
+        assert!(topology.to_json().is_err());
+    }
+
+    #[test]
+    fn reject_missing_producers() {
+        assert!(Topology::new_from_json(NetworkType::Mainnet, "{}".to_string()).is_err());
+        assert!(Topology::new_from_json(NetworkType::Mainnet, r#"{"Producers": [{"port": 1}]}"#.to_string()).is_err());
+    }
+
+    #[test]
+    fn load_p2p_topology() {
+        let topology = P2PTopology::new_from_json(NetworkType::Mainnet, P2P_TOPOLOGY.to_string()).unwrap();
+
+        let local_roots = topology.local_roots();
+        assert_eq!(2, local_roots.len());
+        assert!(local_roots[0].trustable());
+        assert!(!local_roots[0].advertise());
+        assert_eq!(1, local_roots[0].hot_valency());
+        assert_eq!("10.0.0.10", local_roots[0].access_points()[0].addr());
+        assert_eq!(Some(&Value::from("block producer")), local_roots[0].access_points()[0].extra().get("name"));
+
+        // the older `valency` key is read as the hot valency
+        assert_eq!(2, local_roots[1].hot_valency());
+        assert!(local_roots[1].advertise());
+        assert_eq!(5000, local_roots[1].access_points()[0].port());
+
+        assert_eq!(1, topology.public_roots().len());
+        assert_eq!(2, topology.bootstrap_peers().unwrap().len());
+        assert_eq!(Some(128908821), topology.use_ledger_after_slot());
+        assert_eq!(Some(&Value::from("peer-snapshot.json")), topology.extra().get("peerSnapshotFile"));
+
+        assert_eq!(6, topology.nodes().len());
+    }
+
+    #[test]
+    fn round_trip_p2p_topology() {
+        let topology = P2PTopology::new_from_json(NetworkType::Mainnet, P2P_TOPOLOGY.to_string()).unwrap();
+        let written: Value = serde_json::from_str(&topology.to_json().unwrap()).unwrap();
+        let original: Value = serde_json::from_str(P2P_TOPOLOGY).unwrap();
+
+        assert_eq!(original, written);
+    }
+
+    #[test]
+    fn p2p_defaults() {
+        let json = r#"{"localRoots": [{"accessPoints": [{"address": "a", "port": 1}, {"address": "b", "port": 2}]}]}"#;
+        let topology = P2PTopology::new_from_json(NetworkType::Mainnet, json.to_string()).unwrap();
+        let group = &topology.local_roots()[0];
+
+        assert_eq!(2, group.hot_valency());
+        assert_eq!(None, group.warm_valency());
+        assert!(!group.trustable());
+        assert!(topology.public_roots().is_empty());
+        assert!(topology.bootstrap_peers().is_none());
+
+        let written: Value = serde_json::from_str(&topology.to_json().unwrap()).unwrap();
+        assert_eq!(Value::from(2), written["localRoots"][0]["hotValency"]);
+    }
+
+    #[test]
+    fn legacy_to_p2p_and_back() {
+        let legacy = Topology::new_from_json(NetworkType::Mainnet, RELAY_TOPOLOGY.to_string()).unwrap();
+        let p2p = legacy.to_p2p();
+
+        assert_eq!(3, p2p.local_roots().len());
+        assert_eq!(2, p2p.local_roots()[1].hot_valency());
+        assert_eq!("relays-new.cardano-mainnet.iohk.io", p2p.local_roots()[1].access_points()[0].addr());
+
+        let back = p2p.to_legacy();
+        let original: Value = serde_json::from_str(RELAY_TOPOLOGY).unwrap();
+        let round_trip: Value = serde_json::from_str(&back.to_json().unwrap()).unwrap();
+        assert_eq!(original, round_trip);
+    }
+
+    #[test]
+    fn p2p_to_legacy() {
+        let p2p = P2PTopology::new_from_json(NetworkType::Mainnet, P2P_TOPOLOGY.to_string()).unwrap();
+        let legacy = p2p.to_legacy();
+        let nodes = legacy.nodes();
+
+        assert_eq!(4, nodes.len());
+        assert_eq!("10.0.0.10", nodes[0].addr());
+        assert_eq!(1, nodes[0].valency());
+        assert_eq!("costa-rica.adakailabs.com", nodes[1].addr());
+        assert_eq!(1, nodes[1].valency());
+        assert_eq!("relays-new.cardano-mainnet.iohk.io", nodes[3].addr());
+        assert_eq!(vec!["bootstrapPeers", "useLedgerAfterSlot", "peerSnapshotFile"], p2p.legacy_dropped());
+
+        let lossless = Topology::new_from_json(NetworkType::Mainnet, RELAY_TOPOLOGY.to_string()).unwrap().to_p2p();
+        assert!(lossless.legacy_dropped().is_empty());
+    }
+
+    #[test]
+    fn p2p_to_legacy_skips_duplicates() {
+        let mut node = Node::default();
+        node.set_addr("costa-rica.adakailabs.com".to_string());
+        node.set_port(5000);
+
+        let mut p2p = P2PTopology::default();
+        p2p.local_roots_mut().push(LocalRootGroup::new(vec![node.clone()]));
+        p2p.local_roots_mut().push(LocalRootGroup::new(vec![node]));
+
+        assert_eq!(1, p2p.to_legacy().nodes().len());
+    }
+
+    #[test]
+    fn local_root_valency_saturates() {
+        assert_eq!(2, LocalRootGroup::new(vec![Node::default(); 2]).hot_valency());
+        let group = LocalRootGroup::new(vec![Node::default(); u16::MAX as usize + 1]);
+        assert_eq!(u16::MAX, group.hot_valency());
+    }
+
+    #[test]
+    fn detect_topology_format() {
+        match AnyTopology::new_from_json(NetworkType::Mainnet, RELAY_TOPOLOGY.to_string()).unwrap() {
+            AnyTopology::Legacy(t) => assert_eq!(3, t.nodes().len()),
+            AnyTopology::P2P(_) => panic!("expected a legacy topology"),
+        }
+
+        let p2p = AnyTopology::new_from_json(NetworkType::Mainnet, P2P_TOPOLOGY.to_string()).unwrap();
+        assert!(matches!(p2p, AnyTopology::P2P(_)));
+        assert_eq!(6, p2p.nodes().len());
+    }
+
+    #[test]
+    fn diff_topologies() {
+        let old = Topology::new_from_json(NetworkType::Mainnet, RELAY_TOPOLOGY.to_string()).unwrap().into_nodes();
+        assert!(diff(&old, &old).is_empty());
+
+        let mut new = old.clone();
+        let removed = new.remove(0);
+        let valency = new[0].valency();
+        new[0].set_valency(valency + 1);
+        let mut added = Node::default();
+        added.set_addr("relay.example.com".to_string());
+        added.set_port(3001);
+        new.push(added);
+        // the same node written differently is not a change
+        let mut renamed = new[1].clone();
+        renamed.set_addr(renamed.addr().to_uppercase());
+        new[1] = renamed;
+
+        let changes = diff(&old, &new);
+        assert_eq!(vec![removed.id()], changes.removed.iter().map(|n| n.id()).collect::<Vec<_>>());
+        assert_eq!(vec!["relay.example.com"], changes.added.iter().map(|n| n.addr()).collect::<Vec<_>>());
+        assert_eq!(1, changes.changed.len());
+        assert_eq!(old[1].valency() + 1, changes.changed[0].1.valency());
+    }
+
+    #[test]
+    fn validate_topology() {
+        let topology = Topology::new_from_json(NetworkType::Mainnet, RELAY_TOPOLOGY.to_string()).unwrap();
+        assert_eq!(topology.nodes().len(), topology.validate().unwrap().nodes().len());
+
+        let json = r#"{"Producers": [
+            {"addr": "relay.example.com", "port": 3001, "valency": 2},
+            {"addr": "", "port": 0, "valency": 1},
+            {"addr": "bad host", "port": 3001, "valency": 500},
+            {"addr": "10.0.0.1", "port": 3001, "valency": 1, "continent": "Atlantis"}
+        ]}"#;
+        let topology = Topology::new_from_json(NetworkType::Mainnet, json.to_string()).unwrap();
+        match topology.validate() {
+            Err(AdakaiError::Validation(errors)) => {
+                assert_eq!(vec![1, 2, 3], errors.0.iter().map(|n| n.position).collect::<Vec<_>>());
+                assert_eq!(2, errors.0[0].errors.len());
+                assert_eq!(2, errors.0[1].errors.len());
+            }
+            other => panic!("expected a validation error, got {:?}", other.map(|t| t.nodes().len())),
+        }
+    }
+
+    #[test]
+    fn validate_p2p_topology() {
+        let topology = AnyTopology::new_from_json(NetworkType::Mainnet, P2P_TOPOLOGY.to_string()).unwrap();
+        let valid = topology.validate().unwrap();
+        assert_eq!(topology.nodes(), valid.nodes());
+
+        let json = r#"{
+            "localRoots": [{"accessPoints": [{"address": "10.0.0.10", "port": 6000}], "advertise": false, "valency": 1}],
+            "publicRoots": [{"accessPoints": [{"address": "256.1.1.1", "port": 3001}], "advertise": false}],
+            "bootstrapPeers": [{"address": "backbone.cardano.iog.io", "port": 0}]
+        }"#;
+        let topology = AnyTopology::new_from_json(NetworkType::Mainnet, json.to_string()).unwrap();
+        match topology.validate() {
+            Err(AdakaiError::Validation(errors)) => {
+                assert_eq!(vec![1, 2], errors.0.iter().map(|n| n.position).collect::<Vec<_>>());
+                assert_eq!("256.1.1.1", errors.0[0].addr);
+            }
+            other => panic!("expected a validation error, got {:?}", other.map(|t| t.nodes().len())),
+        }
+    }
+}
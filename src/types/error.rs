@@ -0,0 +1,128 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::node::ValidationErrors;
+use crate::ping::PingError;
+use crate::types::ParseNetworkTypeError;
+use crate::updater::UpdaterError;
+
+/// AdakaiError holds the errors returned by the functions of the crate. It is `Send + Sync`, so
+/// it can be returned from asynchronous tasks, and the underlying error is available through
+/// `Error::source`.
+#[derive(Debug)]
+pub enum AdakaiError {
+    /// Json: a JSON document could not be decoded or encoded
+    Json(serde_json::Error),
+
+    /// Cbor: a CBOR message could not be decoded or encoded
+    Cbor(serde_cbor::Error),
+
+    /// Io: a file or socket operation failed
+    Io(io::Error),
+
+    /// Network: a node or a service could not be reached, or answered with an error
+    Network(Box<dyn Error + Send + Sync>),
+
+    /// Topology: a topology is not valid, e.g. a producer without address, holds the reason
+    Topology(String),
+
+    /// Validation: nodes are not valid (see node::NodeBuilder), holds every invalid node
+    Validation(ValidationErrors),
+
+    /// File: the error is about a file, holds the path of the file and the error
+    File {
+        /// path is the path of the file
+        path: PathBuf,
+
+        /// error is the error met reading, parsing or writing the file
+        error: Box<AdakaiError>,
+    },
+
+    /// Config: a configuration value is not valid (options, rules, network name...), holds the
+    /// reason
+    Config(String),
+}
+
+impl fmt::Display for AdakaiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdakaiError::Json(e) => write!(f, "json error: {}", e),
+            AdakaiError::Cbor(e) => write!(f, "cbor error: {}", e),
+            AdakaiError::Io(e) => write!(f, "io error: {}", e),
+            AdakaiError::Network(e) => write!(f, "network error: {}", e),
+            AdakaiError::Topology(reason) => write!(f, "invalid topology: {}", reason),
+            AdakaiError::Validation(e) => write!(f, "validation failed: {}", e),
+            AdakaiError::File { path, error } => write!(f, "{}: {}", path.display(), error),
+            AdakaiError::Config(reason) => write!(f, "invalid configuration: {}", reason),
+        }
+    }
+}
+
+impl AdakaiError {
+    /// in_file: returns the error with the path of the file it is about, e.g. to tell which of
+    /// several topology files could not be read
+    pub fn in_file<P: AsRef<Path>>(self, path: P) -> AdakaiError {
+        AdakaiError::File {
+            path: path.as_ref().to_path_buf(),
+            error: Box::new(self),
+        }
+    }
+}
+
+impl Error for AdakaiError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AdakaiError::Json(e) => Some(e),
+            AdakaiError::Cbor(e) => Some(e),
+            AdakaiError::Io(e) => Some(e),
+            AdakaiError::Network(e) => Some(e.as_ref()),
+            AdakaiError::Validation(e) => Some(e),
+            AdakaiError::File { error, .. } => Some(error.as_ref()),
+            AdakaiError::Topology(_) | AdakaiError::Config(_) => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for AdakaiError {
+    fn from(e: serde_json::Error) -> Self {
+        AdakaiError::Json(e)
+    }
+}
+
+impl From<serde_cbor::Error> for AdakaiError {
+    fn from(e: serde_cbor::Error) -> Self {
+        AdakaiError::Cbor(e)
+    }
+}
+
+impl From<io::Error> for AdakaiError {
+    fn from(e: io::Error) -> Self {
+        AdakaiError::Io(e)
+    }
+}
+
+impl From<PingError> for AdakaiError {
+    fn from(e: PingError) -> Self {
+        AdakaiError::Network(Box::new(e))
+    }
+}
+
+impl From<UpdaterError> for AdakaiError {
+    fn from(e: UpdaterError) -> Self {
+        AdakaiError::Network(Box::new(e))
+    }
+}
+
+impl From<ValidationErrors> for AdakaiError {
+    fn from(e: ValidationErrors) -> Self {
+        AdakaiError::Validation(e)
+    }
+}
+
+impl From<ParseNetworkTypeError> for AdakaiError {
+    fn from(e: ParseNetworkTypeError) -> Self {
+        AdakaiError::Config(e.to_string())
+    }
+}
@@ -1,44 +1,231 @@
 use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
 
-///
+mod country;
+mod error;
+mod types_tests;
+
+/// types is kept for compatibility, the types are declared in this module
+#[allow(clippy::module_inception)]
 pub mod types;
 
-/// AdakaiResult is a generic result type
-pub type AdakaiResult<T> = Result<T, Box<dyn Error>>;
+pub use country::{country_code, country_name};
+pub use error::AdakaiError;
+
+/// AdakaiResult is the result type of the functions of the crate
+pub type AdakaiResult<T> = Result<T, AdakaiError>;
 
 /// MAINNET_MAGIC for cardano main network
 pub const MAINNET_MAGIC: u32 = 764824073;
 
-/// TESTNET_MAGIC for cardano test network
+/// TESTNET_MAGIC for cardano test network (legacy public testnet)
 pub const TESTNET_MAGIC: u32 = 1097911063;
 
-/// NetworkType holds the two different cardano networks supported
-#[derive(Clone, Copy,Serialize, Deserialize, Debug)]
+/// PREPROD_MAGIC for cardano pre-production test network
+pub const PREPROD_MAGIC: u32 = 1;
+
+/// PREVIEW_MAGIC for cardano preview test network
+pub const PREVIEW_MAGIC: u32 = 2;
+
+/// SANCHONET_MAGIC for cardano governance test network
+pub const SANCHONET_MAGIC: u32 = 4;
+
+/// NetworkType holds the cardano networks supported
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq, Hash)]
 pub enum NetworkType {
     /// Mainnet for cardano
+    #[default]
     Mainnet,
 
-    /// Testnet for cardano
+    /// Testnet for cardano (legacy public testnet)
     TestNet,
+
+    /// Preprod for the cardano pre-production test network
+    Preprod,
+
+    /// Preview for the cardano preview test network
+    Preview,
+
+    /// Sanchonet for the cardano governance test network
+    Sanchonet,
+
+    /// Custom for any other network, holds its magic
+    Custom(u32),
 }
 
 /// NodeType holds the two different cardano node types used in a cardano staking pool
-#[derive(Clone, Copy,Serialize, Deserialize, Debug)]
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq, Hash)]
 pub enum NodeType {
     /// Relay node
+    #[default]
     Relay,
 
     /// Producer node
     Producer,
 }
 
+/// Continent holds the continents a node can be located on
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Continent {
+    /// Africa
+    Africa,
+
+    /// Antarctica
+    Antarctica,
+
+    /// Asia
+    Asia,
+
+    /// Europe
+    Europe,
+
+    /// NorthAmerica, Central America and the Caribbean included
+    NorthAmerica,
+
+    /// Oceania, Australia included
+    Oceania,
+
+    /// SouthAmerica
+    SouthAmerica,
+}
+
+impl NetworkType {
+    /// magic: returns the network magic used in the handshake with nodes of the network
+    pub fn magic(&self) -> u32 {
+        match self {
+            NetworkType::Mainnet => MAINNET_MAGIC,
+            NetworkType::TestNet => TESTNET_MAGIC,
+            NetworkType::Preprod => PREPROD_MAGIC,
+            NetworkType::Preview => PREVIEW_MAGIC,
+            NetworkType::Sanchonet => SANCHONET_MAGIC,
+            NetworkType::Custom(magic) => *magic,
+        }
+    }
+
+    /// from_magic: returns the network running with the magic, Custom if it is not a known one
+    pub fn from_magic(magic: u32) -> NetworkType {
+        match magic {
+            MAINNET_MAGIC => NetworkType::Mainnet,
+            TESTNET_MAGIC => NetworkType::TestNet,
+            PREPROD_MAGIC => NetworkType::Preprod,
+            PREVIEW_MAGIC => NetworkType::Preview,
+            SANCHONET_MAGIC => NetworkType::Sanchonet,
+            magic => NetworkType::Custom(magic),
+        }
+    }
+}
+
+impl fmt::Display for NetworkType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkType::Mainnet => write!(f, "mainnet"),
+            NetworkType::TestNet => write!(f, "testnet"),
+            NetworkType::Preprod => write!(f, "preprod"),
+            NetworkType::Preview => write!(f, "preview"),
+            NetworkType::Sanchonet => write!(f, "sanchonet"),
+            NetworkType::Custom(magic) => write!(f, "magic={}", magic),
+        }
+    }
+}
+
+impl fmt::Display for Continent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Continent::Africa => "Africa",
+            Continent::Antarctica => "Antarctica",
+            Continent::Asia => "Asia",
+            Continent::Europe => "Europe",
+            Continent::NorthAmerica => "North America",
+            Continent::Oceania => "Oceania",
+            Continent::SouthAmerica => "South America",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// ParseContinentError is returned when a string does not name a continent
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseContinentError(String);
+
+impl fmt::Display for ParseContinentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown continent '{}'", self.0)
+    }
+}
+
+impl Error for ParseContinentError {}
 
-impl Default for NetworkType {
-    fn default() -> Self { NetworkType::Mainnet }
+impl FromStr for Continent {
+    type Err = ParseContinentError;
+
+    /// from_str: parses a continent name or its two letters code (case insensitive, spaces,
+    /// dashes and underscores ignored), e.g. `North America`, `north_america` or `NA`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name: String = s
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-' && *c != '_')
+            .collect::<String>()
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "africa" | "af" => Ok(Continent::Africa),
+            "antarctica" | "an" => Ok(Continent::Antarctica),
+            "asia" | "as" => Ok(Continent::Asia),
+            "europe" | "eu" => Ok(Continent::Europe),
+            "northamerica" | "na" | "centralamerica" => Ok(Continent::NorthAmerica),
+            "oceania" | "oc" | "australia" => Ok(Continent::Oceania),
+            "southamerica" | "sa" => Ok(Continent::SouthAmerica),
+            _ => Err(ParseContinentError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for NodeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeType::Relay => write!(f, "relay"),
+            NodeType::Producer => write!(f, "producer"),
+        }
+    }
 }
 
-impl Default for NodeType {
-    fn default() -> Self { NodeType::Relay }
-}
\ No newline at end of file
+/// ParseNetworkTypeError is returned when a string does not name a network
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseNetworkTypeError(String);
+
+impl fmt::Display for ParseNetworkTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown network '{}', expected mainnet, testnet, preprod, preview, sanchonet or magic=<number>",
+            self.0
+        )
+    }
+}
+
+impl Error for ParseNetworkTypeError {}
+
+impl FromStr for NetworkType {
+    type Err = ParseNetworkTypeError;
+
+    /// from_str: parses a network name (case insensitive) or a magic, written `magic=N` or `N`.
+    /// A magic of a known network returns that network.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase();
+        match name.as_str() {
+            "mainnet" => Ok(NetworkType::Mainnet),
+            "testnet" => Ok(NetworkType::TestNet),
+            "preprod" => Ok(NetworkType::Preprod),
+            "preview" => Ok(NetworkType::Preview),
+            "sanchonet" => Ok(NetworkType::Sanchonet),
+            _ => name
+                .strip_prefix("magic=")
+                .unwrap_or(&name)
+                .parse::<u32>()
+                .map(NetworkType::from_magic)
+                .map_err(|_| ParseNetworkTypeError(s.to_string())),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::io;
+    use std::str::FromStr;
+
+    use crate::node::{InvalidNode, Node, ValidationError, ValidationErrors};
+    use crate::ping::PingError;
+    use crate::topology::Topology;
+    use crate::types::{country_code, country_name, AdakaiError, Continent, NetworkType, MAINNET_MAGIC, PREPROD_MAGIC, PREVIEW_MAGIC, SANCHONET_MAGIC, TESTNET_MAGIC};
+
+    #[test]
+    fn network_magic() {
+        assert_eq!(MAINNET_MAGIC, NetworkType::Mainnet.magic());
+        assert_eq!(TESTNET_MAGIC, NetworkType::TestNet.magic());
+        assert_eq!(PREPROD_MAGIC, NetworkType::Preprod.magic());
+        assert_eq!(PREVIEW_MAGIC, NetworkType::Preview.magic());
+        assert_eq!(SANCHONET_MAGIC, NetworkType::Sanchonet.magic());
+        assert_eq!(42, NetworkType::Custom(42).magic());
+
+        assert_eq!(NetworkType::Preview, NetworkType::from_magic(2));
+        assert_eq!(NetworkType::Custom(42), NetworkType::from_magic(42));
+    }
+
+    #[test]
+    fn network_from_str() {
+        assert_eq!(Ok(NetworkType::Mainnet), NetworkType::from_str("mainnet"));
+        assert_eq!(Ok(NetworkType::Preprod), NetworkType::from_str("PreProd"));
+        assert_eq!(Ok(NetworkType::Preview), "preview".parse());
+        assert_eq!(Ok(NetworkType::Sanchonet), "sanchonet".parse());
+        assert_eq!(Ok(NetworkType::Custom(42)), "magic=42".parse());
+        assert_eq!(Ok(NetworkType::Custom(42)), "42".parse());
+        assert_eq!(Ok(NetworkType::Preprod), "magic=1".parse());
+        assert!("devnet".parse::<NetworkType>().is_err());
+        assert!("magic=".parse::<NetworkType>().is_err());
+    }
+
+    #[test]
+    fn network_display_round_trip() {
+        let networks = [
+            NetworkType::Mainnet,
+            NetworkType::TestNet,
+            NetworkType::Preprod,
+            NetworkType::Preview,
+            NetworkType::Sanchonet,
+            NetworkType::Custom(42),
+        ];
+        for network in networks {
+            assert_eq!(Ok(network), network.to_string().parse());
+        }
+        assert_eq!("magic=42", NetworkType::Custom(42).to_string());
+    }
+
+    fn assert_send_sync<T: Send + Sync + 'static>(_: &T) {}
+
+    #[test]
+    fn adakai_error() {
+        let json = Node::new_from_json(NetworkType::Mainnet, "{\"addr\": ".to_string()).unwrap_err();
+        assert_send_sync(&json);
+        assert!(matches!(json, AdakaiError::Json(_)));
+        assert!(json.to_string().starts_with("json error: "), "{}", json);
+        assert!(json.source().unwrap().downcast_ref::<serde_json::Error>().is_some());
+
+        let io = Topology::new_from_file(NetworkType::Mainnet, "/nonexistent/topology.json").unwrap_err();
+        assert!(matches!(&io, AdakaiError::Io(e) if e.kind() == io::ErrorKind::NotFound));
+
+        let mut node = Node::default();
+        node.set_port(3001);
+        let topology = Topology::new(vec![node]).to_json().unwrap_err();
+        assert!(matches!(&topology, AdakaiError::Topology(reason) if reason == "invalid producer: ':3001'"));
+        assert!(topology.source().is_none());
+
+        let network = AdakaiError::from(PingError::Timeout("handshake".to_string()));
+        let source = network.source().unwrap().downcast_ref::<PingError>();
+        assert_eq!(Some(&PingError::Timeout("handshake".to_string())), source);
+
+        let config = AdakaiError::from("nonet".parse::<NetworkType>().unwrap_err());
+        assert!(matches!(config, AdakaiError::Config(_)));
+
+        let cbor = AdakaiError::from(serde_cbor::from_slice::<u64>(&[0xff]).unwrap_err());
+        assert!(matches!(cbor, AdakaiError::Cbor(_)));
+
+        let file = AdakaiError::Topology("no producer".to_string()).in_file("topology.json");
+        assert_eq!("topology.json: invalid topology: no producer", file.to_string());
+        assert_eq!("invalid topology: no producer", file.source().unwrap().to_string());
+        let validation = AdakaiError::Validation(ValidationErrors(vec![InvalidNode {
+            position: 2,
+            addr: "relay".to_string(),
+            port: 0,
+            errors: vec![ValidationError::InvalidPort(0)],
+        }]));
+        assert_eq!("validation failed: 1 invalid nodes: node #2 'relay:0': invalid port 0", validation.to_string());
+
+        // crosses asynchronous tasks
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let error = runtime.block_on(async { tokio::spawn(async { Err::<(), _>(AdakaiError::Config("test".to_string())) }).await.unwrap() });
+        assert_eq!("invalid configuration: test", error.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn continents_and_countries() {
+        assert_eq!(Ok(Continent::NorthAmerica), "north_america".parse());
+        assert_eq!(Ok(Continent::NorthAmerica), " North-America ".parse());
+        assert_eq!(Ok(Continent::Oceania), "Australia".parse());
+        assert_eq!(Ok(Continent::Europe), "EU".parse());
+        assert!("Atlantis".parse::<Continent>().is_err());
+        assert_eq!("South America", Continent::SouthAmerica.to_string());
+
+        assert_eq!(Some("CR"), country_code("Costa Rica"));
+        assert_eq!(Some("IE"), country_code("ie"));
+        assert_eq!(Some("DE"), country_code("DEU"));
+        assert_eq!(None, country_code("Narnia"));
+        assert_eq!(Some("Japan"), country_name("jp"));
+    }
+}
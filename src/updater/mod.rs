@@ -0,0 +1,257 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+pub use crate::http::{HttpResponse, ReqwestTransport};
+use crate::http::HttpError;
+use crate::node::Node;
+use crate::topology::Topology;
+use crate::types::NetworkType;
+
+mod updater_tests;
+
+/// DEFAULT_BASE_URL is the address of the community topology-updater service
+pub const DEFAULT_BASE_URL: &str = "https://api.clio.one/htopology/v1";
+
+/// HttpTransport performs the HTTP requests of the updater client. It lets the client run against
+/// a local stand-in of the service. Every http::HttpTransport (e.g. ReqwestTransport) is one.
+pub trait HttpTransport {
+    /// get: performs a GET request on the url
+    fn get(&self, url: &str) -> Result<HttpResponse, UpdaterError>;
+}
+
+impl<T: crate::http::HttpTransport> HttpTransport for T {
+    fn get(&self, url: &str) -> Result<HttpResponse, UpdaterError> {
+        crate::http::HttpTransport::get(self, url).map_err(UpdaterError::from)
+    }
+}
+
+/// UpdaterError holds the errors returned by the topology-updater client
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UpdaterError {
+    /// Transport: the request could not be performed (DNS, connection, TLS, timeout...)
+    Transport(String),
+
+    /// Http: the service answered with an unexpected HTTP status
+    Http {
+        /// status is the HTTP status code
+        status: u16,
+    },
+
+    /// RateLimited: the service refused the request because of too many requests
+    RateLimited {
+        /// retry_after is the delay requested by the service, if given
+        retry_after: Option<Duration>,
+
+        /// msg is the message of the service
+        msg: String,
+    },
+
+    /// Service: the service answered with an error result code
+    Service {
+        /// code is the `resultcode` of the response
+        code: u16,
+
+        /// msg is the `msg` of the response
+        msg: String,
+    },
+
+    /// Decode: the response could not be decoded
+    Decode(String),
+}
+
+impl fmt::Display for UpdaterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdaterError::Transport(e) => write!(f, "topology updater transport error: {}", e),
+            UpdaterError::Http { status } => write!(f, "topology updater HTTP status: {}", status),
+            UpdaterError::RateLimited { retry_after, msg } => match retry_after {
+                Some(d) => write!(f, "topology updater rate limited (retry after {}s): {}", d.as_secs(), msg),
+                None => write!(f, "topology updater rate limited: {}", msg),
+            },
+            UpdaterError::Service { code, msg } => write!(f, "topology updater error {}: {}", code, msg),
+            UpdaterError::Decode(e) => write!(f, "topology updater response decode error: {}", e),
+        }
+    }
+}
+
+impl Error for UpdaterError {}
+
+impl From<HttpError> for UpdaterError {
+    fn from(e: HttpError) -> Self {
+        UpdaterError::Transport(e.0)
+    }
+}
+
+/// IpVersion selects the address family of the peers returned by fetch
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum IpVersion {
+    /// V4 returns IPv4 peers
+    #[default]
+    V4,
+
+    /// V6 returns IPv6 peers
+    V6,
+
+    /// Mix returns peers of both families
+    Mix,
+}
+
+impl IpVersion {
+    fn as_param(&self) -> &'static str {
+        match self {
+            IpVersion::V4 => "4",
+            IpVersion::V6 => "6",
+            IpVersion::Mix => "mix",
+        }
+    }
+}
+
+/// PushResponse holds the answer of the service to a push (relay registration)
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PushResponse {
+    /// resultcode is the result code of the service, 2xx for success
+    pub resultcode: String,
+
+    /// datetime is the time of the registration as reported by the service
+    #[serde(default)]
+    pub datetime: String,
+
+    /// client_ip is the address the service registered for the relay
+    #[serde(default, rename = "clientIp")]
+    pub client_ip: String,
+
+    /// iptype is the address family of client_ip (4 or 6)
+    #[serde(default)]
+    pub iptype: u8,
+
+    /// msg is the message of the service
+    #[serde(default)]
+    pub msg: String,
+}
+
+/// ServiceStatus is the part of every response of the service holding its result
+#[derive(Deserialize)]
+struct ServiceStatus {
+    resultcode: Option<String>,
+
+    #[serde(default)]
+    msg: String,
+}
+
+/// UpdaterClient registers a relay in the community topology-updater service (push) and fetches
+/// the list of peers it suggests (fetch).
+pub struct UpdaterClient<T: HttpTransport> {
+    transport: T,
+    base_url: String,
+    network_type: NetworkType,
+}
+
+impl UpdaterClient<ReqwestTransport> {
+    /// new_default: returns a client for the community service using the reqwest transport
+    pub fn new_default(network_type: NetworkType) -> Result<UpdaterClient<ReqwestTransport>, UpdaterError> {
+        let transport = ReqwestTransport::new(Duration::from_secs(30))?;
+        Ok(UpdaterClient::new(transport, DEFAULT_BASE_URL, network_type))
+    }
+}
+
+impl<T: HttpTransport> UpdaterClient<T> {
+    /// new: returns a client for the service at base_url
+    /// # Arguments:
+    /// - **transport**: the transport performing the HTTP requests
+    /// - **base_url**: the service address, e.g. DEFAULT_BASE_URL
+    /// - **network_type**: the network of the relay, its magic is sent with every request
+    pub fn new(transport: T, base_url: &str, network_type: NetworkType) -> UpdaterClient<T> {
+        UpdaterClient {
+            transport,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            network_type,
+        }
+    }
+
+    /// transport: returns the transport used by the client
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// push: registers the relay in the service. It must be called once per hour for the relay
+    /// to stay in the peer lists handed out by the service.
+    /// # Arguments:
+    /// - **port**: the public TCP port of the relay
+    /// - **block_no**: the current block number of the relay, the service uses it to check the
+    ///   relay is in sync
+    /// - **valency**: the valency announced for the relay
+    /// - **hostname**: the public DNS name of the relay, None to register its IP address
+    pub fn push(&self, port: u16, block_no: u64, valency: u16, hostname: Option<&str>) -> Result<PushResponse, UpdaterError> {
+        let mut params = vec![
+            ("port", port.to_string()),
+            ("blockNo", block_no.to_string()),
+            ("valency", valency.to_string()),
+            ("magic", self.network_type.magic().to_string()),
+        ];
+        if let Some(hostname) = hostname {
+            params.push(("hostname", hostname.to_string()));
+        }
+
+        let body = self.request(&format!("{}/", self.base_url), &params)?;
+        serde_json::from_str(&body).map_err(|e| UpdaterError::Decode(e.to_string()))
+    }
+
+    /// fetch: returns the peers suggested by the service, ready to be passed to
+    /// `ping::ping_vec`.
+    /// # Arguments:
+    /// - **max**: the maximum number of peers to return
+    /// - **ip_version**: the address family of the peers
+    pub fn fetch(&self, max: usize, ip_version: IpVersion) -> Result<Vec<Node>, UpdaterError> {
+        let params = vec![
+            ("max", max.to_string()),
+            ("magic", self.network_type.magic().to_string()),
+            ("ipv", ip_version.as_param().to_string()),
+        ];
+
+        let body = self.request(&format!("{}/fetch/", self.base_url), &params)?;
+        let topology = Topology::new_from_json(self.network_type, body)
+            .map_err(|e| UpdaterError::Decode(e.to_string()))?;
+        Ok(topology.into_nodes())
+    }
+
+    fn request(&self, url: &str, params: &[(&str, String)]) -> Result<String, UpdaterError> {
+        let url = Url::parse_with_params(url, params).map_err(|e| UpdaterError::Transport(e.to_string()))?;
+        debug!("topology updater request: {}", url);
+
+        let response = self.transport.get(url.as_str())?;
+
+        if response.status == 429 {
+            return Err(UpdaterError::RateLimited {
+                retry_after: response.retry_after,
+                msg: response.body,
+            });
+        }
+        if !(200..300).contains(&response.status) {
+            return Err(UpdaterError::Http { status: response.status });
+        }
+
+        let status: ServiceStatus =
+            serde_json::from_str(&response.body).map_err(|e| UpdaterError::Decode(e.to_string()))?;
+        if let Some(code) = status.resultcode {
+            let code: u16 = code
+                .trim()
+                .parse()
+                .map_err(|_| UpdaterError::Decode(format!("invalid resultcode: '{}'", code)))?;
+            if code == 429 {
+                return Err(UpdaterError::RateLimited {
+                    retry_after: response.retry_after,
+                    msg: status.msg,
+                });
+            }
+            if code >= 300 {
+                return Err(UpdaterError::Service { code, msg: status.msg });
+            }
+        }
+
+        Ok(response.body)
+    }
+}
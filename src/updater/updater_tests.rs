@@ -0,0 +1,186 @@
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+
+    use crate::types::NetworkType;
+    use crate::updater::{HttpResponse, HttpTransport, IpVersion, ReqwestTransport, UpdaterClient, UpdaterError};
+
+    const FETCH_RESPONSE: &str = r#"{
+      "resultcode": "201",
+      "networkMagic": "764824073",
+      "ipType": 4,
+      "Producers": [
+        { "addr": "54.220.20.40", "port": 3002, "valency": 1, "distance": 217, "continent": "Europe", "state": "IE" },
+        { "addr": "costa-rica.adakailabs.com", "port": 5000, "valency": 1, "distance": 3521 }
+      ]
+    }"#;
+
+    const PUSH_RESPONSE: &str = r#"{
+      "resultcode": "204",
+      "datetime": "2021-11-01 10:00:00",
+      "clientIp": "190.10.10.10",
+      "iptype": 4,
+      "msg": "glad you're staying with us"
+    }"#;
+
+    /// serve starts a stand-in of the service answering each request with the next of the given
+    /// (status, headers, body) responses. The handle returns the received request lines.
+    fn serve(responses: Vec<(u16, &'static str, &'static str)>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/htopology/v1", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for (status, headers, body) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                }
+                requests.push(request_line.trim().to_string());
+
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}",
+                    status,
+                    body.len(),
+                    headers,
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
+
+        (base_url, handle)
+    }
+
+    fn client(base_url: &str) -> UpdaterClient<ReqwestTransport> {
+        let transport = ReqwestTransport::new(Duration::from_secs(5)).unwrap();
+        UpdaterClient::new(transport, base_url, NetworkType::Mainnet)
+    }
+
+    #[test]
+    fn fetch_peers() {
+        let (base_url, server) = serve(vec![(200, "", FETCH_RESPONSE)]);
+        let nodes = client(&base_url).fetch(14, IpVersion::V4).unwrap();
+
+        assert_eq!(2, nodes.len());
+        assert_eq!("54.220.20.40", nodes[0].addr());
+        assert_eq!(3002, nodes[0].port());
+        assert_eq!("Europe", nodes[0].continent());
+        assert_eq!(5000, nodes[1].port());
+
+        let requests = server.join().unwrap();
+        assert_eq!("GET /htopology/v1/fetch/?max=14&magic=764824073&ipv=4 HTTP/1.1", requests[0]);
+    }
+
+    #[test]
+    fn push_relay() {
+        let (base_url, server) = serve(vec![(200, "", PUSH_RESPONSE)]);
+        let response = client(&base_url)
+            .push(5000, 6543210, 1, Some("costa-rica.adakailabs.com"))
+            .unwrap();
+
+        assert_eq!("204", response.resultcode);
+        assert_eq!("190.10.10.10", response.client_ip);
+        assert_eq!(4, response.iptype);
+
+        let requests = server.join().unwrap();
+        assert_eq!(
+            "GET /htopology/v1/?port=5000&blockNo=6543210&valency=1&magic=764824073&hostname=costa-rica.adakailabs.com HTTP/1.1",
+            requests[0]
+        );
+    }
+
+    #[test]
+    fn rate_limited() {
+        let (base_url, server) = serve(vec![
+            (429, "Retry-After: 3600\r\n", "too many requests"),
+            (200, "", r#"{"resultcode": "429", "msg": "one request per hour please"}"#),
+        ]);
+        let client = client(&base_url);
+
+        assert_eq!(
+            UpdaterError::RateLimited {
+                retry_after: Some(Duration::from_secs(3600)),
+                msg: "too many requests".to_string()
+            },
+            client.push(5000, 1, 1, None).unwrap_err()
+        );
+        assert_eq!(
+            UpdaterError::RateLimited {
+                retry_after: None,
+                msg: "one request per hour please".to_string()
+            },
+            client.push(5000, 1, 1, None).unwrap_err()
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn service_and_http_errors() {
+        let (base_url, server) = serve(vec![
+            (200, "", r#"{"resultcode": "402", "msg": "blockNo is not current"}"#),
+            (500, "", "internal error"),
+            (200, "", "<html></html>"),
+        ]);
+        let client = client(&base_url);
+
+        assert_eq!(
+            UpdaterError::Service { code: 402, msg: "blockNo is not current".to_string() },
+            client.push(5000, 1, 1, None).unwrap_err()
+        );
+        assert_eq!(UpdaterError::Http { status: 500 }, client.fetch(14, IpVersion::Mix).unwrap_err());
+        assert!(matches!(client.fetch(14, IpVersion::V6).unwrap_err(), UpdaterError::Decode(_)));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn transport_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/htopology/v1", listener.local_addr().unwrap());
+        drop(listener);
+
+        assert!(matches!(client(&base_url).fetch(14, IpVersion::V4).unwrap_err(), UpdaterError::Transport(_)));
+    }
+
+    struct FakeTransport {
+        urls: RefCell<Vec<String>>,
+    }
+
+    impl HttpTransport for FakeTransport {
+        fn get(&self, url: &str) -> Result<HttpResponse, UpdaterError> {
+            self.urls.borrow_mut().push(url.to_string());
+            Ok(HttpResponse { status: 200, retry_after: None, body: FETCH_RESPONSE.to_string() })
+        }
+    }
+
+    #[test]
+    fn custom_transport() {
+        let transport = FakeTransport { urls: RefCell::new(Vec::new()) };
+        let client = UpdaterClient::new(transport, "https://example.org/htopology/v1/", NetworkType::TestNet);
+
+        let nodes = client.fetch(2, IpVersion::V4).unwrap();
+        assert_eq!(2, nodes.len());
+        assert_eq!(
+            vec!["https://example.org/htopology/v1/fetch/?max=2&magic=1097911063&ipv=4".to_string()],
+            *client_urls(&client)
+        );
+    }
+
+    fn client_urls(client: &UpdaterClient<FakeTransport>) -> std::cell::Ref<'_, Vec<String>> {
+        client.transport().urls.borrow()
+    }
+}